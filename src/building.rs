@@ -1,14 +1,105 @@
 use bevy::{platform::collections::HashSet, prelude::*};
+use serde::{Deserialize, Serialize};
 
-use crate::geometry_utils::{point_closest_to_segment, segments_cross, signed_polygon_area_2d};
+use crate::geometry_utils::{
+    point_closest_to_segment, point_in_polygon, polygon_centroid, segments_cross,
+    signed_polygon_area_2d,
+};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Building {
     /// The y position of the base of the building.
     pub floor_y: i32,
 
     /// The points making up the building.
+    #[serde(with = "outline_serde")]
     pub outline: Vec<IVec2>,
+
+    /// The outlines of courtyards cut out of the building, each wound clockwise.
+    #[serde(default, with = "holes_serde", skip_serializing_if = "Vec::is_empty")]
+    pub holes: Vec<Vec<IVec2>>,
+
+    /// The thickness of the building's walls, in grid units.
+    #[serde(
+        default = "default_wall_thickness",
+        skip_serializing_if = "is_default_wall_thickness"
+    )]
+    pub wall_thickness: f32,
+
+    /// The height of the building's rooms, in grid units.
+    #[serde(default = "default_height", skip_serializing_if = "is_default_height")]
+    pub height: i32,
+}
+
+// Buildings are used as cache keys. The wall thickness of a valid building is never NaN.
+impl Eq for Building {}
+
+impl std::hash::Hash for Building {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.floor_y.hash(state);
+        self.outline.hash(state);
+        self.holes.hash(state);
+        self.wall_thickness.to_bits().hash(state);
+        self.height.hash(state);
+    }
+}
+
+fn default_wall_thickness() -> f32 {
+    BUILDING_WALL_THICKNESS
+}
+
+fn is_default_wall_thickness(wall_thickness: &f32) -> bool {
+    *wall_thickness == BUILDING_WALL_THICKNESS
+}
+
+fn default_height() -> i32 {
+    BUILDING_HEIGHT
+}
+
+fn is_default_height(height: &i32) -> bool {
+    *height == BUILDING_HEIGHT
+}
+
+/// Stores the points of an outline as `[x, z]` pairs.
+mod outline_serde {
+    use bevy::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(outline: &[IVec2], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(outline.iter().map(|p| p.to_array()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<IVec2>, D::Error> {
+        let points: Vec<[i32; 2]> = Vec::deserialize(deserializer)?;
+        Ok(points.into_iter().map(IVec2::from_array).collect())
+    }
+}
+
+/// Stores the points of each hole as `[x, z]` pairs.
+mod holes_serde {
+    use bevy::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        holes: &[Vec<IVec2>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            holes
+                .iter()
+                .map(|hole| hole.iter().map(|p| p.to_array()).collect::<Vec<_>>()),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<IVec2>>, D::Error> {
+        let holes: Vec<Vec<[i32; 2]>> = Vec::deserialize(deserializer)?;
+        Ok(holes
+            .into_iter()
+            .map(|hole| hole.into_iter().map(IVec2::from_array).collect())
+            .collect())
+    }
 }
 
 #[derive(Default)]
@@ -19,9 +110,20 @@ pub struct BuildingValidity {
     allow_two_points: bool,
 }
 
+/// The default thickness of a building's walls, in grid units.
 pub const BUILDING_WALL_THICKNESS: f32 = 0.125;
+/// The offset of a room's interior surface from its outline, in grid units, for a building with
+/// the default wall thickness.
+pub const INTERIOR_WALL_WIDTH: f32 = -0.1;
+/// The thickness of a room's floor and ceiling, in grid units.
+pub const SHELL_SLAB_THICKNESS: f32 = 0.1;
+/// The default height of a building, in grid units.
+pub const BUILDING_HEIGHT: i32 = 2;
 pub const MIN_EXTENDED: f32 = 0.45;
 pub const MIN_INTERIOR_THICKNESS: f32 = 0.5;
+/// Imported points closer than this (in grid units) are merged, which covers points rounded into
+/// the same or diagonally adjacent grid cells.
+pub const IMPORT_VERTEX_MERGE_DISTANCE: f32 = 1.5;
 
 #[derive(Copy, Clone, Debug)]
 pub struct Corner {
@@ -30,9 +132,9 @@ pub struct Corner {
     pub b: IVec2,
 }
 
-/// If a corner is too sharp, then it will lead to degenerate solids.
-/// Do not allow this to happen.
-pub fn is_corner_too_sharp(corner: Corner) -> bool {
+/// If a corner is too sharp for walls of the given thickness, then it will lead to degenerate
+/// solids. Do not allow this to happen.
+pub fn is_corner_too_sharp(corner: Corner, wall_thickness: f32) -> bool {
     let a = corner.a;
     let b = corner.b;
     let pivot = corner.pivot;
@@ -44,7 +146,7 @@ pub fn is_corner_too_sharp(corner: Corner) -> bool {
         .dot((b - pivot).as_vec2().normalize())
         .acos();
 
-    let max_movement = BUILDING_WALL_THICKNESS / (angle / 2.0).sin();
+    let max_movement = wall_thickness / (angle / 2.0).sin();
 
     max_movement >= MIN_EXTENDED
 }
@@ -59,13 +161,83 @@ impl Building {
             outline.iter().copied().collect::<HashSet<_>>().len() == outline.len(),
             "floor outline must have no duplicate points"
         );
-        Self { floor_y, outline }
+        Self {
+            floor_y,
+            outline,
+            holes: Vec::new(),
+            wall_thickness: BUILDING_WALL_THICKNESS,
+            height: BUILDING_HEIGHT,
+        }
+    }
+
+    /// A square building on the ground with its minimum corner at `min`, for tests.
+    #[cfg(test)]
+    pub(crate) fn square(min: IVec2, size: i32) -> Self {
+        Self::new(
+            0,
+            vec![
+                min,
+                min + IVec2::new(size, 0),
+                min + IVec2::new(size, size),
+                min + IVec2::new(0, size),
+            ],
+        )
+    }
+
+    /// Creates a building from an imported outline, merging (nearly) coincident points first.
+    /// Returns `None` if the cleaned-up building is not valid.
+    pub fn try_new(floor_y: i32, outline: Vec<IVec2>) -> Option<Self> {
+        let mut building = Self {
+            floor_y,
+            outline,
+            holes: Vec::new(),
+            wall_thickness: BUILDING_WALL_THICKNESS,
+            height: BUILDING_HEIGHT,
+        };
+        building.dedupe_vertices(IMPORT_VERTEX_MERGE_DISTANCE);
+        if !building.is_valid(BuildingValidity::default()) {
+            return None;
+        }
+        Some(building)
+    }
+
+    /// Removes points that are closer than `min_spacing` to an earlier point in the outline,
+    /// keeping the earlier one. Returns the number of points removed.
+    ///
+    /// The order of the remaining points is preserved. This never removes points below 3; any
+    /// remaining duplicates are left for validation to reject.
+    pub fn dedupe_vertices(&mut self, min_spacing: f32) -> usize {
+        let original_len = self.outline.len();
+        let mut kept: Vec<IVec2> = Vec::with_capacity(original_len);
+        for (i, &p) in self.outline.iter().enumerate() {
+            let remaining = kept.len() + (original_len - i);
+            let is_near_kept = kept
+                .iter()
+                .any(|k| k.as_vec2().distance(p.as_vec2()) < min_spacing);
+            if is_near_kept && remaining > 3 {
+                continue;
+            }
+            kept.push(p);
+        }
+        self.outline = kept;
+        original_len - self.outline.len()
     }
 
     pub fn floor_y(&self) -> i32 {
         self.floor_y
     }
 
+    /// Returns the offset of the building's interior surface from its outline, in grid units.
+    /// This is `INTERIOR_WALL_WIDTH`, scaled by the building's wall thickness.
+    pub fn interior_offset(&self) -> f32 {
+        INTERIOR_WALL_WIDTH * self.wall_thickness / BUILDING_WALL_THICKNESS
+    }
+
+    /// Returns the y position of the ceiling of the building.
+    pub fn ceiling_y(&self) -> i32 {
+        self.floor_y + self.height
+    }
+
     /// Returns the points making up the building.
     pub fn points(&self) -> &[IVec2] {
         &self.outline
@@ -75,8 +247,181 @@ impl Building {
         &mut self.outline
     }
 
+    /// Moves the outline and holes of the building by `delta`.
+    pub fn translate(&mut self, delta: IVec2) {
+        for p in self
+            .outline
+            .iter_mut()
+            .chain(self.holes.iter_mut().flatten())
+        {
+            // TODO: Check for overflow
+            *p += delta;
+        }
+    }
+
+    /// Rotates the building a quarter turn clockwise (as seen from above) around its centroid,
+    /// rounded to the nearest grid point so that every point stays on the grid.
+    /// Returns `false` and leaves the building unchanged if the result would be invalid.
+    pub fn rotate_90_cw(&mut self) -> bool {
+        let center = polygon_centroid(&self.outline).round().as_ivec2();
+        let mut new_building = self.clone();
+        for p in new_building
+            .outline
+            .iter_mut()
+            .chain(new_building.holes.iter_mut().flatten())
+        {
+            let offset = *p - center;
+            *p = center + IVec2::new(-offset.y, offset.x);
+        }
+        if !new_building.is_valid(BuildingValidity::default()) {
+            return false;
+        }
+        *self = new_building;
+        true
+    }
+
+    /// Returns the outlines of the courtyards cut out of the building.
+    pub fn holes(&self) -> &[Vec<IVec2>] {
+        &self.holes
+    }
+
+    /// Adds a courtyard to the building, which must be wound clockwise.
+    /// Returns `false` and leaves the building unchanged if the result would be invalid.
+    pub fn add_hole(&mut self, hole: Vec<IVec2>) -> bool {
+        let mut new_building = self.clone();
+        new_building.holes.push(hole);
+        if !new_building.is_valid(BuildingValidity::default()) {
+            return false;
+        }
+        *self = new_building;
+        true
+    }
+
+    /// Inserts a point into the outline, between the points at `edge_index` and `edge_index + 1`.
+    /// Returns `false` and leaves the building unchanged if the result would be invalid.
+    pub fn insert_point(&mut self, edge_index: usize, p: IVec2) -> bool {
+        let mut new_building = self.clone();
+        new_building.outline.insert(edge_index + 1, p);
+        if !new_building.is_valid(BuildingValidity::default()) {
+            return false;
+        }
+        *self = new_building;
+        true
+    }
+
+    /// Returns the area enclosed by the outline, less any holes, in square grid units.
+    pub fn area(&self) -> f32 {
+        let hole_area: f32 = self
+            .holes
+            .iter()
+            .map(|hole| signed_polygon_area_2d(hole).abs())
+            .sum();
+        self.signed_area().abs() - hole_area
+    }
+
+    /// Returns the signed area enclosed by the outline, which is positive when it is wound
+    /// counterclockwise.
+    pub fn signed_area(&self) -> f32 {
+        signed_polygon_area_2d(&self.outline)
+    }
+
+    /// Returns whether the (flat) grid-space point lies inside the outline, and not in any hole.
+    pub fn contains(&self, p: Vec2) -> bool {
+        point_in_polygon(p, &self.outline)
+            && !self.holes.iter().any(|hole| point_in_polygon(p, hole))
+    }
+
+    /// Returns the axis-aligned bounding box of the outline, as `(min, max)`.
+    pub fn bounds(&self) -> (IVec2, IVec2) {
+        let mut min = self.outline[0];
+        let mut max = self.outline[0];
+        for &p in self.outline.iter() {
+            min = min.min(p);
+            max = max.max(p);
+        }
+        (min, max)
+    }
+
+    /// Returns whether the building would still be valid after moving the point at `index` to
+    /// `new_p`.
+    ///
+    /// This assumes the building is currently valid, and only re-checks the corners and edges that
+    /// touch the moved point, so it is much cheaper than `is_valid` for buildings with many points.
+    pub fn is_valid_after_point_change(&self, index: usize, new_p: IVec2) -> bool {
+        let len = self.outline.len();
+        if len < 3 || !self.holes.is_empty() {
+            let mut new_building = self.clone();
+            new_building.outline[index] = new_p;
+            return new_building.is_valid(BuildingValidity::default());
+        }
+
+        let point = |i: usize| {
+            let i = i % len;
+            if i == index { new_p } else { self.outline[i] }
+        };
+        let prev = index + len - 1;
+        let next = index + 1;
+
+        // The corners at the moved point and at its two neighbors.
+        for pivot_index in [prev, index, next] {
+            let corner = Corner {
+                a: point(pivot_index + len - 1),
+                pivot: point(pivot_index),
+                b: point(pivot_index + 1),
+            };
+            if is_corner_too_sharp(corner, self.wall_thickness) {
+                return false;
+            }
+        }
+
+        if (0..len).any(|i| i != index && self.outline[i] == new_p) {
+            return false;
+        }
+
+        let changed_edges = [(point(prev), new_p), (new_p, point(next))];
+        let is_too_close = |p: IVec2, (a, b): (IVec2, IVec2)| {
+            if a == p || b == p {
+                return false;
+            }
+            let p_on_line = point_closest_to_segment(p.as_vec2(), (a.as_vec2(), b.as_vec2()));
+            p_on_line.distance(p.as_vec2()) < MIN_INTERIOR_THICKNESS
+        };
+
+        for i in 0..len {
+            let edge = (point(i), point(i + 1));
+
+            // The moved point against every edge.
+            if is_too_close(new_p, edge) {
+                return false;
+            }
+            // Every point against the changed edges.
+            for &changed_edge in changed_edges.iter() {
+                if is_too_close(point(i), changed_edge) {
+                    return false;
+                }
+            }
+            // The changed edges against every edge.
+            for &(a1, b1) in changed_edges.iter() {
+                let (a2, b2) = edge;
+                if a1 == a2 || a1 == b2 || b1 == a2 || b1 == b2 {
+                    continue;
+                }
+                if segments_cross((a1.as_vec2(), b1.as_vec2()), (a2.as_vec2(), b2.as_vec2())) {
+                    return false;
+                }
+            }
+        }
+
+        let new_outline: Vec<IVec2> = (0..len).map(point).collect();
+        signed_polygon_area_2d(&new_outline) > 0.0
+    }
+
     /// Returns whether the arrangement of points in this building is valid.
     pub fn is_valid(&self, options: BuildingValidity) -> bool {
+        if !(self.wall_thickness.is_finite() && self.wall_thickness > 0.0) || self.height < 1 {
+            return false;
+        }
+
         let len = self.outline.len();
         if len == 1 && !options.allow_one_point {
             return false;
@@ -91,7 +436,7 @@ impl Building {
                 let pivot = self.outline[pivot_index];
                 let b = self.outline[(pivot_index + 1) % len];
 
-                if is_corner_too_sharp(Corner { a, pivot, b }) {
+                if is_corner_too_sharp(Corner { a, pivot, b }, self.wall_thickness) {
                     return false;
                 }
             }
@@ -147,6 +492,148 @@ impl Building {
             return false;
         }
 
+        self.holes_are_valid()
+    }
+
+    /// Returns whether every hole is a clockwise ring lying inside the outline, at least
+    /// `MIN_INTERIOR_THICKNESS` away from it and from the other holes.
+    fn holes_are_valid(&self) -> bool {
+        // Whether any point of ring `a` is too close to an edge of ring `b`, or their edges cross.
+        let rings_touch = |a: &[IVec2], b: &[IVec2]| {
+            for &p in a.iter() {
+                for i in 0..b.len() {
+                    let edge = (b[i].as_vec2(), b[(i + 1) % b.len()].as_vec2());
+                    let p_on_line = point_closest_to_segment(p.as_vec2(), edge);
+                    if p_on_line.distance(p.as_vec2()) < MIN_INTERIOR_THICKNESS {
+                        return true;
+                    }
+                }
+            }
+            for i in 0..a.len() {
+                let edge_a = (a[i].as_vec2(), a[(i + 1) % a.len()].as_vec2());
+                for j in 0..b.len() {
+                    let edge_b = (b[j].as_vec2(), b[(j + 1) % b.len()].as_vec2());
+                    if segments_cross(edge_a, edge_b) {
+                        return true;
+                    }
+                }
+            }
+            false
+        };
+
+        for (hole_index, hole) in self.holes.iter().enumerate() {
+            // A hole is checked like an outline, but must be wound the other way.
+            let mut reversed = hole.clone();
+            reversed.reverse();
+            let ring = Building {
+                floor_y: self.floor_y,
+                outline: reversed,
+                holes: Vec::new(),
+                wall_thickness: self.wall_thickness,
+                height: self.height,
+            };
+            if !ring.is_valid(BuildingValidity::default()) {
+                return false;
+            }
+
+            if !hole
+                .iter()
+                .all(|p| point_in_polygon(p.as_vec2(), &self.outline))
+            {
+                return false;
+            }
+            if rings_touch(hole, &self.outline) || rings_touch(&self.outline, hole) {
+                return false;
+            }
+
+            for other in self.holes[..hole_index].iter() {
+                if rings_touch(hole, other)
+                    || rings_touch(other, hole)
+                    || point_in_polygon(hole[0].as_vec2(), other)
+                    || point_in_polygon(other[0].as_vec2(), hole)
+                {
+                    return false;
+                }
+            }
+        }
         true
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dedupe_vertices_removes_exact_and_near_duplicates() {
+        let mut building = Building {
+            floor_y: 0,
+            outline: vec![
+                IVec2::new(0, 0),
+                IVec2::new(0, 0),
+                IVec2::new(8, 0),
+                IVec2::new(8, 1),
+                IVec2::new(8, 8),
+                IVec2::new(0, 8),
+            ],
+            holes: Vec::new(),
+            wall_thickness: BUILDING_WALL_THICKNESS,
+            height: BUILDING_HEIGHT,
+        };
+
+        assert_eq!(building.dedupe_vertices(1.5), 2);
+        assert_eq!(
+            building.points(),
+            [
+                IVec2::new(0, 0),
+                IVec2::new(8, 0),
+                IVec2::new(8, 8),
+                IVec2::new(0, 8),
+            ]
+        );
+        assert!(building.is_valid(BuildingValidity::default()));
+    }
+
+    #[test]
+    fn four_quarter_turns_restore_the_outline() {
+        let original = Building::new(
+            0,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(6, 0),
+                IVec2::new(6, 2),
+                IVec2::new(0, 2),
+            ],
+        );
+
+        let mut building = original.clone();
+        for turn in 1..=4 {
+            assert!(building.rotate_90_cw());
+            if turn < 4 {
+                assert_ne!(building.points(), original.points());
+            }
+        }
+        assert_eq!(building, original);
+    }
+
+    #[test]
+    fn thick_walls_reject_a_corner_thin_walls_accept() {
+        // A right triangle, with two 45 degree corners.
+        let corner = Corner {
+            a: IVec2::new(4, 4),
+            pivot: IVec2::new(0, 0),
+            b: IVec2::new(4, 0),
+        };
+        assert!(!is_corner_too_sharp(corner, BUILDING_WALL_THICKNESS));
+        assert!(is_corner_too_sharp(corner, 0.3));
+
+        let thin = Building::new(
+            0,
+            vec![IVec2::new(0, 0), IVec2::new(4, 0), IVec2::new(4, 4)],
+        );
+        let mut thick = thin.clone();
+        thick.wall_thickness = 0.3;
+        assert!(thin.is_valid(BuildingValidity::default()));
+        assert!(!thick.is_valid(BuildingValidity::default()));
+    }
+}
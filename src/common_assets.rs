@@ -1,4 +1,11 @@
-use bevy::{prelude::*, render::mesh::PlaneMeshBuilder};
+use bevy::{
+    image::{ImageAddressMode, ImageLoaderSettings, ImageSampler, ImageSamplerDescriptor},
+    pbr::{NotShadowCaster, NotShadowReceiver},
+    prelude::*,
+    render::mesh::{PlaneMeshBuilder, VertexAttributeValues},
+};
+
+use crate::flycam::CameraControls;
 
 #[allow(unused)]
 #[derive(Resource)]
@@ -20,7 +27,11 @@ pub struct CommonPlugin;
 
 impl Plugin for CommonPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup_common);
+        app.add_systems(Startup, (setup_common, spawn_sky_dome).chain())
+            .add_systems(
+                PostUpdate,
+                follow_camera_sky_dome_system.before(TransformSystem::TransformPropagate),
+            );
     }
 }
 
@@ -30,7 +41,15 @@ pub fn setup_common(
     mut meshes: ResMut<Assets<Mesh>>,
     asset_server: Res<AssetServer>,
 ) {
-    let grid_texture: Handle<Image> = asset_server.load("grid.png");
+    // The grid repeats, so that it can be tiled across the ground plane.
+    let grid_texture: Handle<Image> =
+        asset_server.load_with_settings("grid.png", |settings: &mut ImageLoaderSettings| {
+            settings.sampler = ImageSampler::Descriptor(ImageSamplerDescriptor {
+                address_mode_u: ImageAddressMode::Repeat,
+                address_mode_v: ImageAddressMode::Repeat,
+                ..ImageSamplerDescriptor::linear()
+            });
+        });
     let common = Common {
         cube_mesh: meshes.add(Cuboid::new(1., 1., 1.).mesh()),
         plane_mesh: meshes.add(PlaneMeshBuilder::default().normal(Dir3::Z).build()),
@@ -71,7 +90,7 @@ pub fn setup_common(
             ..default()
         }),
         sky_material: materials.add(StandardMaterial {
-            base_color_texture: Some(asset_server.load("grid.png")),
+            base_color_texture: Some(grid_texture.clone()),
             base_color: Color::linear_rgb(0.3, 0.7, 0.9),
             perceptual_roughness: 1.0,
             emissive: LinearRgba::new(0.1, 0.2, 0.3, 1.0),
@@ -82,3 +101,46 @@ pub fn setup_common(
     };
     commands.insert_resource(common);
 }
+
+/// The radius of the sky dome, far enough to be behind the whole level, but within the cameras'
+/// far plane so that it is still drawn.
+const SKY_DOME_RADIUS: f32 = crate::CAMERA_FAR / 2.;
+
+/// A large sphere around the camera, drawn with `Common::sky_material` as the editor's backdrop.
+#[derive(Component)]
+struct SkyDome;
+
+fn spawn_sky_dome(mut commands: Commands, common: Res<Common>, mut meshes: ResMut<Assets<Mesh>>) {
+    // The dome is seen from inside, so its faces and normals must point inward.
+    let mut mesh = Sphere::new(SKY_DOME_RADIUS).mesh().uv(32, 18);
+    mesh.invert_winding()
+        .expect("sphere meshes are triangle lists");
+    if let Some(VertexAttributeValues::Float32x3(normals)) =
+        mesh.attribute_mut(Mesh::ATTRIBUTE_NORMAL)
+    {
+        for normal in normals.iter_mut() {
+            *normal = normal.map(|n| -n);
+        }
+    }
+
+    commands.spawn((
+        SkyDome,
+        Mesh3d(meshes.add(mesh)),
+        MeshMaterial3d(common.sky_material.clone()),
+        Transform::default(),
+        NotShadowCaster,
+        NotShadowReceiver,
+        Pickable::IGNORE,
+    ));
+}
+
+/// Keeps the sky dome centered on the camera, so that the horizon doesn't move as it flies around.
+fn follow_camera_sky_dome_system(
+    camera: Query<&Transform, (With<CameraControls>, Without<SkyDome>)>,
+    mut sky_dome: Query<&mut Transform, With<SkyDome>>,
+) {
+    let (Ok(camera), Ok(mut sky_dome)) = (camera.single(), sky_dome.single_mut()) else {
+        return;
+    };
+    sky_dome.translation = camera.translation;
+}
@@ -0,0 +1,84 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::prelude::*;
+
+pub struct CsgProgressPlugin;
+
+impl Plugin for CsgProgressPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CsgBuildProgress>()
+            .add_systems(Startup, setup_csg_progress)
+            .add_systems(Update, csg_progress_system);
+    }
+}
+
+/// How far the world CSG build has got, shared with the code doing the build so that it can be
+/// updated from its progress callback.
+#[derive(Resource, Clone, Default)]
+pub struct CsgBuildProgress(Arc<Mutex<Option<f32>>>);
+
+impl CsgBuildProgress {
+    /// Returns the fraction of the current build completed, or `None` if no build is running.
+    pub fn get(&self) -> Option<f32> {
+        *self.0.lock().unwrap()
+    }
+
+    /// Records the fraction of the current build completed, or `None` once it has finished.
+    pub fn set(&self, fraction: Option<f32>) {
+        *self.0.lock().unwrap() = fraction;
+    }
+}
+
+#[derive(Component)]
+struct CsgProgressBar;
+
+#[derive(Component)]
+struct CsgProgressFill;
+
+/// The width of the progress bar, in pixels.
+const PROGRESS_BAR_WIDTH: f32 = 200.;
+
+fn setup_csg_progress(mut commands: Commands) {
+    commands.spawn((
+        CsgProgressBar,
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.),
+            bottom: Val::Px(8.),
+            width: Val::Px(PROGRESS_BAR_WIDTH),
+            height: Val::Px(8.),
+            ..default()
+        },
+        BackgroundColor(Color::linear_rgba(0., 0., 0., 0.7)),
+        Visibility::Hidden,
+        Pickable::IGNORE,
+        children![(
+            CsgProgressFill,
+            Node {
+                width: Val::Percent(0.),
+                height: Val::Percent(100.),
+                ..default()
+            },
+            BackgroundColor(Color::linear_rgb(0.3, 1., 0.6)),
+            Pickable::IGNORE,
+        )],
+    ));
+}
+
+/// Shows a progress bar in a corner of the screen while the world CSG is being built.
+fn csg_progress_system(
+    progress: Res<CsgBuildProgress>,
+    mut bar: Query<&mut Visibility, With<CsgProgressBar>>,
+    mut fill: Query<&mut Node, With<CsgProgressFill>>,
+) {
+    let (Ok(mut visibility), Ok(mut fill)) = (bar.single_mut(), fill.single_mut()) else {
+        return;
+    };
+    match progress.get() {
+        Some(fraction) => {
+            *visibility = Visibility::Inherited;
+            fill.width = Val::Percent(100. * fraction.clamp(0., 1.));
+        }
+        None => *visibility = Visibility::Hidden,
+    }
+}
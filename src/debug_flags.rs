@@ -0,0 +1,64 @@
+use bevy::prelude::*;
+
+use crate::keybindings::Keybindings;
+
+/// Toggles for debugging visualizations.
+#[derive(Resource, Default)]
+pub struct DebugFlags {
+    /// Draw the inset interior outline of each building.
+    pub show_interior_outlines: bool,
+    /// Check whether any room interior is open to the outside, and mark where.
+    pub show_leaks: bool,
+    /// Check whether the world geometry is manifold, and mark the defective edges.
+    pub show_manifold_defects: bool,
+    /// Show how many entities each previewer is tracking.
+    pub show_preview_stats: bool,
+    /// Which shells of the world geometry are rendered as solid meshes.
+    pub shells: CsgShells,
+}
+
+/// Selects the shells of the world geometry to render, by which side of the walls they face.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CsgShells {
+    /// Faces which look into rooms.
+    pub inside: bool,
+    /// Faces which look out of buildings, drawn in the x-ray layer.
+    pub outside: bool,
+}
+
+impl Default for CsgShells {
+    fn default() -> Self {
+        Self {
+            inside: true,
+            outside: true,
+        }
+    }
+}
+
+pub struct DebugFlagsPlugin;
+
+impl Plugin for DebugFlagsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugFlags>()
+            .add_systems(Update, toggle_debug_flags_system);
+    }
+}
+
+fn toggle_debug_flags_system(
+    mut flags: ResMut<DebugFlags>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_interior_outlines) {
+        flags.show_interior_outlines = !flags.show_interior_outlines;
+    }
+    if keys.just_pressed(bindings.toggle_leaks) {
+        flags.show_leaks = !flags.show_leaks;
+    }
+    if keys.just_pressed(bindings.toggle_manifold_defects) {
+        flags.show_manifold_defects = !flags.show_manifold_defects;
+    }
+    if keys.just_pressed(bindings.toggle_preview_stats) {
+        flags.show_preview_stats = !flags.show_preview_stats;
+    }
+}
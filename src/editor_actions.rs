@@ -1,296 +1,1269 @@
 use bevy::ecs::system::SystemParam;
+use bevy::input::mouse::MouseMotion;
 use bevy::prelude::*;
 use bevy::render::view::RenderLayers;
+use bevy::window::PrimaryWindow;
 
 use crate::building::{
-    Building, BuildingValidity, Corner, MIN_INTERIOR_THICKNESS, is_corner_too_sharp,
+    BUILDING_WALL_THICKNESS, Building, Corner, MIN_INTERIOR_THICKNESS, is_corner_too_sharp,
 };
 use crate::common_assets::Common;
 use crate::editor_state::{
-    EditorTool, EditorWorld, from_flat, grid_to_world, to_flat, world_to_grid,
+    EditorTool, EditorWorld, Selection, VERTEX_LIMITS, from_flat, grid_to_world, to_flat,
+    world_to_grid,
 };
-use crate::geometry_utils::{point_closest_to_segment, segments_cross, signed_polygon_area_2d};
-use crate::preview::Previewer;
-use crate::voxels::VOXEL_SIZE;
+use crate::geometry_utils::{
+    point_closest_to_segment, polygon_centroid, ray_triangle_intersection, segment_intersection,
+    segments_cross, signed_polygon_area_2d,
+};
+use crate::keybindings::Keybindings;
+use crate::labels::WorldLabels;
+use crate::preview::{PreviewStats, Previewer};
+use crate::reference::ReferenceGeometry;
+use crate::voxels::{CommittedEditorState, SelectedWorldFace, SymmetryKind, VOXEL_SIZE, Voxels};
+use crate::{CSG, RenderedCsg};
 
 pub struct EditorActionPlugin;
 
 impl Plugin for EditorActionPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (
-                switch_tool_system,
-                move_building_system,
-                editor_insert_building_system,
-                preview_xray_buildings_system,
-            )
-                .chain(),
-        );
+        app.init_resource::<Selection>()
+            .init_resource::<HoveredBuilding>()
+            .init_resource::<SelectedTriangle>()
+            .add_systems(
+                Update,
+                (
+                    switch_tool_system,
+                    snap_step_system,
+                    vertex_limit_system,
+                    symmetry_system,
+                    building_undo_system,
+                    clipboard_system,
+                    hover_building_system,
+                    draw_building_measurements_system,
+                    move_building_system,
+                    delete_selected_system,
+                    delete_building_system,
+                    editor_insert_building_system,
+                    sightline_system,
+                    select_face_system,
+                    preview_xray_buildings_system,
+                )
+                    .chain(),
+            );
     }
 }
 
-pub fn switch_tool_system(mut editor_world: ResMut<EditorWorld>, keys: Res<ButtonInput<KeyCode>>) {
-    if keys.just_pressed(KeyCode::Digit1) {
+pub fn switch_tool_system(
+    mut editor_world: ResMut<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.tool_select) {
         editor_world.set_tool(EditorTool::SelectBuilding);
     }
-    if keys.just_pressed(KeyCode::Digit2) {
+    if keys.just_pressed(bindings.tool_create) {
         editor_world.set_tool(EditorTool::CreateBuilding);
     }
+    if keys.just_pressed(bindings.tool_delete) {
+        editor_world.set_tool(EditorTool::DeleteBuilding);
+    }
+    if keys.just_pressed(bindings.tool_sightline) {
+        editor_world.set_tool(EditorTool::Sightline);
+    }
+    if keys.just_pressed(bindings.tool_select_face) {
+        editor_world.set_tool(EditorTool::SelectFace);
+    }
+    if keys.just_pressed(bindings.tool_voxels) {
+        editor_world.set_tool(EditorTool::EditVoxels);
+    }
+}
+
+/// Halves or doubles the grid snapping step.
+fn snap_step_system(
+    mut editor_world: ResMut<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    let snap_step = editor_world.snap_step();
+    if keys.just_pressed(bindings.snap_finer) {
+        editor_world.set_snap_step(snap_step / 2);
+    }
+    if keys.just_pressed(bindings.snap_coarser) {
+        editor_world.set_snap_step(snap_step * 2);
+    }
+}
+
+/// Cycles through the limits on the number of points per building in `VERTEX_LIMITS`.
+fn vertex_limit_system(
+    mut editor_world: ResMut<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if !keys.just_pressed(bindings.cycle_vertex_limit) {
+        return;
+    }
+    let current = VERTEX_LIMITS
+        .iter()
+        .position(|&limit| limit == editor_world.max_vertices())
+        .unwrap_or(0);
+    let limit = VERTEX_LIMITS[(current + 1) % VERTEX_LIMITS.len()];
+    editor_world.set_max_vertices(limit);
+    match limit {
+        Some(max_vertices) => info!("Buildings are limited to {max_vertices} points"),
+        None => info!("Buildings may have any number of points"),
+    }
+}
+
+/// Cycles the voxel symmetry, and draws its mirror plane or rotation axis.
+fn symmetry_system(
+    mut gizmos: Gizmos,
+    mut voxels: ResMut<Voxels>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.cycle_symmetry) {
+        let symmetry = voxels.symmetry().next();
+        voxels.set_symmetry(symmetry);
+        info!("Symmetry: {symmetry:?}");
+    }
+
+    let color = Color::linear_rgba(0.5, 1., 1., 0.6);
+    let size = 40. * VOXEL_SIZE;
+    match voxels.symmetry() {
+        SymmetryKind::None => {}
+        SymmetryKind::Rotation => {
+            gizmos.line(Vec3::NEG_Y * size, Vec3::Y * size, color);
+        }
+        SymmetryKind::MirrorX => {
+            gizmos.rect(
+                Isometry3d::from_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+                Vec2::splat(size),
+                color,
+            );
+        }
+        SymmetryKind::MirrorZ => {
+            gizmos.rect(Isometry3d::IDENTITY, Vec2::splat(size), color);
+        }
+        SymmetryKind::QuadMirror => {
+            gizmos.rect(
+                Isometry3d::from_rotation(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2)),
+                Vec2::splat(size),
+                color,
+            );
+            gizmos.rect(Isometry3d::IDENTITY, Vec2::splat(size), color);
+        }
+    }
+}
+
+/// Undoes building edits with `Ctrl+Z`, and redoes them with `Ctrl+Shift+Z`, unless the active
+/// tool edits voxels.
+fn building_undo_system(
+    mut editor_world: ResMut<EditorWorld>,
+    mut selection: ResMut<Selection>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if editor_world.tool().undoes_voxels()
+        || !keys.just_pressed(bindings.undo)
+        || !(keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight))
+    {
+        return;
+    }
+
+    let changed = if keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight) {
+        editor_world.redo()
+    } else {
+        editor_world.undo()
+    };
+
+    // Building and point indexes may no longer refer to the same things.
+    if changed {
+        *selection = Selection::Nothing;
+    }
 }
 
+/// How far (in pixels) the cursor must move after pressing a point before the point is dragged.
+const DRAG_THRESHOLD_PIXELS: f32 = 4.0;
+
+/// How close (in grid cells) a dragged point must be to the symmetry axis to snap onto it.
+const SYMMETRY_SNAP_DISTANCE: i32 = 1;
+
+/// Moves a point onto each mirror axis of `symmetry` that it is within `SYMMETRY_SNAP_DISTANCE`.
+fn snap_to_symmetry_axis(p: IVec2, symmetry: SymmetryKind) -> IVec2 {
+    match symmetry {
+        SymmetryKind::MirrorX if p.x.abs() <= SYMMETRY_SNAP_DISTANCE => IVec2::new(0, p.y),
+        SymmetryKind::MirrorZ if p.y.abs() <= SYMMETRY_SNAP_DISTANCE => IVec2::new(p.x, 0),
+        SymmetryKind::QuadMirror => {
+            let snap = |v: i32| {
+                if v.abs() <= SYMMETRY_SNAP_DISTANCE {
+                    0
+                } else {
+                    v
+                }
+            };
+            IVec2::new(snap(p.x), snap(p.y))
+        }
+        _ => p,
+    }
+}
+
+/// How close (in grid cells) a dragged point must be to another building's vertex to snap onto it.
+const VERTEX_SNAP_DISTANCE: f32 = 1.5;
+
+/// How close (in grid cells) a click must be to a building edge to insert a point on it.
+const EDGE_INSERT_DISTANCE: f32 = 0.5;
+
+/// How far (in grid cells) a click must be from a building's points to insert a point next to them.
+const EDGE_INSERT_POINT_CLEARANCE: f32 = 1.0;
+
+/// Finds the building edge under the cursor where a new point could be inserted.
+///
+/// Returns the building index, the index of the point the edge starts at, and the grid point to
+/// insert.
+fn find_edge_insertion(
+    mouse_grid: &MouseGrid,
+    buildings: &[Building],
+) -> Option<(usize, usize, IVec2)> {
+    for (building_index, building) in buildings.iter().enumerate() {
+        let Some(p) = mouse_grid.pick_plane(building.floor_y()) else {
+            continue;
+        };
+        let p = p.xz() / VOXEL_SIZE;
+
+        let points = building.points();
+        for edge_index in 0..points.len() {
+            let a = points[edge_index].as_vec2();
+            let b = points[(edge_index + 1) % points.len()].as_vec2();
+            let on_edge = point_closest_to_segment(p, (a, b));
+            if on_edge.distance(p) <= EDGE_INSERT_DISTANCE
+                && a.distance(p) > EDGE_INSERT_POINT_CLEARANCE
+                && b.distance(p) > EDGE_INSERT_POINT_CLEARANCE
+            {
+                return Some((building_index, edge_index, on_edge.round().as_ivec2()));
+            }
+        }
+    }
+    None
+}
+
+/// What is being dragged in `move_building_system`.
+#[derive(Clone, Copy, Debug, Default)]
+enum DragTarget {
+    /// The selected building point.
+    #[default]
+    Vertex,
+    /// The whole selected building.
+    Building {
+        /// The grid point under the cursor when the building was last moved.
+        last_mouse: IVec2,
+        /// How far the building has moved since the drag began, undone if it is cancelled.
+        moved: IVec2,
+    },
+}
+
+/// The state of a drag in `move_building_system`.
+#[derive(Default)]
 struct DraggingState {
-    building_index: usize,
-    point_index: usize,
+    /// The cursor position where the point was pressed, while the mouse button is held.
+    pressed_at: Option<Vec2>,
+    /// The position of the point before the drag began, restored if the drag is cancelled.
+    original_point: Option<IVec2>,
+    /// Whether the point is following the cursor.
+    dragging: bool,
+    /// What the drag moves.
+    target: DragTarget,
 }
 
+/// Runs the `EditorTool::SelectBuilding` tool.
+///
+/// Clicking a building point selects it, and holding the mouse button drags the selected point.
+/// The point only starts moving once the cursor has left the spot where it was pressed, so a plain
+/// click never nudges it. Pressing `Escape` during a drag puts the point back where it started.
+///
+/// Clicking on a building edge, away from its points, inserts a new point there and starts
+/// dragging it straight away. Clicking anywhere else inside a building selects it, and dragging
+/// moves the whole building.
+///
+/// When the voxel grid is mirrored, dragged points near the mirror axis snap onto it. Dragged points
+/// also snap onto nearby vertices of other buildings. Holding `Alt` turns off snapping.
+///
+/// `PageUp` and `PageDown` raise and lower the floor of the hovered building.
 fn move_building_system(
+    mut gizmos: Gizmos,
+    window: Query<&Window, With<PrimaryWindow>>,
     mouse_grid: MouseGrid,
     mut editor_world: ResMut<EditorWorld>,
     common: Res<Common>,
-    mut preview: Local<Previewer<IVec3>>,
+    mut preview: Local<Option<Previewer<(IVec3, i32)>>>,
+    mut preview_stats: ResMut<PreviewStats>,
     mut commands: Commands,
+    mut selection: ResMut<Selection>,
+    voxels: Res<Voxels>,
+    hovered: Res<HoveredBuilding>,
 
-    mut dragging: Local<Option<DraggingState>>,
+    mut drag: Local<DraggingState>,
     mouse_button: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
 ) {
+    let preview = preview.get_or_insert_with(|| Previewer::with_pooling(1));
+    preview_stats.record("building cursor", preview);
     let mut preview = preview.collect_scope(&mut commands);
 
-    if !matches!(editor_world.tool(), EditorTool::SelectBuilding) {
-        if dragging.is_some() {
-            *dragging = None;
+    if !matches!(editor_world.tool(), EditorTool::SelectBuilding)
+        || !mouse_button.pressed(MouseButton::Left)
+    {
+        if drag.dragging {
+            editor_world.end_edit_group();
         }
+        *drag = DraggingState::default();
+    }
+    if !matches!(editor_world.tool(), EditorTool::SelectBuilding) {
         return;
     }
 
-    if dragging.is_some() && !mouse_button.pressed(MouseButton::Left) {
-        *dragging = None;
+    // Raise or lower the hovered building's floor, or its ceiling with `Shift`.
+    let floor_change = if keys.just_pressed(bindings.raise_floor) {
+        1
+    } else if keys.just_pressed(bindings.lower_floor) {
+        -1
+    } else {
+        0
+    };
+    let hovered_building = hovered
+        .building
+        .filter(|&index| index < editor_world.buildings().len());
+    let shift = keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight);
+    match hovered_building.filter(|_| floor_change != 0) {
+        // With `Shift`, move the ceiling instead.
+        Some(building_index) if shift => {
+            let height = editor_world.buildings()[building_index].height;
+            editor_world.set_building_height(building_index, height + floor_change);
+        }
+        Some(building_index) => {
+            let floor_y = editor_world.buildings()[building_index].floor_y();
+            editor_world.set_building_floor(building_index, floor_y + floor_change);
+        }
+        None => {}
+    }
+
+    // Rotate the selected buildings a quarter turn.
+    if keys.just_pressed(bindings.rotate_building) && !drag.dragging {
+        editor_world.begin_edit_group();
+        for building_index in selection.buildings() {
+            if !editor_world.rotate_building(building_index) {
+                info!("building {building_index} cannot be rotated without becoming invalid");
+            }
+        }
+        editor_world.end_edit_group();
     }
 
+    let cursor = window
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+
     let editing_plane_y = 0;
 
     let Some(mouse) = mouse_grid.pick_grid(editing_plane_y) else {
         return;
     };
+    // Hit testing uses the exact grid point, but drags move to snapped points.
+    let Some(snapped_mouse) = mouse_grid.pick_snapped_grid(editing_plane_y, &editor_world) else {
+        return;
+    };
 
     if mouse_button.just_pressed(MouseButton::Left) {
         // Find the selected point, if any.
-        for (building_index, building) in editor_world.buildings().iter().enumerate() {
-            for (point_index, point) in building.points().iter().enumerate() {
-                if *point == mouse.xz() {
-                    *dragging = Some(DraggingState {
-                        building_index,
-                        point_index,
-                    });
-                }
+        *selection = Selection::Nothing;
+        let picked_point = editor_world.building_point_at(mouse.xz());
+        if let Some((building_index, point_index)) = picked_point {
+            drag.pressed_at = cursor;
+            drag.original_point = Some(mouse.xz());
+            drag.target = DragTarget::Vertex;
+            *selection = Selection::Vertex {
+                building: building_index,
+                point: point_index,
+            };
+        }
+
+        let insertion = match *selection {
+            Selection::Nothing => find_edge_insertion(&mouse_grid, editor_world.buildings()),
+            _ => None,
+        };
+        if let Some((building_index, edge_index, p)) = insertion {
+            // The insertion and the drag that follows are undone together.
+            editor_world.begin_edit_group();
+            if editor_world.insert_building_point(building_index, edge_index, p) {
+                drag.pressed_at = cursor;
+                drag.original_point = Some(p);
+                drag.dragging = true;
+                drag.target = DragTarget::Vertex;
+                *selection = Selection::Vertex {
+                    building: building_index,
+                    point: edge_index + 1,
+                };
+            } else {
+                editor_world.end_edit_group();
             }
         }
-    }
 
-    if let Some(dragging_state) = dragging.as_ref() {
-        let building = &editor_world.buildings()[dragging_state.building_index];
+        // Otherwise, clicking inside a building picks up the whole building.
+        let picked_building = match (&*selection, insertion) {
+            (Selection::Nothing, None) => editor_world.building_at(mouse.xz()),
+            _ => None,
+        };
+        if let Some(building_index) = picked_building {
+            drag.pressed_at = cursor;
+            drag.target = DragTarget::Building {
+                last_mouse: snapped_mouse.xz(),
+                moved: IVec2::ZERO,
+            };
+            *selection = Selection::Building(building_index);
+        }
+    }
 
-        let mouse_point = mouse.xz();
+    if drag
+        .pressed_at
+        .zip(cursor)
+        .is_some_and(|(start, cursor)| start.distance(cursor) > DRAG_THRESHOLD_PIXELS)
+        && !drag.dragging
+    {
+        // The whole drag is undone as one step.
+        editor_world.begin_edit_group();
+        drag.dragging = true;
+    }
 
-        if building.points()[dragging_state.point_index] != mouse_point {
-            let mut new_building = building.clone();
-            new_building.outline[dragging_state.point_index] = mouse_point;
-            if new_building.is_valid(BuildingValidity::default()) {
-                editor_world.set_building_point(
-                    dragging_state.building_index,
-                    dragging_state.point_index,
-                    mouse_point,
-                );
+    if keys.just_pressed(bindings.cancel) && drag.dragging {
+        // Cancel the drag. The selection stays, but ignores the cursor until the next press.
+        match (&*selection, drag.original_point, drag.target) {
+            (&Selection::Vertex { building, point }, Some(original_point), _) => {
+                editor_world.set_building_point(building, point, original_point);
+            }
+            (&Selection::Building(building), _, DragTarget::Building { moved, .. }) => {
+                editor_world.try_translate_building(building, -moved);
             }
+            _ => {}
+        }
+        editor_world.end_edit_group();
+        *drag = DraggingState::default();
+    }
+
+    // The dragged building is the selected one; if it is deselected, the drag ends.
+    let dragging = drag.dragging;
+    if let (
+        true,
+        &Selection::Building(building_index),
+        DragTarget::Building { last_mouse, moved },
+    ) = (dragging, &*selection, &mut drag.target)
+    {
+        let delta = snapped_mouse.xz() - *last_mouse;
+        if delta != IVec2::ZERO && editor_world.try_translate_building(building_index, delta) {
+            *last_mouse = snapped_mouse.xz();
+            *moved += delta;
+        }
+    }
+
+    // The dragged point is the selected one; if it is deselected (e.g. deleted), the drag ends.
+    let dragged_point = match *selection {
+        Selection::Vertex { building, point } if drag.dragging => Some((building, point)),
+        _ => None,
+    };
+    if let Some((building_index, point_index)) = dragged_point {
+        let building = &editor_world.buildings()[building_index];
+
+        let mut mouse_point = snapped_mouse.xz();
+        let snapping = !keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]);
+        if snapping {
+            mouse_point = snap_to_symmetry_axis(mouse_point, voxels.symmetry());
+        }
+
+        // Snap onto a nearby vertex of another building, if the result is valid.
+        let vertex_snap = editor_world
+            .nearest_vertex(mouse_point, (building_index, point_index))
+            .filter(|&vertex| {
+                snapping
+                    && vertex.as_vec2().distance(mouse_point.as_vec2()) <= VERTEX_SNAP_DISTANCE
+                    && building.is_valid_after_point_change(point_index, vertex)
+            });
+        if let Some(vertex) = vertex_snap {
+            mouse_point = vertex;
+            gizmos.sphere(
+                grid_to_world(from_flat(vertex, building.floor_y())),
+                16.,
+                Color::linear_rgb(0., 1., 1.),
+            );
+        }
+
+        // Moves which would make the building invalid are refused, leaving the point where it was.
+        if building.points()[point_index] != mouse_point {
+            editor_world.set_building_point(building_index, point_index, mouse_point);
         }
     }
 
-    let world_mouse = grid_to_world(mouse);
+    let world_mouse = grid_to_world(snapped_mouse);
+    // The cursor covers the cell it snaps to, so it grows with the snapping step.
+    let snap_step = editor_world.snap_step();
 
-    preview.render(&mouse, |commands| {
-        commands
-            .spawn((
-                Transform::from_translation(world_mouse)
-                    .with_scale(Vec3::new(0.4, 0.005, 0.4) * VOXEL_SIZE),
-                Mesh3d(common.cube_mesh.clone()),
-                MeshMaterial3d(common.ui_gold_material.clone()),
-                RenderLayers::layer(7),
-            ))
-            .id()
+    preview.render_bundle(&(snapped_mouse, snap_step), || {
+        let cell = 0.4 * snap_step as f32;
+        (
+            Transform::from_translation(world_mouse)
+                .with_scale(Vec3::new(cell, 0.005, cell) * VOXEL_SIZE),
+            Mesh3d(common.cube_mesh.clone()),
+            MeshMaterial3d(common.ui_gold_material.clone()),
+            RenderLayers::layer(7),
+        )
     });
 }
 
+/// How far a pasted building is moved from the copied one, so that they don't exactly overlap.
+const PASTE_OFFSET: IVec2 = IVec2::splat(2);
+
+/// A pasted building which is following the mouse.
+#[derive(Clone, Debug)]
+struct PastedBuilding {
+    building: usize,
+    /// The grid point under the mouse when the building was last moved.
+    last_mouse: Option<IVec2>,
+}
+
+/// Copies the selected building with `Ctrl+C`, and pastes it with `Ctrl+V`.
+///
+/// The pasted building follows the mouse until it is placed with a click. Pressing `Escape`
+/// instead removes it again. The paste and the following moves are undone together.
+fn clipboard_system(
+    mouse_grid: MouseGrid,
+    mut editor_world: ResMut<EditorWorld>,
+    mut selection: ResMut<Selection>,
+    mut pasted: Local<Option<PastedBuilding>>,
+    mut mouse_button: ResMut<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    let ctrl = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+
+    if ctrl && keys.just_pressed(bindings.copy) {
+        let copied = match *selection {
+            Selection::Building(building) | Selection::Vertex { building, .. } => Some(building),
+            _ => None,
+        };
+        if let Some(building) = copied {
+            editor_world.copy_building(building);
+        }
+    }
+
+    if ctrl && keys.just_pressed(bindings.paste) && pasted.is_none() {
+        editor_world.begin_edit_group();
+        match editor_world.paste_building(PASTE_OFFSET) {
+            Some(building) => {
+                *selection = Selection::Building(building);
+                *pasted = Some(PastedBuilding {
+                    building,
+                    last_mouse: None,
+                });
+            }
+            None => editor_world.end_edit_group(),
+        }
+    }
+
+    // The pasted building may have been removed, e.g. by an undo.
+    let Some(paste) = pasted
+        .as_mut()
+        .filter(|paste| paste.building < editor_world.buildings().len())
+    else {
+        *pasted = None;
+        return;
+    };
+
+    if keys.just_pressed(bindings.cancel) {
+        editor_world.end_edit_group();
+        editor_world.undo();
+        *selection = Selection::Nothing;
+        *pasted = None;
+        return;
+    }
+
+    let editing_plane_y = 0;
+    if let Some(mouse) = mouse_grid.pick_snapped_grid(editing_plane_y, &editor_world) {
+        let mouse = mouse.xz();
+        if let Some(last_mouse) = paste.last_mouse.filter(|&last_mouse| last_mouse != mouse) {
+            editor_world.translate_building(paste.building, mouse - last_mouse);
+        }
+        paste.last_mouse = Some(mouse);
+    }
+
+    if mouse_button.just_pressed(MouseButton::Left) {
+        // The click places the building, so the other tools shouldn't also see it.
+        mouse_button.clear_just_pressed(MouseButton::Left);
+        editor_world.end_edit_group();
+        *pasted = None;
+    }
+}
+
+/// Deletes whatever is currently selected when `Delete` is pressed.
+fn delete_selected_system(
+    mut commands: Commands,
+    mut editor_world: ResMut<EditorWorld>,
+    mut selection: ResMut<Selection>,
+    mut voxels: ResMut<Voxels>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if !keys.just_pressed(bindings.delete_selected) {
+        return;
+    }
+
+    match &*selection {
+        Selection::Nothing => {}
+        &Selection::Vertex { building, point } => {
+            // Buildings need at least 3 points, so this may be refused.
+            if !editor_world.remove_building_point(building, point) {
+                return;
+            }
+        }
+        &Selection::Building(building) => {
+            if building < editor_world.buildings().len() {
+                editor_world.remove_building(building);
+            }
+        }
+        Selection::Buildings(buildings) => {
+            // Remove from the back so that the remaining indexes stay valid.
+            let mut buildings: Vec<usize> = buildings.iter().copied().collect();
+            buildings.sort();
+            for &building in buildings.iter().rev() {
+                if building < editor_world.buildings().len() {
+                    editor_world.remove_building(building);
+                }
+            }
+        }
+        Selection::Voxels(selected_voxels) => {
+            for &voxel in selected_voxels.iter() {
+                voxels.remove_voxel(&mut commands, voxel);
+            }
+        }
+    }
+
+    // Commit the removed voxels as their own action, so that one undo restores them.
+    if voxels.has_changes_to_commit() {
+        let editor_state_before =
+            voxels
+                .editor_state_before
+                .clone()
+                .unwrap_or(CommittedEditorState {
+                    selection: Vec::new(),
+                    world_face: None,
+                });
+        voxels.commit_changes(editor_state_before);
+    }
+
+    *selection = Selection::Nothing;
+}
+
 /// A system parameter for getting the mouse position in the world grid.
 #[derive(SystemParam)]
 pub struct MouseGrid<'w> {
     ray_map: Res<'w, bevy::picking::backend::ray::RayMap>,
 }
 
+const MAX_PICK_DISTANCE: f32 = 10_000.0;
+
 impl MouseGrid<'_> {
-    fn pick_grid(&self, editing_plane_y: i32) -> Option<IVec3> {
-        let mouse_ray = self.ray_map.iter().next().map(|r| *r.1);
+    /// Returns the grid point under the mouse on the plane at grid height `editing_plane_y`, for
+    /// finding what is under the mouse.
+    pub fn pick_grid(&self, editing_plane_y: i32) -> Option<IVec3> {
+        let mouse_ray = self.ray_map.iter().next().map(|r| *r.1)?;
 
-        let max_pick_distance = 10_000.0;
+        pick_grid_from_ray(mouse_ray, editing_plane_y, MAX_PICK_DISTANCE)
+    }
 
-        let mouse_point: Option<Vec3> = (|| {
-            let mouse_ray = mouse_ray?;
-            let intersection_distance = mouse_ray.intersect_plane(
-                grid_to_world(IVec3::Y * editing_plane_y),
-                InfinitePlane3d::new(Vec3::Y),
-            )?;
+    /// Returns the grid point under the mouse like `pick_grid`, but rounded to a multiple of the
+    /// snapping step, for placing or dragging points and buildings.
+    pub fn pick_snapped_grid(
+        &self,
+        editing_plane_y: i32,
+        editor_world: &EditorWorld,
+    ) -> Option<IVec3> {
+        let mouse_ray = self.ray_map.iter().next().map(|r| *r.1)?;
 
-            if intersection_distance > max_pick_distance {
-                return None;
+        pick_snapped_grid_from_ray(
+            mouse_ray,
+            editing_plane_y,
+            MAX_PICK_DISTANCE,
+            editor_world.snap_step(),
+        )
+    }
+
+    /// Returns the exact world-space point under the mouse on the plane at grid height `plane_y`.
+    fn pick_plane(&self, plane_y: i32) -> Option<Vec3> {
+        let mouse_ray = self.ray_map.iter().next().map(|r| *r.1)?;
+
+        pick_plane_from_ray(mouse_ray, plane_y, MAX_PICK_DISTANCE)
+    }
+
+    /// Returns the nearest triangle of `csg` under the mouse.
+    fn pick_face(&self, csg: &CSG) -> Option<SelectedWorldFace> {
+        let mouse_ray = self.ray_map.iter().next().map(|r| *r.1)?;
+
+        pick_face_from_ray(mouse_ray, csg, MAX_PICK_DISTANCE)
+    }
+}
+
+/// Intersects a ray with the horizontal plane at grid height `plane_y`, returning the world-space
+/// intersection point.
+///
+/// Returns `None` if the ray does not hit the plane, or hits it further than `max_dist` away.
+pub fn pick_plane_from_ray(ray: Ray3d, plane_y: i32, max_dist: f32) -> Option<Vec3> {
+    let intersection_distance = ray.intersect_plane(
+        grid_to_world(IVec3::Y * plane_y),
+        InfinitePlane3d::new(Vec3::Y),
+    )?;
+
+    if intersection_distance > max_dist {
+        return None;
+    }
+
+    Some(ray.get_point(intersection_distance))
+}
+
+/// Intersects a ray with the horizontal plane at grid height `plane_y`, returning the nearest grid
+/// point to the intersection.
+///
+/// Returns `None` if the ray does not hit the plane, or hits it further than `max_dist` away.
+pub fn pick_grid_from_ray(ray: Ray3d, plane_y: i32, max_dist: f32) -> Option<IVec3> {
+    pick_plane_from_ray(ray, plane_y, max_dist).map(world_to_grid)
+}
+
+/// Intersects a ray with the horizontal plane at grid height `plane_y`, returning the nearest grid
+/// point whose `x` and `z` are multiples of `snap_step`. Halfway points round up.
+///
+/// The exact intersection is rounded once, since rounding it to the grid first and then to the
+/// snapping step can pick a point which is not the nearest.
+///
+/// Returns `None` if the ray does not hit the plane, or hits it further than `max_dist` away.
+pub fn pick_snapped_grid_from_ray(
+    ray: Ray3d,
+    plane_y: i32,
+    max_dist: f32,
+    snap_step: i32,
+) -> Option<IVec3> {
+    let p = pick_plane_from_ray(ray, plane_y, max_dist)? / (VOXEL_SIZE * snap_step as f32);
+    let snap = |v: f32| (v + 0.5).floor() as i32 * snap_step;
+    Some(IVec3::new(snap(p.x), plane_y, snap(p.z)))
+}
+
+/// Intersects a ray with the triangles of `csg`, returning the nearest triangle hit.
+/// Polygons with more than 3 vertices are split into a fan of triangles.
+///
+/// Returns `None` if the ray hits nothing within `max_dist`.
+pub fn pick_face_from_ray(ray: Ray3d, csg: &CSG, max_dist: f32) -> Option<SelectedWorldFace> {
+    let mut nearest: Option<(f32, SelectedWorldFace)> = None;
+    for poly in csg.polygons.iter() {
+        let Some(detail) = poly.metadata else {
+            continue;
+        };
+        let Some(first) = poly.vertices.first() else {
+            continue;
+        };
+        let normal = Vec3::new(
+            first.normal.x as f32,
+            first.normal.y as f32,
+            first.normal.z as f32,
+        );
+        let points: Vec<Vec3> = poly
+            .vertices
+            .iter()
+            .map(|v| Vec3::new(v.pos.x as f32, v.pos.y as f32, v.pos.z as f32))
+            .collect();
+
+        for pair in points[1..].windows(2) {
+            let triangle = [points[0], pair[0], pair[1]];
+            let Some(distance) = ray_triangle_intersection(ray, triangle) else {
+                continue;
+            };
+            if distance > max_dist || nearest.is_some_and(|(nearest, _)| nearest <= distance) {
+                continue;
             }
+            let hit = SelectedWorldFace {
+                triangle,
+                normal,
+                detail,
+            };
+            nearest = Some((distance, hit));
+        }
+    }
+    nearest.map(|(_, hit)| hit)
+}
+
+/// The building currently under the mouse cursor.
+#[derive(Resource, Default)]
+pub struct HoveredBuilding {
+    /// The hovered building index, after debouncing.
+    pub building: Option<usize>,
+    /// The building most recently found under the cursor, which may not be stable yet.
+    candidate: Option<usize>,
+    /// The number of consecutive frames `candidate` has been under the cursor.
+    candidate_frames: u32,
+}
+
+/// The number of frames a new building must stay under the cursor before it counts as hovered.
+const HOVER_DEBOUNCE_FRAMES: u32 = 2;
+
+/// Finds the building under the mouse cursor.
+fn hover_building_system(
+    mouse_grid: MouseGrid,
+    editor_world: Res<EditorWorld>,
+    mut hovered: ResMut<HoveredBuilding>,
+) {
+    let candidate = editor_world
+        .buildings()
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, building)| {
+            mouse_grid
+                .pick_plane(building.floor_y())
+                .is_some_and(|p| building.contains(p.xz() / VOXEL_SIZE))
+        })
+        .map(|(index, _)| index);
 
-            Some(mouse_ray.get_point(intersection_distance))
-        })();
+    if candidate != hovered.candidate {
+        hovered.candidate = candidate;
+        hovered.candidate_frames = 0;
+    }
+    hovered.candidate_frames = hovered.candidate_frames.saturating_add(1);
 
-        mouse_point.map(world_to_grid)
+    let building = if hovered.candidate_frames >= HOVER_DEBOUNCE_FRAMES {
+        hovered.candidate
+    } else {
+        // Keep the previous building while the new one settles, unless it no longer exists.
+        hovered
+            .building
+            .filter(|&index| index < editor_world.buildings().len())
+    };
+    if hovered.building != building {
+        hovered.building = building;
     }
 }
-/// Runs the `EditorTool::CreateBuilding` tool.
-pub fn editor_insert_building_system(
+
+/// Labels the hovered building with its area and perimeter, and each of its edges with its length,
+/// while the `EditorTool::SelectBuilding` tool is active.
+///
+/// Measurements are in grid units, and the totals are also given in Hammer units.
+fn draw_building_measurements_system(
+    editor_world: Res<EditorWorld>,
+    hovered: Res<HoveredBuilding>,
+    mut labels: ResMut<WorldLabels>,
+) {
+    if !matches!(editor_world.tool(), EditorTool::SelectBuilding) {
+        return;
+    }
+    let Some(building) = hovered
+        .building
+        .and_then(|index| editor_world.buildings().get(index))
+    else {
+        return;
+    };
+
+    let color = Color::linear_rgb(0.6, 0.9, 1.);
+    let points = building.points();
+    let floor_y = building.floor_y() as f32;
+    let to_world = |p: Vec2| Vec3::new(p.x, floor_y, p.y) * VOXEL_SIZE;
+
+    let mut perimeter = 0.0;
+    for i in 0..points.len() {
+        let a = points[i].as_vec2();
+        let b = points[(i + 1) % points.len()].as_vec2();
+        let length = a.distance(b);
+        perimeter += length;
+        labels.add(to_world(a.lerp(b, 0.5)), format!("{length:.1}"), color);
+    }
+
+    let area = building.area();
+    labels.add(
+        to_world(polygon_centroid(points)),
+        format!(
+            "Area: {area:.1} ({:.0} HU\u{b2})\nPerimeter: {perimeter:.1} ({:.0} HU)",
+            area * VOXEL_SIZE * VOXEL_SIZE,
+            perimeter * VOXEL_SIZE,
+        ),
+        color,
+    );
+}
+
+/// How far (in grid cells) the cursor may be from a building's point to pick that building for
+/// deletion.
+const DELETE_PICK_DISTANCE: f32 = 1.0;
+
+/// Runs the `EditorTool::DeleteBuilding` tool.
+///
+/// The building under the cursor (or with a point near it) is highlighted in red, and clicking
+/// removes it.
+fn delete_building_system(
     mut gizmos: Gizmos,
     mouse_grid: MouseGrid,
     mouse_button: Res<ButtonInput<MouseButton>>,
-
     mut editor_world: ResMut<EditorWorld>,
-    keys: Res<ButtonInput<KeyCode>>,
-    mut points: Local<Vec<IVec2>>,
+    mut selection: ResMut<Selection>,
 ) {
-    if !matches!(editor_world.tool(), EditorTool::CreateBuilding) {
-        if !points.is_empty() {
-            points.clear();
-        }
+    if !matches!(editor_world.tool(), EditorTool::DeleteBuilding) {
         return;
     }
 
-    let color_active = Color::linear_rgb(1., 1., 0.);
-    let color_speculative = Color::linear_rgb(0., 0., 1.);
-    let color_invalid = Color::linear_rgb(1., 0., 0.);
+    let target = editor_world
+        .buildings()
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, building)| {
+            let Some(p) = mouse_grid.pick_plane(building.floor_y()) else {
+                return false;
+            };
+            let p = p.xz() / VOXEL_SIZE;
+            building.contains(p)
+                || building
+                    .points()
+                    .iter()
+                    .any(|point| point.as_vec2().distance(p) <= DELETE_PICK_DISTANCE)
+        })
+        .map(|(index, _)| index);
+    let Some(target) = target else {
+        return;
+    };
 
-    if keys.just_pressed(KeyCode::Escape) {
-        points.clear();
+    let color_delete = Color::linear_rgb(1., 0.1, 0.1);
+    let building = &editor_world.buildings()[target];
+    let points = building.points();
+    for i in 0..points.len() {
+        let a = points[i];
+        let b = points[(i + 1) % points.len()];
+        for y in [building.floor_y(), building.ceiling_y()] {
+            gizmos.line(
+                grid_to_world(from_flat(a, y)),
+                grid_to_world(from_flat(b, y)),
+                color_delete,
+            );
+        }
+        gizmos.line(
+            grid_to_world(from_flat(a, building.floor_y())),
+            grid_to_world(from_flat(a, building.ceiling_y())),
+            color_delete,
+        );
     }
 
-    let editing_plane_y = 0;
-    let mouse_point_grid = mouse_grid.pick_grid(editing_plane_y);
+    if mouse_button.just_pressed(MouseButton::Left) {
+        editor_world.remove_building(target);
+        // Later buildings have shifted down, so selected indexes may be stale.
+        *selection = Selection::Nothing;
+    }
+}
 
-    let new_point_is_valid = (|| {
-        let Some(mouse_point_grid) = mouse_point_grid else {
-            return false;
-        };
+/// How far (in grid cells) a reference vertex can be from the cursor to be snapped to.
+const REFERENCE_SNAP_DISTANCE: f32 = 1.5;
 
-        let mouse_point_grid = to_flat(mouse_point_grid);
+/// Finds the reference vertex nearest to the world-space point `p`, among those within half a cell
+/// of the plane at grid height `plane_y`, and returns the grid point closest to it.
+fn snap_to_reference(p: Vec3, plane_y: i32, reference: &ReferenceGeometry) -> Option<IVec3> {
+    let p = p / VOXEL_SIZE;
+    reference
+        .vertices()
+        .iter()
+        .map(|&vertex| vertex / VOXEL_SIZE)
+        .filter(|vertex| (vertex.y - plane_y as f32).abs() <= 0.5)
+        .map(|vertex| (vertex, vertex.xz().distance(p.xz())))
+        .filter(|&(_, distance)| distance <= REFERENCE_SNAP_DISTANCE)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(vertex, _)| from_flat(vertex.xz().round().as_ivec2(), plane_y))
+}
 
-        if points.len() <= 1 {
-            // No possible invalid states.
-            return true;
-        }
+/// How far (in grid cells) the cursor can be from a building edge to be snapped onto it.
+const EDGE_SNAP_DISTANCE: f32 = 0.5;
+
+/// Finds the grid point on an existing building edge nearest to the world-space point `p`, if an
+/// edge is close enough, so that new walls can be drawn flush against existing ones.
+fn snap_to_building_edge(p: Vec3, plane_y: i32, editor_world: &EditorWorld) -> Option<IVec3> {
+    let p = p / VOXEL_SIZE;
+    editor_world
+        .nearest_edge_point(p.xz(), EDGE_SNAP_DISTANCE)
+        .map(|p| from_flat(p, plane_y))
+}
 
-        if points.len() == 2 && mouse_point_grid == points[0] {
-            return false;
+/// Two clicks within this many seconds are a double-click, which closes the building being drawn.
+const DOUBLE_CLICK_SECONDS: f32 = 0.3;
+
+/// How long the building being drawn flashes red after a double-click fails to close it.
+const INVALID_CLOSE_FLASH_SECONDS: f32 = 0.4;
+
+/// Why a point can't be added to the outline of the building being drawn.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum PlacementError {
+    /// The outline would be closed with fewer than 3 points.
+    TooFewPoints,
+    /// The point is already part of the outline.
+    DuplicatePoint,
+    /// A corner of the outline would be too sharp.
+    TooSharp,
+    /// The new edge would pass too close to a point of the outline, or the point would be too
+    /// close to an edge.
+    TooCloseToEdge,
+    /// The new edge would cross an edge of the outline.
+    CrossesEdge,
+}
+
+impl std::fmt::Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PlacementError::TooFewPoints => "A building needs at least 3 points",
+            PlacementError::DuplicatePoint => "Point is already in the outline",
+            PlacementError::TooSharp => "Corner is too sharp",
+            PlacementError::TooCloseToEdge => "Too close to an edge",
+            PlacementError::CrossesEdge => "Edge crosses the outline",
+        })
+    }
+}
+
+/// Checks whether `p` can be added as the next point of the partially drawn outline `points`.
+/// Adding `points[0]` again closes the outline. New buildings have the default wall thickness.
+fn check_next_point(points: &[IVec2], p: IVec2) -> Result<(), PlacementError> {
+    if points.len() <= 1 {
+        // No possible invalid states.
+        return Ok(());
+    }
+
+    if points.len() == 2 && p == points[0] {
+        return Err(PlacementError::TooFewPoints);
+    }
+
+    if points.len() >= 3 && p == points[0] {
+        // If the corner is too sharp, then we have a problem.
+        let corner = Corner {
+            a: points[1],
+            pivot: points[0],
+            b: points[points.len() - 1],
+        };
+        if is_corner_too_sharp(corner, BUILDING_WALL_THICKNESS) {
+            return Err(PlacementError::TooSharp);
         }
+    }
 
-        if points.len() >= 3 && mouse_point_grid == points[0] {
-            // If the corner is too sharp, then we have a problem.
-            if is_corner_too_sharp(Corner {
-                a: points[1],
-                pivot: points[0],
-                b: points[points.len() - 1],
-            }) {
-                return false;
-            }
+    if p != points[0] && points.contains(&p) {
+        return Err(PlacementError::DuplicatePoint);
+    }
+
+    let new_line: (IVec2, IVec2) = (points.last().copied().unwrap(), p);
+    // If this line crosses any existing line, it is invalid.
+    for q in points.iter().copied() {
+        if q == new_line.0 || q == new_line.1 {
+            continue;
         }
+        // Project q onto the line.
+        let q_on_line =
+            point_closest_to_segment(q.as_vec2(), (new_line.0.as_vec2(), new_line.1.as_vec2()));
 
-        if mouse_point_grid != points[0] && points.contains(&mouse_point_grid) {
-            return false;
+        if q_on_line.distance(q.as_vec2()) < MIN_INTERIOR_THICKNESS {
+            // This point is too close to the line.
+            return Err(PlacementError::TooCloseToEdge);
         }
+    }
 
-        let new_line: (IVec2, IVec2) = (points.last().copied().unwrap(), mouse_point_grid);
-        // If this line crosses any existing line, it is invalid.
-        for p in points.iter().copied() {
-            if p == new_line.0 || p == new_line.1 {
+    if points.len() >= 2 {
+        for i in 0..points.len() - 1 {
+            if points[i] == p || points[i + 1] == p {
                 continue;
             }
-            // Project p onto the line.
-            let p_on_line =
-                point_closest_to_segment(p.as_vec2(), (new_line.0.as_vec2(), new_line.1.as_vec2()));
+            let existing_line = (points[i].as_vec2(), points[i + 1].as_vec2());
+            let near = point_closest_to_segment(p.as_vec2(), existing_line);
+            if p.as_vec2().distance(near) < MIN_INTERIOR_THICKNESS {
+                return Err(PlacementError::TooCloseToEdge);
+            }
 
-            if p_on_line.distance(p.as_vec2()) < MIN_INTERIOR_THICKNESS {
-                // This point is too close to the line.
-                return false;
+            if i + 2 < points.len()
+                && segments_cross(existing_line, (new_line.0.as_vec2(), new_line.1.as_vec2()))
+            {
+                return Err(PlacementError::CrossesEdge);
             }
         }
+    }
 
-        if points.len() >= 2 {
-            for i in 0..points.len() - 1 {
-                if points[i] == mouse_point_grid || points[i + 1] == mouse_point_grid {
-                    continue;
-                }
-                let existing_line = (points[i].as_vec2(), points[i + 1].as_vec2());
-                let near = point_closest_to_segment(mouse_point_grid.as_vec2(), existing_line);
-                if mouse_point_grid.as_vec2().distance(near) < MIN_INTERIOR_THICKNESS {
-                    return false;
-                }
+    if points.len() >= 2 {
+        // If the corner is too sharp, then we have a problem.
+        let a = points[points.len() - 2];
+        let pivot = points[points.len() - 1];
+        let b = p;
 
-                if i + 2 < points.len()
-                    && segments_cross(existing_line, (new_line.0.as_vec2(), new_line.1.as_vec2()))
-                {
-                    return false;
-                }
-            }
+        if is_corner_too_sharp(Corner { a, pivot, b }, BUILDING_WALL_THICKNESS) {
+            return Err(PlacementError::TooSharp);
         }
+    }
 
-        if points.len() >= 2 {
-            // If the corner is too sharp, then we have a problem.
-            let a = points[points.len() - 2];
-            let pivot = points[points.len() - 1];
-            let b = mouse_point_grid;
+    Ok(())
+}
 
-            if is_corner_too_sharp(Corner { a, pivot, b }) {
-                return false;
-            }
+/// Runs the `EditorTool::CreateBuilding` tool.
+///
+/// Right-clicking without moving the mouse removes the last point.
+///
+/// Clicking on the first point again closes the building. Double-clicking also closes it, by
+/// joining the last point to the first; if that edge is invalid the outline flashes red instead.
+///
+/// New points snap to nearby vertices of the reference geometry, or else onto nearby edges of
+/// existing buildings, unless `Alt` is held.
+pub fn editor_insert_building_system(
+    mut gizmos: Gizmos,
+    mouse_grid: MouseGrid,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+
+    mut editor_world: ResMut<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+    mut labels: ResMut<WorldLabels>,
+    reference: Res<ReferenceGeometry>,
+    time: Res<Time>,
+    mut points: Local<Vec<IVec2>>,
+    mut last_click: Local<Option<f32>>,
+    mut invalid_close_flash_until: Local<f32>,
+    mut mouse_motion: EventReader<MouseMotion>,
+    mut right_drag_moved: Local<bool>,
+) {
+    let mouse_moved = mouse_motion.read().count() > 0;
+
+    if !matches!(editor_world.tool(), EditorTool::CreateBuilding) {
+        if !points.is_empty() {
+            points.clear();
         }
+        return;
+    }
+
+    let color_active = Color::linear_rgb(1., 1., 0.);
+    let color_speculative = Color::linear_rgb(0., 0., 1.);
+    let color_invalid = Color::linear_rgb(1., 0., 0.);
+    let color_reference_snap = Color::linear_rgb(1., 0., 1.);
+    let color_edge_snap = Color::linear_rgb(0., 1., 1.);
 
-        true
-    })();
+    if keys.just_pressed(bindings.cancel) {
+        points.clear();
+    }
+
+    // A right-click removes the last point, but a right-drag turns the camera instead.
+    if mouse_button.just_pressed(MouseButton::Right) {
+        *right_drag_moved = false;
+    }
+    if mouse_button.pressed(MouseButton::Right) && mouse_moved {
+        *right_drag_moved = true;
+    }
+    if mouse_button.just_released(MouseButton::Right) && !*right_drag_moved {
+        points.pop();
+    }
+
+    let editing_plane_y = 0;
+    let mouse_plane = mouse_grid
+        .pick_plane(editing_plane_y)
+        .filter(|_| !keys.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]));
+    let reference_snap =
+        mouse_plane.and_then(|p| snap_to_reference(p, editing_plane_y, &reference));
+    let edge_snap = mouse_plane
+        .filter(|_| reference_snap.is_none())
+        .and_then(|p| snap_to_building_edge(p, editing_plane_y, &editor_world));
+    let mouse_point_grid = reference_snap
+        .or(edge_snap)
+        .or_else(|| mouse_grid.pick_snapped_grid(editing_plane_y, &editor_world));
+
+    let new_point_error =
+        mouse_point_grid.and_then(|p| check_next_point(&points, to_flat(p)).err());
+    let new_point_is_valid = mouse_point_grid.is_some() && new_point_error.is_none();
+
+    // Only closing the building is allowed once it has as many points as permitted.
+    let at_vertex_limit = editor_world
+        .max_vertices()
+        .is_some_and(|max_vertices| points.len() >= max_vertices)
+        && mouse_point_grid.is_none_or(|p| points.first() != Some(&to_flat(p)));
 
     // Place the point, if it is valid.
     if let Some(mouse_point_grid) = mouse_point_grid {
         gizmos.sphere(
             grid_to_world(mouse_point_grid),
             8.,
-            if new_point_is_valid {
-                color_speculative
-            } else {
+            if !new_point_is_valid {
                 color_invalid
+            } else if reference_snap.is_some() {
+                color_reference_snap
+            } else if edge_snap.is_some() {
+                color_edge_snap
+            } else {
+                color_speculative
             },
         );
 
         if mouse_button.just_pressed(MouseButton::Left) {
-            if points.len() >= 3 && to_flat(mouse_point_grid) == points[0] && new_point_is_valid {
+            let now = time.elapsed_secs();
+            let double_click = last_click.is_some_and(|last| now - last <= DOUBLE_CLICK_SECONDS);
+            *last_click = Some(now);
+
+            let clicked_start = points.len() >= 3 && to_flat(mouse_point_grid) == points[0];
+            let double_click_closes = double_click && points.len() >= 3 && !clicked_start;
+            if double_click_closes {
+                // The first click of the pair placed the last point. A third click starts afresh.
+                *last_click = None;
+            }
+            let closing_edge_is_valid =
+                double_click_closes && check_next_point(&points, points[0]).is_ok();
+            if double_click_closes && !closing_edge_is_valid {
+                *invalid_close_flash_until = now + INVALID_CLOSE_FLASH_SECONDS;
+            }
+
+            if (clicked_start && new_point_is_valid) || closing_edge_is_valid {
                 // Create the new shape and insert it into the editor.
 
-                let mut points = std::mem::take(&mut *points);
+                let mut outline = points.clone();
 
-                if signed_polygon_area_2d(&points) < 0.0 {
-                    points.reverse();
+                if signed_polygon_area_2d(&outline) < 0.0 {
+                    outline.reverse();
                 }
 
-                editor_world.insert_building(Building::new(editing_plane_y, points))
+                // The limit may have been lowered while drawing; if so, the points are kept.
+                if editor_world.insert_building(Building::new(editing_plane_y, outline)) {
+                    points.clear();
+                }
+            } else if double_click_closes {
+                // The closing edge was invalid; keep the points so the outline can be fixed.
+            } else if new_point_is_valid && at_vertex_limit {
+                warn!(
+                    "Buildings are limited to {} points",
+                    editor_world.max_vertices().unwrap_or_default()
+                );
             } else if new_point_is_valid {
                 points.push(to_flat(mouse_point_grid));
             } else {
                 points.clear();
             }
         }
+
+        if at_vertex_limit {
+            labels.add(
+                grid_to_world(mouse_point_grid),
+                "Point limit reached: close the building",
+                color_invalid,
+            );
+        } else if let Some(error) = new_point_error {
+            labels.add(
+                grid_to_world(mouse_point_grid),
+                error.to_string(),
+                color_invalid,
+            );
+        }
     }
 
+    // The outline flashes red after a double-click that couldn't close it.
+    let color_outline = if time.elapsed_secs() < *invalid_close_flash_until {
+        color_invalid
+    } else {
+        color_active
+    };
+
     for i in 0..points.len() {
         let point_a = grid_to_world(from_flat(points[i], 0));
-        gizmos.sphere(point_a, 12., color_active);
+        gizmos.sphere(point_a, 12., color_outline);
         let (point_b, color) = if i == points.len() - 1 {
             // From the last point, draw a line to the cursor.
             let Some(mouse_point_grid) = mouse_point_grid else {
@@ -305,13 +1278,206 @@ pub fn editor_insert_building_system(
                 },
             )
         } else {
-            (grid_to_world(from_flat(points[i + 1], 0)), color_active)
+            (grid_to_world(from_flat(points[i + 1], 0)), color_outline)
         };
 
         gizmos.line(point_a, point_b, color);
     }
 }
 
+/// The height of the line of sight above the editing plane, in grid units.
+const SIGHTLINE_EYE_HEIGHT: f32 = 1.0;
+
+/// The first building wall that blocks a line of sight.
+struct SightlineBlocker {
+    /// Where the line of sight hits the wall, in flat grid coordinates.
+    hit: Vec2,
+    /// The wall that was hit, in flat grid coordinates.
+    wall: (Vec2, Vec2),
+    /// The vertical extent of the wall, in grid units.
+    wall_y: (f32, f32),
+}
+
+/// Finds the first wall blocking the straight line from `from` to `to` at height `eye_y`. The walls
+/// around courtyards block sightlines too.
+///
+/// Walls are ignored where they lie inside the interior of another room at that height, since the
+/// rooms are joined there.
+fn find_sightline_blocker(
+    editor_world: &EditorWorld,
+    (from, to): (Vec2, Vec2),
+    eye_y: f32,
+) -> Option<SightlineBlocker> {
+    let at_eye_height = |building: &Building| {
+        building.floor_y() as f32 <= eye_y && eye_y <= building.ceiling_y() as f32
+    };
+
+    let mut nearest: Option<SightlineBlocker> = None;
+    for (building_index, building) in editor_world.buildings().iter().enumerate() {
+        if !at_eye_height(building) {
+            continue;
+        }
+        let rings =
+            std::iter::once(building.points()).chain(building.holes().iter().map(Vec::as_slice));
+        let walls = rings.flat_map(|points| {
+            (0..points.len()).map(|i| {
+                (
+                    points[i].as_vec2(),
+                    points[(i + 1) % points.len()].as_vec2(),
+                )
+            })
+        });
+        for wall in walls {
+            let Some(hit) = segment_intersection((from, to), wall) else {
+                continue;
+            };
+
+            let joined = editor_world
+                .buildings()
+                .iter()
+                .enumerate()
+                .any(|(other_index, other)| {
+                    other_index != building_index && at_eye_height(other) && other.contains(hit)
+                });
+            if joined {
+                continue;
+            }
+
+            if nearest
+                .as_ref()
+                .is_none_or(|nearest| from.distance(hit) < from.distance(nearest.hit))
+            {
+                nearest = Some(SightlineBlocker {
+                    hit,
+                    wall,
+                    wall_y: (building.floor_y() as f32, building.ceiling_y() as f32),
+                });
+            }
+        }
+    }
+    nearest
+}
+
+/// The world geometry triangle selected with the `EditorTool::SelectFace` tool, which is
+/// highlighted while a face is selected.
+///
+/// It is recorded in each voxel undo step's `CommittedEditorState`, and restored by undo.
+#[derive(Resource, Default)]
+pub struct SelectedTriangle(pub Option<SelectedWorldFace>);
+
+/// Runs the `EditorTool::SelectFace` tool.
+///
+/// Click a face of the world geometry to select it, or click away from the geometry to clear the
+/// selection.
+fn select_face_system(
+    mut gizmos: Gizmos,
+    mouse_grid: MouseGrid,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    editor_world: Res<EditorWorld>,
+    rendered_csg: Res<RenderedCsg>,
+    mut selected_triangle: ResMut<SelectedTriangle>,
+) {
+    // The triangle may no longer exist once the geometry is rebuilt.
+    if rendered_csg.is_changed() {
+        selected_triangle.0 = None;
+    }
+
+    if matches!(editor_world.tool(), EditorTool::SelectFace)
+        && mouse_button.just_pressed(MouseButton::Left)
+    {
+        selected_triangle.0 = mouse_grid.pick_face(&rendered_csg.0);
+    }
+
+    let Some(face) = selected_triangle.0 else {
+        return;
+    };
+    let [a, b, c] = face.triangle;
+    let center = (a + b + c) / 3.;
+    gizmos.linestrip([a, b, c, a], Color::linear_rgb(1., 1., 0.));
+    gizmos.line(
+        center,
+        center + face.normal * VOXEL_SIZE / 4.,
+        Color::linear_rgb(1., 1., 0.),
+    );
+}
+
+/// Runs the `EditorTool::Sightline` tool.
+///
+/// Click two points to check whether the line of sight between them is blocked by a wall.
+fn sightline_system(
+    mut gizmos: Gizmos,
+    mouse_grid: MouseGrid,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    editor_world: Res<EditorWorld>,
+    mut points: Local<Vec<IVec2>>,
+) {
+    if !matches!(editor_world.tool(), EditorTool::Sightline) {
+        if !points.is_empty() {
+            points.clear();
+        }
+        return;
+    }
+
+    let editing_plane_y = 0;
+    let mouse_point_grid = mouse_grid
+        .pick_snapped_grid(editing_plane_y, &editor_world)
+        .map(to_flat);
+
+    if let Some(mouse_point_grid) = mouse_point_grid {
+        if mouse_button.just_pressed(MouseButton::Left) {
+            if points.len() >= 2 {
+                points.clear();
+            }
+            points.push(mouse_point_grid);
+        }
+    }
+
+    // Until the second point is placed, preview the line to the cursor.
+    let (from, to) = match (points.as_slice(), mouse_point_grid) {
+        (&[from, to], _) => (from, to),
+        (&[from], Some(to)) => (from, to),
+        _ => return,
+    };
+
+    let eye_y = editing_plane_y as f32 + SIGHTLINE_EYE_HEIGHT;
+    let to_world = |p: Vec2| Vec3::new(p.x, eye_y, p.y) * VOXEL_SIZE;
+
+    let color_clear = Color::linear_rgb(0., 1., 0.);
+    let color_blocked = Color::linear_rgb(1., 0., 0.);
+    let color_wall = Color::linear_rgb(1., 1., 0.);
+
+    let (from, to) = (from.as_vec2(), to.as_vec2());
+    for p in [from, to] {
+        gizmos.sphere(to_world(p), 10., color_clear);
+    }
+
+    match find_sightline_blocker(&editor_world, (from, to), eye_y) {
+        None => {
+            gizmos.line(to_world(from), to_world(to), color_clear);
+        }
+        Some(blocker) => {
+            gizmos.line(to_world(from), to_world(blocker.hit), color_clear);
+            gizmos.line(to_world(blocker.hit), to_world(to), color_blocked);
+            gizmos.sphere(to_world(blocker.hit), 16., color_blocked);
+
+            // Highlight the blocking wall as a rectangle.
+            let (a, b) = blocker.wall;
+            let (bottom, top) = blocker.wall_y;
+            gizmos.linestrip(
+                [
+                    Vec3::new(a.x, bottom, a.y),
+                    Vec3::new(a.x, top, a.y),
+                    Vec3::new(b.x, top, b.y),
+                    Vec3::new(b.x, bottom, b.y),
+                    Vec3::new(a.x, bottom, a.y),
+                ]
+                .map(|p| p * VOXEL_SIZE),
+                color_wall,
+            );
+        }
+    }
+}
+
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 enum XrayPreview {
     Segment(IVec3, IVec3),
@@ -321,58 +1487,231 @@ enum XrayPreview {
 fn preview_xray_buildings_system(
     mut commands: Commands,
     mut preview: Local<Previewer<XrayPreview>>,
+    mut preview_stats: ResMut<PreviewStats>,
     common: Res<Common>,
     editor_world: Res<EditorWorld>,
 ) {
+    preview_stats.record("x-ray buildings", &*preview);
     if !editor_world.is_changed() {
         return;
     }
 
     let mut preview = preview.collect_scope(&mut commands);
 
-    for building in editor_world.buildings() {
+    let keys = editor_world.buildings().iter().flat_map(|building| {
         let points = building.points();
-
-        for i in 0..points.len() {
-            let p = points[i];
-            let q = points[(i + 1) % points.len()];
-
-            let p = from_flat(p, 0);
-            let q = from_flat(q, 0);
-
-            let world_p = grid_to_world(p);
-            let world_q = grid_to_world(q);
-
-            preview.render(&XrayPreview::Segment(p, q), |commands| {
-                commands
-                    .spawn((
-                        Transform::from_translation((world_p + world_q) / 2.)
-                            .with_scale(Vec3::new(
-                                0.1 * VOXEL_SIZE,
-                                0.005,
-                                world_p.distance(world_q),
-                            ))
-                            .looking_at(world_p, Vec3::Y),
-                        Mesh3d(common.cube_mesh.clone()),
-                        MeshMaterial3d(common.xray_blue_material.clone()),
-                        RenderLayers::layer(7),
-                    ))
-                    .id()
-            });
-        }
-        for &p in points.iter() {
-            let p = from_flat(p, 0);
-            preview.render(&XrayPreview::Point(p), |commands| {
-                commands
-                    .spawn((
-                        Transform::from_translation(grid_to_world(p))
-                            .with_scale(Vec3::new(0.2, 0.01, 0.2) * VOXEL_SIZE),
-                        Mesh3d(common.cube_mesh.clone()),
-                        MeshMaterial3d(common.xray_blue_material.clone()),
-                        RenderLayers::layer(7),
+        let segments = (0..points.len()).map(|i| {
+            XrayPreview::Segment(
+                from_flat(points[i], 0),
+                from_flat(points[(i + 1) % points.len()], 0),
+            )
+        });
+        let corners = points.iter().map(|&p| XrayPreview::Point(from_flat(p, 0)));
+        segments.chain(corners)
+    });
+    preview.render_many(keys, |key| {
+        let transform = match *key {
+            XrayPreview::Segment(p, q) => {
+                let world_p = grid_to_world(p);
+                let world_q = grid_to_world(q);
+                Transform::from_translation((world_p + world_q) / 2.)
+                    .with_scale(Vec3::new(
+                        0.1 * VOXEL_SIZE,
+                        0.005,
+                        world_p.distance(world_q),
                     ))
-                    .id()
-            });
+                    .looking_at(world_p, Vec3::Y)
+            }
+            XrayPreview::Point(p) => Transform::from_translation(grid_to_world(p))
+                .with_scale(Vec3::new(0.2, 0.01, 0.2) * VOXEL_SIZE),
+        };
+        (
+            transform,
+            Mesh3d(common.cube_mesh.clone()),
+            MeshMaterial3d(common.xray_blue_material.clone()),
+            RenderLayers::layer(7),
+        )
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn switch_tool_follows_remapped_key() {
+        let mut app = App::new();
+        app.insert_resource(EditorWorld::new())
+            .insert_resource(Keybindings {
+                tool_create: KeyCode::KeyK,
+                ..default()
+            })
+            .init_resource::<ButtonInput<KeyCode>>()
+            .add_systems(Update, switch_tool_system);
+
+        // The default key no longer switches tools.
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Digit2);
+        app.update();
+        assert_eq!(
+            *app.world().resource::<EditorWorld>().tool(),
+            EditorTool::SelectBuilding
+        );
+
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::KeyK);
+        app.update();
+        assert_eq!(
+            *app.world().resource::<EditorWorld>().tool(),
+            EditorTool::CreateBuilding
+        );
+    }
+
+    #[test]
+    fn snapped_picks_are_the_nearest_multiple_of_the_snap_step() {
+        let snap_step = 4;
+        let pick = |x: f32, z: f32| {
+            let target = Vec3::new(x, 0., z) * VOXEL_SIZE;
+            let ray = Ray3d::new(target + Vec3::Y * 1000., Dir3::NEG_Y);
+            pick_snapped_grid_from_ray(ray, 0, MAX_PICK_DISTANCE, snap_step).unwrap()
+        };
+
+        for x in -9..=9 {
+            for z in -9..=9 {
+                let (x, z) = (x as f32 * 1.3, z as f32 * 0.7);
+                let snapped = pick(x, z);
+                assert_eq!(
+                    snapped.x.rem_euclid(snap_step),
+                    0,
+                    "({x}, {z}) snapped to {snapped}"
+                );
+                assert_eq!(
+                    snapped.z.rem_euclid(snap_step),
+                    0,
+                    "({x}, {z}) snapped to {snapped}"
+                );
+                assert!(
+                    (snapped.x as f32 - x).abs() <= 2.,
+                    "({x}, {z}) snapped to {snapped}"
+                );
+                assert!(
+                    (snapped.z as f32 - z).abs() <= 2.,
+                    "({x}, {z}) snapped to {snapped}"
+                );
+            }
         }
+
+        // Rounding to the grid first would take these to 2 or -2, and then to 4 or 0.
+        assert_eq!(pick(1.6, -1.6), IVec3::new(0, 0, 0));
+        assert_eq!(pick(-2.4, 2.4), IVec3::new(-4, 0, 4));
+        // Halfway points round up on both sides of zero.
+        assert_eq!(pick(-6., 2.), IVec3::new(-4, 0, 4));
+    }
+
+    #[test]
+    fn sightline_is_blocked_by_the_nearest_wall_only_at_wall_height() {
+        let mut editor_world = EditorWorld::new();
+        editor_world.insert_building(Building::square(IVec2::ZERO, 4));
+        let eye_y = SIGHTLINE_EYE_HEIGHT;
+
+        let through = (Vec2::new(-2., 2.), Vec2::new(6., 2.));
+        let blocker = find_sightline_blocker(&editor_world, through, eye_y).unwrap();
+        assert_eq!(blocker.hit, Vec2::new(0., 2.));
+        assert_eq!(blocker.wall, (Vec2::new(0., 4.), Vec2::new(0., 0.)));
+
+        let beside = (Vec2::new(-2., -2.), Vec2::new(6., -2.));
+        assert!(find_sightline_blocker(&editor_world, beside, eye_y).is_none());
+
+        let above = editor_world.buildings()[0].ceiling_y() as f32 + 1.;
+        assert!(find_sightline_blocker(&editor_world, through, above).is_none());
+    }
+
+    #[test]
+    fn sightline_passes_between_joined_rooms() {
+        let mut editor_world = EditorWorld::new();
+        editor_world.insert_building(Building::square(IVec2::ZERO, 4));
+        editor_world.insert_building(Building::square(IVec2::new(4, 0), 4));
+
+        let across = (Vec2::new(2., 2.), Vec2::new(6., 2.));
+        assert!(find_sightline_blocker(&editor_world, across, SIGHTLINE_EYE_HEIGHT).is_none());
+    }
+
+    #[test]
+    fn pick_plane_hits_misses_parallel_rays_and_respects_max_distance() {
+        let down = Ray3d::new(Vec3::new(300., 1000., -200.), Dir3::NEG_Y);
+        assert_eq!(
+            pick_plane_from_ray(down, 0, MAX_PICK_DISTANCE),
+            Some(Vec3::new(300., 0., -200.))
+        );
+        assert_eq!(
+            pick_grid_from_ray(down, 0, MAX_PICK_DISTANCE),
+            Some(world_to_grid(Vec3::new(300., 0., -200.)))
+        );
+
+        let parallel = Ray3d::new(Vec3::new(0., 1000., 0.), Dir3::X);
+        assert_eq!(pick_plane_from_ray(parallel, 0, MAX_PICK_DISTANCE), None);
+
+        // The plane is 1000 units away, which is just too far.
+        assert_eq!(pick_plane_from_ray(down, 0, 999.), None);
+        assert!(pick_plane_from_ray(down, 0, 1000.).is_some());
+    }
+
+    #[test]
+    fn sightline_is_blocked_by_courtyard_walls() {
+        let mut room = Building::new(
+            0,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(8, 0),
+                IVec2::new(8, 8),
+                IVec2::new(0, 8),
+            ],
+        );
+        assert!(room.add_hole(vec![
+            IVec2::new(3, 3),
+            IVec2::new(3, 5),
+            IVec2::new(5, 5),
+            IVec2::new(5, 3),
+        ]));
+        let mut editor_world = EditorWorld::new();
+        editor_world.insert_building(room);
+
+        let across_courtyard = (Vec2::new(1., 4.), Vec2::new(7., 4.));
+        let blocker =
+            find_sightline_blocker(&editor_world, across_courtyard, SIGHTLINE_EYE_HEIGHT).unwrap();
+        assert_eq!(blocker.hit, Vec2::new(3., 4.));
+
+        let beside_courtyard = (Vec2::new(1., 1.), Vec2::new(7., 1.));
+        assert!(
+            find_sightline_blocker(&editor_world, beside_courtyard, SIGHTLINE_EYE_HEIGHT).is_none()
+        );
+    }
+
+    #[test]
+    fn pick_face_selects_the_nearest_triangle_with_its_surface() {
+        use crate::geometry_utils::BevyToNalgebra;
+
+        let floor = |y: f32, outside: bool| {
+            csgrs::polygon::Polygon::new(
+                [Vec3::ZERO, Vec3::Z, Vec3::X]
+                    .map(|corner| {
+                        let corner = corner * 512. + Vec3::Y * y;
+                        csgrs::vertex::Vertex::new(corner.to_point(), Vec3::Y.to_vector())
+                    })
+                    .to_vec(),
+                Some(crate::SurfaceDetail { outside }),
+            )
+        };
+        let csg = CSG::from_polygons(&[floor(0., false), floor(256., true)]);
+
+        let down = Ray3d::new(Vec3::new(100., 1000., 100.), Dir3::NEG_Y);
+        let face = pick_face_from_ray(down, &csg, MAX_PICK_DISTANCE).unwrap();
+        assert_eq!(face.triangle[0], Vec3::new(0., 256., 0.));
+        assert!(face.detail.outside);
+
+        let beside = Ray3d::new(Vec3::new(-100., 1000., -100.), Dir3::NEG_Y);
+        assert!(pick_face_from_ray(beside, &csg, MAX_PICK_DISTANCE).is_none());
     }
 }
@@ -1,14 +1,136 @@
-use bevy::prelude::*;
+use bevy::{platform::collections::HashSet, prelude::*};
+use serde::{Deserialize, Serialize};
 
 use crate::{
     building::{Building, BuildingValidity},
+    geometry_utils::point_closest_to_segment,
     voxels::VOXEL_SIZE,
 };
 
+/// The largest allowed grid snapping step.
+pub const MAX_SNAP_STEP: i32 = 16;
+
+/// The point limits cycled through by `Keybindings::cycle_vertex_limit`, starting with unlimited.
+pub const VERTEX_LIMITS: [Option<usize>; 5] = [None, Some(8), Some(16), Some(32), Some(64)];
+
 #[derive(Resource)]
 pub struct EditorWorld {
     buildings: Vec<Building>,
+    /// The bounding box of each building, as returned by `Building::bounds`.
+    bounds: Vec<(IVec2, IVec2)>,
+    /// Whether each building is valid, as returned by `Building::is_valid`, so that it is only
+    /// fully re-checked when its outline changes.
+    valid: Vec<bool>,
     editor_tool: EditorTool,
+    /// The maximum number of points a new building may have, if limited.
+    max_vertices: Option<usize>,
+    /// Picked grid points are rounded to a multiple of this step.
+    snap_step: i32,
+    /// The building most recently copied with `copy_building`.
+    clipboard: Option<Building>,
+    /// A message for the user about the last refused edit, cleared by the next successful edit.
+    status_message: Option<String>,
+
+    /// Groups of edits to the buildings, each of which is undone as a unit.
+    undo_stack: Vec<Vec<BuildingEdit>>,
+    /// Groups of edits which have been undone, and can be redone.
+    redo_stack: Vec<Vec<BuildingEdit>>,
+    /// Whether new edits are added to the last group in `undo_stack`.
+    edit_group_open: bool,
+}
+
+/// A single change to the buildings in an `EditorWorld`, with enough information to undo or redo
+/// it.
+#[derive(Clone, Debug)]
+enum BuildingEdit {
+    /// A building was inserted at `index`.
+    Insert { index: usize, building: Building },
+    /// The building at `index` was removed.
+    Remove { index: usize, building: Building },
+    /// A point of a building was moved.
+    SetPoint {
+        building: usize,
+        point: usize,
+        from: IVec2,
+        to: IVec2,
+    },
+    /// A point was inserted into a building's outline.
+    InsertPoint {
+        building: usize,
+        point: usize,
+        p: IVec2,
+    },
+    /// A point of a building was removed.
+    RemovePoint {
+        building: usize,
+        point: usize,
+        p: IVec2,
+    },
+    /// A building was translated by `delta`.
+    Translate { building: usize, delta: IVec2 },
+    /// A building's floor was moved to a new height.
+    SetFloor { building: usize, from: i32, to: i32 },
+    /// A building's height was changed.
+    SetHeight { building: usize, from: i32, to: i32 },
+    /// A building was replaced by a reshaped copy, e.g. after rotating it.
+    Replace {
+        building: usize,
+        from: Building,
+        to: Building,
+    },
+}
+
+/// What is currently selected in the editor.
+#[derive(Resource, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Selection {
+    /// Nothing is selected.
+    #[default]
+    Nothing,
+    /// A single point of a building's outline.
+    Vertex { building: usize, point: usize },
+    /// A whole building.
+    Building(usize),
+    /// Several whole buildings.
+    Buildings(HashSet<usize>),
+    /// A set of voxels.
+    Voxels(HashSet<IVec3>),
+}
+
+impl Selection {
+    /// Returns the indexes of all buildings that are selected as a whole.
+    pub fn buildings(&self) -> Vec<usize> {
+        match self {
+            Selection::Building(building) => vec![*building],
+            Selection::Buildings(buildings) => {
+                let mut buildings: Vec<usize> = buildings.iter().copied().collect();
+                buildings.sort();
+                buildings
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// An edge of a building's bounding box, used for aligning buildings.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum AlignEdge {
+    /// The minimum X edge.
+    Left,
+    /// The maximum X edge.
+    Right,
+    /// The minimum Z edge.
+    Top,
+    /// The maximum Z edge.
+    Bottom,
+}
+
+/// An axis along which buildings can be evenly distributed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DistributeAxis {
+    /// Distribute along the X axis.
+    Horizontal,
+    /// Distribute along the Z axis.
+    Vertical,
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -17,6 +139,89 @@ pub enum EditorTool {
     CreateBuilding,
     /// Select a building
     SelectBuilding,
+    /// Check the line of sight between two points
+    Sightline,
+    /// Delete buildings by clicking on them
+    DeleteBuilding,
+    /// Select a face of the world geometry
+    SelectFace,
+    /// Edit the voxel grid
+    EditVoxels,
+}
+
+impl EditorTool {
+    /// Whether undo and redo apply to the voxel grid, rather than to buildings, while this tool is
+    /// active.
+    pub fn undoes_voxels(&self) -> bool {
+        matches!(self, EditorTool::EditVoxels | EditorTool::SelectFace)
+    }
+}
+
+/// The saved form of an `EditorWorld`, as written by `EditorWorld::to_json`.
+#[derive(Serialize, Deserialize)]
+struct SavedWorld {
+    buildings: Vec<Building>,
+}
+
+/// An error from loading a world with `EditorWorld::from_json`.
+#[derive(Debug)]
+pub enum WorldLoadError {
+    /// The text is not a saved world.
+    Json(serde_json::Error),
+    /// The buildings at these indexes are not valid.
+    InvalidBuildings(Vec<usize>),
+}
+
+impl std::fmt::Display for WorldLoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldLoadError::Json(err) => write!(f, "failed to parse saved world: {err}"),
+            WorldLoadError::InvalidBuildings(indexes) => {
+                let indexes: Vec<String> = indexes.iter().map(|index| index.to_string()).collect();
+                write!(f, "invalid buildings at indexes {}", indexes.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorldLoadError {}
+
+impl BuildingEdit {
+    /// Combines a later edit into this one, if the two can be undone as a single edit.
+    /// Repeatedly moving the same point or building (e.g. while dragging) only needs to remember
+    /// where it started.
+    fn merge(&mut self, later: &BuildingEdit) -> bool {
+        match (self, later) {
+            (
+                BuildingEdit::SetPoint {
+                    building,
+                    point,
+                    to,
+                    ..
+                },
+                &BuildingEdit::SetPoint {
+                    building: later_building,
+                    point: later_point,
+                    to: later_to,
+                    ..
+                },
+            ) if *building == later_building && *point == later_point => {
+                *to = later_to;
+                true
+            }
+            (
+                BuildingEdit::Translate { building, delta },
+                &BuildingEdit::Translate {
+                    building: later_building,
+                    delta: later_delta,
+                },
+            ) if *building == later_building => {
+                *delta += later_delta;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 impl Default for EditorWorld {
@@ -30,10 +235,51 @@ impl EditorWorld {
     pub fn new() -> Self {
         Self {
             buildings: Vec::new(),
+            bounds: Vec::new(),
+            valid: Vec::new(),
             editor_tool: EditorTool::SelectBuilding,
+            max_vertices: None,
+            snap_step: 1,
+            clipboard: None,
+            status_message: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            edit_group_open: false,
         }
     }
 
+    /// Serializes the buildings in the editor to JSON.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&SavedWorld {
+            buildings: self.buildings.clone(),
+        })
+        .expect("buildings can always be serialized")
+    }
+
+    /// Loads buildings saved by `to_json` into a new editor.
+    ///
+    /// Every building is checked with `Building::is_valid`, and the load fails if any are invalid.
+    pub fn from_json(json: &str) -> Result<EditorWorld, WorldLoadError> {
+        let saved: SavedWorld = serde_json::from_str(json).map_err(WorldLoadError::Json)?;
+
+        let invalid: Vec<usize> = saved
+            .buildings
+            .iter()
+            .enumerate()
+            .filter(|(_, building)| !building.is_valid(BuildingValidity::default()))
+            .map(|(index, _)| index)
+            .collect();
+        if !invalid.is_empty() {
+            return Err(WorldLoadError::InvalidBuildings(invalid));
+        }
+
+        let mut world = EditorWorld::new();
+        for building in saved.buildings {
+            world.insert_building_at(world.buildings.len(), building);
+        }
+        Ok(world)
+    }
+
     /// Get the current tool.
     pub fn tool(&self) -> &EditorTool {
         &self.editor_tool
@@ -44,21 +290,256 @@ impl EditorWorld {
         self.editor_tool = tool;
     }
 
+    /// Get the maximum number of points a building may have, if limited.
+    pub fn max_vertices(&self) -> Option<usize> {
+        self.max_vertices
+    }
+
+    /// Limits the number of points a building may have. `None` means unlimited.
+    ///
+    /// Existing buildings are left alone, but no edit may add points beyond the limit.
+    pub fn set_max_vertices(&mut self, max_vertices: Option<usize>) {
+        self.max_vertices = max_vertices;
+    }
+
+    /// Returns whether a building with `points` points is within the point limit, setting the
+    /// status message if it is not.
+    fn check_vertex_limit(&mut self, points: usize) -> bool {
+        match self.max_vertices {
+            Some(max_vertices) if points > max_vertices => {
+                self.status_message =
+                    Some(format!("Buildings are limited to {max_vertices} points"));
+                false
+            }
+            _ => true,
+        }
+    }
+
+    /// Get the message for the user about the last refused edit, if any.
+    pub fn status_message(&self) -> Option<&str> {
+        self.status_message.as_deref()
+    }
+
+    /// Get the grid snapping step.
+    pub fn snap_step(&self) -> i32 {
+        self.snap_step
+    }
+
+    /// Sets the grid snapping step, rounded down to a power of two between `1` and `MAX_SNAP_STEP`.
+    pub fn set_snap_step(&mut self, snap_step: i32) {
+        let snap_step = snap_step.clamp(1, MAX_SNAP_STEP);
+        self.snap_step = 1 << snap_step.ilog2();
+    }
+
     /// Get the current buildings in the editor.
     pub fn buildings(&self) -> &[Building] {
         &self.buildings
     }
 
+    /// Returns the index of the topmost building whose outline contains `p`, or which has `p` on
+    /// its boundary.
+    ///
+    /// Buildings whose bounding box doesn't contain `p` are skipped without checking their
+    /// outlines.
+    pub fn building_at(&self, p: IVec2) -> Option<usize> {
+        let on_boundary = |building: &Building| {
+            let points = building.points();
+            (0..points.len()).any(|i| {
+                let edge = (
+                    points[i].as_vec2(),
+                    points[(i + 1) % points.len()].as_vec2(),
+                );
+                point_closest_to_segment(p.as_vec2(), edge).distance(p.as_vec2()) < 1e-3
+            })
+        };
+
+        self.buildings_near(p).find(|&index| {
+            let building = &self.buildings[index];
+            building.contains(p.as_vec2()) || on_boundary(building)
+        })
+    }
+
+    /// Returns the topmost building with a point at `p`, as `(building, point)` indexes.
+    pub fn building_point_at(&self, p: IVec2) -> Option<(usize, usize)> {
+        self.buildings_near(p).find_map(|index| {
+            let point = self.buildings[index]
+                .points()
+                .iter()
+                .position(|&q| q == p)?;
+            Some((index, point))
+        })
+    }
+
+    /// Returns the building vertex nearest to `p`, other than the vertex `exclude` (given as
+    /// `(building, point)` indexes).
+    pub fn nearest_vertex(&self, p: IVec2, exclude: (usize, usize)) -> Option<IVec2> {
+        self.buildings
+            .iter()
+            .enumerate()
+            .flat_map(|(building_index, building)| {
+                building
+                    .points()
+                    .iter()
+                    .enumerate()
+                    .filter(move |&(point_index, _)| (building_index, point_index) != exclude)
+                    .map(|(_, &vertex)| vertex)
+            })
+            .min_by_key(|vertex| (*vertex - p).length_squared())
+    }
+
+    /// Returns the grid point lying exactly on a building edge (of an outline or a hole) that is
+    /// nearest to `p`, considering only edges within `max_distance` of `p`.
+    pub fn nearest_edge_point(&self, p: Vec2, max_distance: f32) -> Option<IVec2> {
+        fn gcd(a: i32, b: i32) -> i32 {
+            if b == 0 { a } else { gcd(b, a % b) }
+        }
+
+        self.buildings
+            .iter()
+            .flat_map(|building| {
+                std::iter::once(building.points()).chain(building.holes().iter().map(Vec::as_slice))
+            })
+            .flat_map(|ring| (0..ring.len()).map(move |i| (ring[i], ring[(i + 1) % ring.len()])))
+            .filter(|&(a, b)| {
+                let on_edge = point_closest_to_segment(p, (a.as_vec2(), b.as_vec2()));
+                on_edge.distance(p) <= max_distance
+            })
+            .map(|(a, b)| {
+                // The grid points on the edge are evenly spaced `step` apart.
+                let steps = gcd((b - a).x.abs(), (b - a).y.abs()).max(1);
+                let step = (b - a) / steps;
+                let t = (p - a.as_vec2()).dot(step.as_vec2()) / step.as_vec2().length_squared();
+                a + step * (t.round() as i32).clamp(0, steps)
+            })
+            .min_by(|a, b| a.as_vec2().distance(p).total_cmp(&b.as_vec2().distance(p)))
+    }
+
+    /// Returns the indexes of the buildings whose bounding box contains `p`, topmost first.
+    fn buildings_near(&self, p: IVec2) -> impl Iterator<Item = usize> + '_ {
+        self.bounds
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(move |(_, (min, max))| p.cmpge(*min).all() && p.cmple(*max).all())
+            .map(|(index, _)| index)
+    }
+
     /// Add a new building to the editor.
-    pub fn insert_building(&mut self, building: Building) {
-        self.buildings.push(building);
+    /// Returns `false` and leaves the editor unchanged if the building has too many points.
+    pub fn insert_building(&mut self, building: Building) -> bool {
+        if !self.check_vertex_limit(building.points().len()) {
+            return false;
+        }
+
+        self.record(BuildingEdit::Insert {
+            index: self.buildings.len(),
+            building: building.clone(),
+        });
+        self.insert_building_at(self.buildings.len(), building);
+        true
+    }
+
+    /// Removes a building from the editor, returning it.
+    /// Buildings after it shift down by one index.
+    pub fn remove_building(&mut self, building_index: usize) -> Building {
+        let building = self.remove_building_at(building_index);
+        self.record(BuildingEdit::Remove {
+            index: building_index,
+            building: building.clone(),
+        });
+        building
+    }
+
+    /// Inserts a point into a building's outline, on the edge starting at `edge_index`.
+    /// Returns `false` and leaves the building unchanged if the result would be invalid, or would
+    /// have too many points.
+    pub fn insert_building_point(
+        &mut self,
+        building_index: usize,
+        edge_index: usize,
+        p: IVec2,
+    ) -> bool {
+        let Some(points) = self
+            .buildings
+            .get(building_index)
+            .map(|building| building.points().len())
+        else {
+            return false;
+        };
+        if edge_index >= points || !self.check_vertex_limit(points + 1) {
+            return false;
+        }
+        if !self.buildings[building_index].insert_point(edge_index, p) {
+            return false;
+        }
+
+        // `Building::insert_point` only succeeds if the result is valid.
+        self.update_bounds(building_index);
+        self.valid[building_index] = true;
+        self.record(BuildingEdit::InsertPoint {
+            building: building_index,
+            point: edge_index + 1,
+            p,
+        });
+        true
+    }
+
+    /// Removes a point from a building's outline.
+    /// Returns `false` and leaves the building unchanged if the result would be invalid, e.g.
+    /// because it would have fewer than 3 points.
+    pub fn remove_building_point(&mut self, building_index: usize, point_index: usize) -> bool {
+        let Some(building) = self.buildings.get(building_index) else {
+            return false;
+        };
+        if building.points().len() <= 3 || point_index >= building.points().len() {
+            return false;
+        }
+
+        let mut new_building = building.clone();
+        let p = new_building.outline.remove(point_index);
+        if !new_building.is_valid(BuildingValidity::default()) {
+            return false;
+        }
+
+        self.buildings[building_index] = new_building;
+        self.update_bounds(building_index);
+        self.valid[building_index] = true;
+        self.record(BuildingEdit::RemovePoint {
+            building: building_index,
+            point: point_index,
+            p,
+        });
+        true
     }
 
     /// Changes the position of a point in a building.
-    /// Panics if the resulting building is invalid.
-    pub fn set_building_point(&mut self, building: usize, point: usize, p: IVec2) {
+    /// Returns `false` and leaves the building unchanged if it is valid but would not be after the
+    /// change. A building which is already invalid may be changed freely, so that it can be fixed.
+    pub fn set_building_point(&mut self, building: usize, point: usize, p: IVec2) -> bool {
+        if self.is_building_valid(building) {
+            // A valid building only needs the edges and corners around the moved point re-checked,
+            // which keeps dragging points of large buildings fast.
+            if !self.buildings[building].is_valid_after_point_change(point, p) {
+                return false;
+            }
+        }
+        let from = self.buildings[building].outline[point];
         self.buildings[building].outline[point] = p;
-        assert!(self.buildings[building].is_valid(BuildingValidity::default()));
+        if self.valid[building] {
+            self.update_bounds(building);
+        } else {
+            self.outline_changed(building);
+        }
+
+        if from != p {
+            self.record(BuildingEdit::SetPoint {
+                building,
+                point,
+                from,
+                to: p,
+            });
+        }
+        true
     }
 
     /// Translate an existing building by the specified amount.
@@ -67,10 +548,412 @@ impl EditorWorld {
             return;
         }
 
-        for p in self.buildings[building_index].points_mut().iter_mut() {
-            // TODO: Check for overflow
-            *p += delta;
+        let edit = BuildingEdit::Translate {
+            building: building_index,
+            delta,
+        };
+        self.apply_edit(&edit, true);
+        self.record(edit);
+    }
+
+    /// Copies a building to the clipboard. Returns `false` if there is no such building.
+    pub fn copy_building(&mut self, building_index: usize) -> bool {
+        let Some(building) = self.buildings.get(building_index) else {
+            return false;
+        };
+        self.clipboard = Some(building.clone());
+        true
+    }
+
+    /// Inserts a copy of the clipboard building, translated by `offset`.
+    /// Returns the index of the new building, or `None` if the clipboard is empty or the copy
+    /// would be invalid or have too many points.
+    pub fn paste_building(&mut self, offset: IVec2) -> Option<usize> {
+        let mut building = self.clipboard.clone()?;
+        building.translate(offset);
+        if !building.is_valid(BuildingValidity::default()) || !self.insert_building(building) {
+            return None;
+        }
+        Some(self.buildings.len() - 1)
+    }
+
+    /// Translates a building by the specified amount, unless a point would overflow the grid.
+    /// Returns whether the building was moved.
+    pub fn try_translate_building(&mut self, building_index: usize, delta: IVec2) -> bool {
+        let Some(building) = self.buildings.get(building_index) else {
+            return false;
+        };
+        let fits = building
+            .points()
+            .iter()
+            .chain(building.holes().iter().flatten())
+            .all(|p| p.x.checked_add(delta.x).is_some() && p.y.checked_add(delta.y).is_some());
+        if !fits {
+            return false;
+        }
+
+        self.translate_building(building_index, delta);
+        true
+    }
+
+    /// Moves a building's floor to the grid height `floor_y`.
+    ///
+    /// Building validity only depends on the outline, so buildings on different floors may overlap.
+    pub fn set_building_floor(&mut self, building_index: usize, floor_y: i32) {
+        let Some(building) = self.buildings.get(building_index) else {
+            return;
+        };
+        if building.floor_y == floor_y {
+            return;
+        }
+
+        let edit = BuildingEdit::SetFloor {
+            building: building_index,
+            from: building.floor_y,
+            to: floor_y,
+        };
+        self.apply_edit(&edit, true);
+        self.record(edit);
+    }
+
+    /// Changes a building's height, in grid units. Heights below 1 are ignored.
+    pub fn set_building_height(&mut self, building_index: usize, height: i32) {
+        let Some(building) = self.buildings.get(building_index) else {
+            return;
+        };
+        if building.height == height || height < 1 {
+            return;
+        }
+
+        let edit = BuildingEdit::SetHeight {
+            building: building_index,
+            from: building.height,
+            to: height,
+        };
+        self.apply_edit(&edit, true);
+        self.record(edit);
+    }
+
+    /// Rotates a building a quarter turn clockwise around its centroid.
+    /// Returns `false` and leaves the building unchanged if the rotated building would be invalid.
+    pub fn rotate_building(&mut self, building_index: usize) -> bool {
+        let Some(building) = self.buildings.get(building_index) else {
+            return false;
+        };
+        let mut rotated = building.clone();
+        if !rotated.rotate_90_cw() {
+            return false;
+        }
+
+        let edit = BuildingEdit::Replace {
+            building: building_index,
+            from: building.clone(),
+            to: rotated,
+        };
+        self.apply_edit(&edit, true);
+        self.record(edit);
+        true
+    }
+
+    /// Starts a group of edits, which will be undone and redone as a single step.
+    /// The group lasts until `end_edit_group` is called.
+    pub fn begin_edit_group(&mut self) {
+        self.end_edit_group();
+        self.undo_stack.push(Vec::new());
+        self.edit_group_open = true;
+    }
+
+    /// Ends the group of edits started by `begin_edit_group`.
+    pub fn end_edit_group(&mut self) {
+        if self.edit_group_open && self.undo_stack.last().is_some_and(|group| group.is_empty()) {
+            self.undo_stack.pop();
+        }
+        self.edit_group_open = false;
+    }
+
+    /// Records an edit that has just been made, so that it can be undone.
+    /// Any undone edits can no longer be redone.
+    fn record(&mut self, edit: BuildingEdit) {
+        self.redo_stack.clear();
+        self.status_message = None;
+
+        let group = match self.undo_stack.last_mut() {
+            Some(group) if self.edit_group_open => group,
+            _ => {
+                self.undo_stack.push(vec![edit]);
+                return;
+            }
+        };
+
+        if group.last_mut().is_some_and(|last| last.merge(&edit)) {
+            // A point that was moved back to where it started has no edit left to undo.
+            if matches!(group.last(), Some(BuildingEdit::SetPoint { from, to, .. }) if from == to) {
+                group.pop();
+            }
+            return;
         }
+        group.push(edit);
+    }
+
+    /// Returns whether the building at `index` is valid, as checked when its outline last changed.
+    pub fn is_building_valid(&self, index: usize) -> bool {
+        self.valid[index]
+    }
+
+    /// Inserts a building at `index`, keeping its bounding box and validity in sync.
+    fn insert_building_at(&mut self, index: usize, building: Building) {
+        self.bounds.insert(index, building.bounds());
+        self.valid
+            .insert(index, building.is_valid(BuildingValidity::default()));
+        self.buildings.insert(index, building);
+    }
+
+    /// Removes the building at `index`, keeping the bounding boxes and validity in sync.
+    fn remove_building_at(&mut self, index: usize) -> Building {
+        self.bounds.remove(index);
+        self.valid.remove(index);
+        self.buildings.remove(index)
+    }
+
+    /// Recomputes the bounding box of a building after it moves or its outline changes.
+    fn update_bounds(&mut self, index: usize) {
+        self.bounds[index] = self.buildings[index].bounds();
+    }
+
+    /// Recomputes the bounding box and validity of a building after its outline changes.
+    fn outline_changed(&mut self, index: usize) {
+        self.update_bounds(index);
+        self.valid[index] = self.buildings[index].is_valid(BuildingValidity::default());
+    }
+
+    /// Applies an edit (if `forward` is set) or its inverse, without recording it.
+    fn apply_edit(&mut self, edit: &BuildingEdit, forward: bool) {
+        match edit {
+            BuildingEdit::Insert { index, building } => {
+                if forward {
+                    self.insert_building_at(*index, building.clone());
+                } else {
+                    self.remove_building_at(*index);
+                }
+            }
+            BuildingEdit::Remove { index, building } => {
+                if forward {
+                    self.remove_building_at(*index);
+                } else {
+                    self.insert_building_at(*index, building.clone());
+                }
+            }
+            &BuildingEdit::SetPoint {
+                building,
+                point,
+                from,
+                to,
+            } => {
+                self.buildings[building].outline[point] = if forward { to } else { from };
+                self.outline_changed(building);
+            }
+            &BuildingEdit::InsertPoint { building, point, p } => {
+                if forward {
+                    self.buildings[building].outline.insert(point, p);
+                } else {
+                    self.buildings[building].outline.remove(point);
+                }
+                self.outline_changed(building);
+            }
+            &BuildingEdit::RemovePoint { building, point, p } => {
+                if forward {
+                    self.buildings[building].outline.remove(point);
+                } else {
+                    self.buildings[building].outline.insert(point, p);
+                }
+                self.outline_changed(building);
+            }
+            &BuildingEdit::Translate { building, delta } => {
+                let delta = if forward { delta } else { -delta };
+                self.buildings[building].translate(delta);
+                // Moving a building doesn't change its shape, so it stays as valid as it was.
+                self.update_bounds(building);
+            }
+            &BuildingEdit::SetFloor { building, from, to } => {
+                self.buildings[building].floor_y = if forward { to } else { from };
+            }
+            &BuildingEdit::SetHeight { building, from, to } => {
+                self.buildings[building].height = if forward { to } else { from };
+            }
+            BuildingEdit::Replace { building, from, to } => {
+                self.buildings[*building] = if forward { to.clone() } else { from.clone() };
+                self.outline_changed(*building);
+            }
+        }
+    }
+
+    /// Undoes the most recent group of edits. Returns `false` if there was nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.end_edit_group();
+        let Some(group) = self.undo_stack.pop() else {
+            return false;
+        };
+        for edit in group.iter().rev() {
+            self.apply_edit(edit, false);
+        }
+        self.redo_stack.push(group);
+        true
+    }
+
+    /// Redoes the most recently undone group of edits. Returns `false` if there was nothing to
+    /// redo.
+    pub fn redo(&mut self) -> bool {
+        self.end_edit_group();
+        let Some(group) = self.redo_stack.pop() else {
+            return false;
+        };
+        for edit in group.iter() {
+            self.apply_edit(edit, true);
+        }
+        self.undo_stack.push(group);
+        true
+    }
+
+    /// Aligns the bounding boxes of the given buildings so that the chosen edge is flush with
+    /// the outermost such edge among them.
+    ///
+    /// Buildings whose bounding box would overlap another building on the same floor are left
+    /// in place. Returns the number of buildings that were moved.
+    pub fn align_buildings(&mut self, building_indexes: &[usize], edge: AlignEdge) -> usize {
+        let building_indexes: Vec<usize> = building_indexes
+            .iter()
+            .copied()
+            .filter(|&index| index < self.buildings.len())
+            .collect();
+        if building_indexes.len() < 2 {
+            return 0;
+        }
+
+        let edge_of = |building: &Building| -> i32 {
+            let (min, max) = building.bounds();
+            match edge {
+                AlignEdge::Left => min.x,
+                AlignEdge::Right => max.x,
+                AlignEdge::Top => min.y,
+                AlignEdge::Bottom => max.y,
+            }
+        };
+
+        let edges = building_indexes
+            .iter()
+            .map(|&index| edge_of(&self.buildings[index]));
+        let target = match edge {
+            AlignEdge::Left | AlignEdge::Top => edges.min().unwrap(),
+            AlignEdge::Right | AlignEdge::Bottom => edges.max().unwrap(),
+        };
+
+        self.begin_edit_group();
+        let mut moved = 0;
+        for &index in building_indexes.iter() {
+            let offset = target - edge_of(&self.buildings[index]);
+            let delta = match edge {
+                AlignEdge::Left | AlignEdge::Right => IVec2::new(offset, 0),
+                AlignEdge::Top | AlignEdge::Bottom => IVec2::new(0, offset),
+            };
+            if delta == IVec2::ZERO || self.would_overlap_after_translate(index, delta) {
+                continue;
+            }
+            self.translate_building(index, delta);
+            moved += 1;
+        }
+        self.end_edit_group();
+        moved
+    }
+
+    /// Translates the given buildings along an axis so that the gaps between their bounding
+    /// boxes are equal. The two outermost buildings stay in place.
+    ///
+    /// Requires at least 3 buildings. Buildings whose bounding box would overlap another building
+    /// on the same floor are left in place. Returns the number of buildings that were moved.
+    pub fn distribute_buildings(
+        &mut self,
+        building_indexes: &[usize],
+        axis: DistributeAxis,
+    ) -> usize {
+        let mut building_indexes: Vec<usize> = building_indexes
+            .iter()
+            .copied()
+            .filter(|&index| index < self.buildings.len())
+            .collect();
+        building_indexes.sort();
+        building_indexes.dedup();
+        if building_indexes.len() < 3 {
+            return 0;
+        }
+
+        // The (min, max) extent of a building along the axis.
+        let extent_of = |building: &Building| -> (i32, i32) {
+            let (min, max) = building.bounds();
+            match axis {
+                DistributeAxis::Horizontal => (min.x, max.x),
+                DistributeAxis::Vertical => (min.y, max.y),
+            }
+        };
+
+        building_indexes.sort_by_key(|&index| extent_of(&self.buildings[index]));
+
+        let first = extent_of(&self.buildings[building_indexes[0]]);
+        let last = extent_of(&self.buildings[*building_indexes.last().unwrap()]);
+        let middle = &building_indexes[1..building_indexes.len() - 1];
+
+        let middle_width: i32 = middle
+            .iter()
+            .map(|&index| {
+                let (min, max) = extent_of(&self.buildings[index]);
+                max - min
+            })
+            .sum();
+        let free_space = last.0 - first.1 - middle_width;
+        let gap_count = middle.len() as i32 + 1;
+
+        self.begin_edit_group();
+        let mut moved = 0;
+        let mut width_before = 0;
+        for (i, &index) in middle.iter().enumerate() {
+            let (min, max) = extent_of(&self.buildings[index]);
+            let target_min = first.1 + free_space * (i as i32 + 1) / gap_count + width_before;
+            width_before += max - min;
+
+            let offset = target_min - min;
+            let delta = match axis {
+                DistributeAxis::Horizontal => IVec2::new(offset, 0),
+                DistributeAxis::Vertical => IVec2::new(0, offset),
+            };
+            if delta == IVec2::ZERO || self.would_overlap_after_translate(index, delta) {
+                continue;
+            }
+            self.translate_building(index, delta);
+            moved += 1;
+        }
+        self.end_edit_group();
+        moved
+    }
+
+    /// Returns whether translating the building by `delta` would make its bounding box overlap
+    /// the bounding box of another building on the same floor.
+    fn would_overlap_after_translate(&self, building_index: usize, delta: IVec2) -> bool {
+        let building = &self.buildings[building_index];
+        let (min, max) = building.bounds();
+        let (min, max) = (min + delta, max + delta);
+
+        self.buildings
+            .iter()
+            .enumerate()
+            .any(|(other_index, other)| {
+                if other_index == building_index || other.floor_y() != building.floor_y() {
+                    return false;
+                }
+                let (other_min, other_max) = other.bounds();
+                min.x < other_max.x
+                    && other_min.x < max.x
+                    && min.y < other_max.y
+                    && other_min.y < max.y
+            })
     }
 }
 
@@ -86,3 +969,192 @@ pub fn from_flat(p: IVec2, y: i32) -> IVec3 {
 pub fn to_flat(p: IVec3) -> IVec2 {
     p.xz()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_limit_refuses_every_insert_path() {
+        let mut world = EditorWorld::new();
+        world.set_max_vertices(Some(4));
+
+        assert!(world.insert_building(Building::square(IVec2::ZERO, 4)));
+        assert!(!world.insert_building_point(0, 0, IVec2::new(2, -1)));
+        assert_eq!(world.buildings()[0].points().len(), 4);
+        assert_eq!(
+            world.status_message(),
+            Some("Buildings are limited to 4 points")
+        );
+
+        world.set_max_vertices(Some(3));
+        assert!(!world.insert_building(Building::square(IVec2::new(10, 0), 4)));
+        assert!(world.copy_building(0));
+        assert_eq!(world.paste_building(IVec2::new(10, 0)), None);
+        assert_eq!(world.buildings().len(), 1);
+
+        world.set_max_vertices(None);
+        assert!(world.insert_building_point(0, 0, IVec2::new(2, -1)));
+        assert_eq!(world.buildings()[0].points().len(), 5);
+        assert_eq!(world.status_message(), None);
+    }
+
+    #[test]
+    fn validity_is_cached_and_updated_on_edit() {
+        let mut world = EditorWorld::new();
+        world.insert_building(Building::square(IVec2::ZERO, 4));
+        assert!(world.is_building_valid(0));
+
+        assert!(world.set_building_point(0, 2, IVec2::new(5, 5)));
+        assert!(world.is_building_valid(0));
+        assert!(world.buildings()[0].is_valid(BuildingValidity::default()));
+
+        assert!(world.insert_building_point(0, 0, IVec2::new(2, -1)));
+        assert!(world.is_building_valid(0));
+        assert!(world.undo());
+        assert!(world.undo());
+        assert!(world.is_building_valid(0));
+        assert_eq!(
+            world.buildings()[0].points(),
+            Building::square(IVec2::ZERO, 4).points()
+        );
+    }
+
+    #[test]
+    fn json_round_trip_keeps_every_building() {
+        let mut world = EditorWorld::new();
+        world.insert_building(Building::square(IVec2::ZERO, 4));
+        world.insert_building(Building::square(IVec2::new(10, -3), 5));
+        world.insert_building(Building::new(
+            3,
+            vec![IVec2::new(-8, 0), IVec2::new(-2, 0), IVec2::new(-5, 6)],
+        ));
+
+        let loaded = EditorWorld::from_json(&world.to_json()).unwrap();
+
+        assert_eq!(loaded.buildings(), world.buildings());
+        assert_eq!(loaded.buildings()[2].floor_y(), 3);
+    }
+
+    #[test]
+    fn json_round_trip_keeps_building_heights() {
+        let mut tall = Building::square(IVec2::ZERO, 4);
+        tall.height = 5;
+        let mut world = EditorWorld::new();
+        world.insert_building(tall);
+        world.insert_building(Building::square(IVec2::new(10, 0), 4));
+
+        let json = world.to_json();
+        let loaded = EditorWorld::from_json(&json).unwrap();
+
+        assert_eq!(loaded.buildings()[0].ceiling_y(), 5);
+        assert_eq!(
+            loaded.buildings()[1].height,
+            crate::building::BUILDING_HEIGHT
+        );
+        // The default height is left out, so older saves without heights load the same way.
+        assert_eq!(json.matches("\"height\"").count(), 1);
+    }
+
+    #[test]
+    fn building_at_finds_nothing_outside_every_building() {
+        let mut world = EditorWorld::new();
+        world.insert_building(Building::square(IVec2::ZERO, 4));
+        world.insert_building(Building::square(IVec2::new(10, 0), 4));
+
+        assert_eq!(world.building_at(IVec2::new(2, 2)), Some(0));
+        assert_eq!(world.building_at(IVec2::new(14, 4)), Some(1));
+        // Between the buildings, outside both bounding boxes.
+        assert_eq!(world.building_at(IVec2::new(7, 2)), None);
+        // Far from every building.
+        assert_eq!(world.building_at(IVec2::new(-100, 50)), None);
+    }
+
+    #[test]
+    fn multi_frame_drag_is_one_undo_step() {
+        let mut world = EditorWorld::new();
+        world.insert_building(Building::square(IVec2::ZERO, 4));
+        let original = world.buildings()[0].clone();
+
+        // As `move_building_system` does, over several frames of a drag.
+        world.begin_edit_group();
+        for p in [IVec2::new(5, 5), IVec2::new(6, 5), IVec2::new(6, 6)] {
+            assert!(world.set_building_point(0, 2, p));
+        }
+        world.end_edit_group();
+        assert_eq!(world.buildings()[0].points()[2], IVec2::new(6, 6));
+
+        assert!(world.undo());
+        assert_eq!(world.buildings()[0], original);
+        assert!(world.redo());
+        assert_eq!(world.buildings()[0].points()[2], IVec2::new(6, 6));
+
+        // The next undo takes back the drag again, and the one after removes the building.
+        assert!(world.undo());
+        assert!(world.undo());
+        assert!(world.buildings().is_empty());
+    }
+
+    #[test]
+    fn set_building_point_refuses_to_break_a_valid_building() {
+        let mut world = EditorWorld::new();
+        world.insert_building(Building::square(IVec2::ZERO, 4));
+
+        // The moved edge would cross the opposite wall.
+        assert!(!world.set_building_point(0, 2, IVec2::new(-2, 2)));
+        assert_eq!(world.buildings()[0], Building::square(IVec2::ZERO, 4));
+        assert!(world.is_building_valid(0));
+    }
+
+    #[test]
+    fn set_building_point_can_fix_an_invalid_building() {
+        let mut world = EditorWorld::new();
+        let bowtie = vec![
+            IVec2::new(0, 0),
+            IVec2::new(4, 0),
+            IVec2::new(0, 4),
+            IVec2::new(4, 4),
+        ];
+        world.insert_building(Building::new(0, bowtie));
+        assert!(!world.is_building_valid(0));
+
+        assert!(world.set_building_point(0, 2, IVec2::new(4, 4)));
+        assert!(world.set_building_point(0, 3, IVec2::new(0, 4)));
+        assert!(world.is_building_valid(0));
+    }
+
+    /// Compares the full validity check against the incremental one used while dragging a point.
+    /// Run with `cargo test --release drag_path_benchmark -- --ignored --nocapture`.
+    #[test]
+    #[ignore]
+    fn drag_path_benchmark() {
+        const POINTS: usize = 2000;
+        const MOVES: i32 = 200;
+        let outline = (0..POINTS)
+            .map(|i| {
+                let angle = i as f32 / POINTS as f32 * std::f32::consts::TAU;
+                (Vec2::from_angle(angle) * 100_000.).as_ivec2()
+            })
+            .collect();
+        let building = Building::new(0, outline);
+        let p = building.points()[0];
+
+        let start = std::time::Instant::now();
+        for i in 0..MOVES {
+            let mut moved = building.clone();
+            moved.outline[0] = p + IVec2::new(i, 0);
+            std::hint::black_box(moved.is_valid(BuildingValidity::default()));
+        }
+        let full = start.elapsed() / MOVES as u32;
+
+        let start = std::time::Instant::now();
+        for i in 0..MOVES {
+            std::hint::black_box(building.is_valid_after_point_change(0, p + IVec2::new(i, 0)));
+        }
+        let incremental = start.elapsed() / MOVES as u32;
+
+        println!(
+            "{POINTS} points: full check {full:?}, incremental check {incremental:?} per move"
+        );
+    }
+}
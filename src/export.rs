@@ -0,0 +1,340 @@
+use bevy::prelude::*;
+use std::fmt::Write;
+
+use crate::building::{Building, SHELL_SLAB_THICKNESS};
+use crate::editor_state::EditorWorld;
+use crate::geometry_utils::{
+    offset_polygon, signed_polygon_area_2d, triangulate_polygon_with_holes,
+};
+use crate::js_ffi::take_export_requests;
+use crate::keybindings::Keybindings;
+use crate::manifold::check_manifold;
+use crate::voxels::VOXEL_SIZE;
+use crate::{CSG, RenderedCsg};
+
+/// Where `export_obj_system` writes the world geometry on native builds.
+const OBJ_EXPORT_PATH: &str = "world.obj";
+
+/// Where `export_vmf_system` writes the buildings on native builds, and the name of the file it
+/// hands to the client on web builds.
+const VMF_EXPORT_PATH: &str = "world.vmf";
+
+pub struct ExportPlugin;
+
+impl Plugin for ExportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<VmfExportRequestedEvent>().add_systems(
+            Update,
+            (
+                export_obj_system,
+                (receive_export_requests_system, export_vmf_system).chain(),
+            ),
+        );
+    }
+}
+
+/// Converts the CSG to the Wavefront OBJ format, with the outside and inside surfaces in separate
+/// `outside` and `inside` objects.
+///
+/// Every triangle gets its own vertices. Polygons without surface details are skipped.
+pub fn csg_to_obj(csg: &CSG) -> String {
+    let tessellated_csg = csg.tessellate();
+
+    let mut obj = String::new();
+    let mut vertex_count = 0;
+    for (name, outside) in [("outside", true), ("inside", false)] {
+        writeln!(obj, "o {name}").unwrap();
+
+        for poly in tessellated_csg.polygons.iter() {
+            if poly.vertices.len() != 3
+                || poly
+                    .metadata
+                    .as_ref()
+                    .is_none_or(|detail| detail.outside != outside)
+            {
+                continue;
+            }
+
+            for v in poly.vertices.iter() {
+                writeln!(obj, "v {} {} {}", v.pos.x, v.pos.y, v.pos.z).unwrap();
+                writeln!(obj, "vn {} {} {}", v.normal.x, v.normal.y, v.normal.z).unwrap();
+            }
+
+            // OBJ indexes are 1-based.
+            let [a, b, c] = [vertex_count + 1, vertex_count + 2, vertex_count + 3];
+            writeln!(obj, "f {a}//{a} {b}//{b} {c}//{c}").unwrap();
+            vertex_count += 3;
+        }
+    }
+    obj
+}
+
+/// Exports the world geometry as an OBJ file when the export key (`F7` by default) is pressed,
+/// with a warning if the geometry is not manifold.
+fn export_obj_system(
+    rendered_csg: Res<RenderedCsg>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if !keys.just_pressed(bindings.export_obj) {
+        return;
+    }
+
+    let defects = check_manifold(&rendered_csg.0);
+    if !defects.is_empty() {
+        warn!(
+            "Exporting world geometry with {} non-manifold edges, which some tools will reject",
+            defects.len()
+        );
+    }
+
+    let obj = csg_to_obj(&rendered_csg.0);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = std::fs::write(OBJ_EXPORT_PATH, &obj) {
+        error!("Failed to write {OBJ_EXPORT_PATH}: {err}");
+        return;
+    }
+
+    info!("Exported {} bytes of OBJ to {OBJ_EXPORT_PATH}", obj.len());
+}
+
+/// Sent when the client asks for the buildings as a VMF map through `tfbe_ffi_export_file`.
+#[derive(Event)]
+pub struct VmfExportRequestedEvent;
+
+/// Sends a `VmfExportRequestedEvent` for each request made through `tfbe_ffi_export_file`.
+fn receive_export_requests_system(mut events: EventWriter<VmfExportRequestedEvent>) {
+    for _ in 0..take_export_requests() {
+        events.write(VmfExportRequestedEvent);
+    }
+}
+
+/// Exports the buildings as a VMF map when the export key (`F11` by default) is pressed, or when
+/// the client asks for it. Native builds write the map to `VMF_EXPORT_PATH`, and web builds hand it
+/// to the client with `tfbe_ffi_save_file`.
+fn export_vmf_system(
+    world: Res<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+    mut requests: EventReader<VmfExportRequestedEvent>,
+) {
+    let requested = requests.read().count() > 0;
+    if !keys.just_pressed(bindings.export_vmf) && !requested {
+        return;
+    }
+
+    let vmf = editor_world_to_vmf(&world);
+    let text = vmf.to_vmf_string();
+
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = std::fs::write(VMF_EXPORT_PATH, &text) {
+        error!("Failed to write {VMF_EXPORT_PATH}: {err}");
+        return;
+    }
+    #[cfg(target_arch = "wasm32")]
+    crate::js_ffi::tfbe_ffi_save_file(VMF_EXPORT_PATH, &text);
+
+    info!(
+        "Exported {} brushes ({} bytes of VMF) to {VMF_EXPORT_PATH}",
+        vmf.world.solids.len(),
+        text.len()
+    );
+}
+
+/// The material applied to every face of exported VMF brushes.
+const VMF_MATERIAL: &str = "DEV/DEV_MEASUREGENERIC01B";
+
+/// The texture scale of exported VMF faces, in Hammer units per texel.
+const VMF_TEXTURE_SCALE: f32 = 0.25;
+
+/// Converts the buildings of the editor world into a VMF map of brushes.
+///
+/// See `buildings_to_vmf` for how the buildings are converted.
+pub fn editor_world_to_vmf(world: &EditorWorld) -> vmf_forge::VmfFile {
+    buildings_to_vmf(world.buildings())
+}
+
+/// Converts buildings into a VMF map, with each building made of convex `solid` brushes in the
+/// `worldspawn` entity:
+///
+/// - one brush for each wall, running from an outline (or courtyard) edge to the matching edge of
+///   the interior outline, between the floor and ceiling;
+/// - a floor slab and a ceiling slab below and above the room, each split into triangular brushes
+///   covering the outline, with openings left for courtyards.
+///
+/// One grid unit is `VOXEL_SIZE` Hammer units. The editor's Y axis is up, which becomes Hammer's Z
+/// axis; the editor's Z axis becomes Hammer's negative Y axis, so the map is not mirrored.
+/// Coordinates are not rounded to whole Hammer units. Every face gets `VMF_MATERIAL`, aligned to
+/// the world axes.
+pub fn buildings_to_vmf(buildings: &[Building]) -> vmf_forge::VmfFile {
+    let mut vmf = vmf_forge::VmfFile::default();
+    for (key, value) in [
+        ("id", "1"),
+        ("mapversion", "1"),
+        ("classname", "worldspawn"),
+    ] {
+        vmf.world
+            .key_values
+            .insert(key.to_string(), value.to_string());
+    }
+
+    let mut ids = VmfIds {
+        next_solid: 2,
+        next_side: 1,
+    };
+    // Grid coordinates on the editing plane, to Hammer's horizontal plane.
+    let to_hammer = |p: Vec2| Vec2::new(p.x, -p.y) * VOXEL_SIZE;
+
+    for building in buildings {
+        let floor = building.floor_y() as f32 * VOXEL_SIZE;
+        let ceiling = building.ceiling_y() as f32 * VOXEL_SIZE;
+        let slab = SHELL_SLAB_THICKNESS * VOXEL_SIZE;
+
+        let rings =
+            std::iter::once(building.points()).chain(building.holes().iter().map(Vec::as_slice));
+        for ring in rings {
+            let inner = offset_polygon(ring, building.interior_offset());
+            for i in 0..ring.len() {
+                let j = (i + 1) % ring.len();
+                let wall =
+                    [ring[i].as_vec2(), ring[j].as_vec2(), inner[j], inner[i]].map(to_hammer);
+                vmf.world
+                    .solids
+                    .push(vmf_prism(&mut ids, &wall, floor, ceiling));
+            }
+        }
+
+        let outline: Vec<Vec2> = building.points().iter().map(|p| p.as_vec2()).collect();
+        let holes: Vec<Vec<Vec2>> = building
+            .holes()
+            .iter()
+            .map(|hole| hole.iter().map(|p| p.as_vec2()).collect())
+            .collect();
+        for triangle in triangulate_polygon_with_holes(&outline, &holes) {
+            let triangle = triangle.map(to_hammer);
+            vmf.world
+                .solids
+                .push(vmf_prism(&mut ids, &triangle, floor - slab, floor));
+            vmf.world
+                .solids
+                .push(vmf_prism(&mut ids, &triangle, ceiling, ceiling + slab));
+        }
+    }
+
+    vmf
+}
+
+/// The next unused ids for VMF solids and sides.
+struct VmfIds {
+    next_solid: u64,
+    next_side: u32,
+}
+
+/// Makes a `solid` brush: a vertical prism over the convex polygon `points` (in Hammer's horizontal
+/// plane), between the heights `bottom` and `top`.
+fn vmf_prism(
+    ids: &mut VmfIds,
+    points: &[Vec2],
+    bottom: f32,
+    top: f32,
+) -> vmf_forge::prelude::Solid {
+    // Faces are written with their points clockwise as seen from outside the brush, which needs
+    // the polygon to be counterclockwise as seen from above.
+    let mut points = points.to_vec();
+    if signed_polygon_area_2d(&points) < 0.0 {
+        points.reverse();
+    }
+
+    let at = |p: Vec2, z: f32| p.extend(z);
+    let mut faces = vec![
+        // The top, then the bottom.
+        [at(points[0], top), at(points[2], top), at(points[1], top)],
+        [
+            at(points[0], bottom),
+            at(points[1], bottom),
+            at(points[2], bottom),
+        ],
+    ];
+    for i in 0..points.len() {
+        let (a, b) = (points[i], points[(i + 1) % points.len()]);
+        faces.push([at(a, bottom), at(a, top), at(b, top)]);
+    }
+
+    let sides = faces
+        .into_iter()
+        .map(|plane| {
+            // Outward normal of a face whose points are clockwise from outside.
+            let normal = (plane[2] - plane[0]).cross(plane[1] - plane[0]);
+            let (u_axis, v_axis) = if normal.z.abs() >= normal.x.abs().max(normal.y.abs()) {
+                ("1 0 0", "0 -1 0")
+            } else if normal.x.abs() >= normal.y.abs() {
+                ("0 1 0", "0 0 -1")
+            } else {
+                ("1 0 0", "0 0 -1")
+            };
+            let [p1, p2, p3] = plane.map(|p| format!("({} {} {})", p.x, p.y, p.z));
+
+            let side = vmf_forge::prelude::Side {
+                id: ids.next_side,
+                plane: format!("{p1} {p2} {p3}"),
+                material: VMF_MATERIAL.to_string(),
+                u_axis: format!("[{u_axis} 0] {VMF_TEXTURE_SCALE}"),
+                v_axis: format!("[{v_axis} 0] {VMF_TEXTURE_SCALE}"),
+                lightmap_scale: 16,
+                ..default()
+            };
+            ids.next_side += 1;
+            side
+        })
+        .collect();
+
+    let solid = vmf_forge::prelude::Solid {
+        id: ids.next_solid,
+        sides,
+        ..default()
+    };
+    ids.next_solid += 1;
+    solid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BuildingPrismCache, build_world_csg};
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn obj_has_one_face_per_triangle() {
+        let room = Building::new(
+            0,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(4, 0),
+                IVec2::new(4, 4),
+                IVec2::new(0, 4),
+            ],
+        );
+        let csg = build_world_csg(
+            &[room],
+            &mut BuildingPrismCache::default(),
+            |_| {},
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+
+        let triangles = csg
+            .tessellate()
+            .polygons
+            .iter()
+            .filter(|poly| poly.vertices.len() == 3 && poly.metadata.is_some())
+            .count();
+        assert!(triangles > 0);
+
+        let obj = csg_to_obj(&csg);
+        let faces = obj.lines().filter(|line| line.starts_with("f ")).count();
+        let vertices = obj.lines().filter(|line| line.starts_with("v ")).count();
+        assert_eq!(faces, triangles);
+        assert_eq!(vertices, 3 * triangles);
+    }
+}
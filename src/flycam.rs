@@ -1,4 +1,9 @@
-use bevy::{input::mouse::MouseMotion, prelude::*};
+use bevy::{
+    input::mouse::{MouseMotion, MouseWheel},
+    prelude::*,
+};
+
+use crate::keybindings::Keybindings;
 
 pub struct FlyCameraPlugin;
 
@@ -11,11 +16,54 @@ impl Plugin for FlyCameraPlugin {
 #[derive(Component)]
 pub struct CameraControls {
     speed: f32,
+    /// How much faster the camera moves while left `Shift` is held.
+    pub sprint_multiplier: f32,
+    /// Whether the camera eases into and out of motion. When disabled, the camera starts and stops
+    /// moving instantly.
+    pub smooth: bool,
+    /// How quickly the camera's velocity approaches the velocity of the pressed keys, per second.
+    /// Higher is snappier.
+    pub damping: f32,
+    /// The camera's current velocity, in world units per second.
+    velocity: Vec3,
 }
 
 impl Default for CameraControls {
     fn default() -> Self {
-        Self { speed: 512. }
+        Self {
+            speed: 512.,
+            sprint_multiplier: 4.0,
+            smooth: true,
+            damping: 8.0,
+            velocity: Vec3::ZERO,
+        }
+    }
+}
+
+impl CameraControls {
+    /// The slowest and fastest the camera may move, in world units per second.
+    pub const SPEED_RANGE: (f32, f32) = (16.0, 8192.0);
+
+    /// Returns how fast the camera moves, in world units per second.
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Sets how fast the camera moves, clamped to `SPEED_RANGE`.
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(Self::SPEED_RANGE.0, Self::SPEED_RANGE.1);
+    }
+
+    /// Moves the camera's velocity toward `target` over `delta_secs`, and returns the new velocity.
+    ///
+    /// The approach is exponential, so it doesn't depend on the frame rate.
+    fn update_velocity(&mut self, target: Vec3, delta_secs: f32) -> Vec3 {
+        self.velocity = if self.smooth {
+            target + (self.velocity - target) * (-self.damping * delta_secs).exp()
+        } else {
+            target
+        };
+        self.velocity
     }
 }
 
@@ -23,29 +71,53 @@ fn control_camera_system(
     time: Res<Time>,
     mut camera: Query<(&mut Transform, &mut CameraControls)>,
     key: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
     mouse_button: Res<ButtonInput<MouseButton>>,
     mut mouse_move: EventReader<MouseMotion>,
+    mut mouse_wheel: EventReader<MouseWheel>,
 ) {
-    for (mut camera_transform, controls) in camera.iter_mut() {
+    // Each notch of the mouse wheel speeds the camera up or slows it down.
+    let mut speed_factor = 1.0;
+    for evt in mouse_wheel.read() {
+        if evt.y > 0.0 {
+            speed_factor *= 1.1;
+        } else if evt.y < 0.0 {
+            speed_factor *= 0.9;
+        }
+    }
+
+    for (mut camera_transform, mut controls) in camera.iter_mut() {
+        if speed_factor != 1.0 {
+            let speed = controls.speed * speed_factor;
+            controls.set_speed(speed);
+        }
+
         let forward = camera_transform.forward();
         let right = camera_transform.right();
 
         let mut local: Vec3 = Vec3::ZERO;
-        if key.pressed(KeyCode::KeyD) {
+        if key.pressed(bindings.camera_right) {
             local.x += 1.0;
         }
-        if key.pressed(KeyCode::KeyA) {
+        if key.pressed(bindings.camera_left) {
             local.x -= 1.0;
         }
-        if key.pressed(KeyCode::KeyW) {
+        if key.pressed(bindings.camera_forward) {
             local.z += 1.0;
         }
-        if key.pressed(KeyCode::KeyS) {
+        if key.pressed(bindings.camera_back) {
             local.z -= 1.0;
         }
 
-        camera_transform.translation +=
-            (local.x * right + local.z * forward) * controls.speed * time.delta_secs();
+        let mut speed = controls.speed;
+        if key.pressed(KeyCode::ShiftLeft) {
+            speed *= controls.sprint_multiplier;
+        }
+        let velocity = controls.update_velocity(
+            (local.x * right + local.z * forward) * speed,
+            time.delta_secs(),
+        );
+        camera_transform.translation += velocity * time.delta_secs();
 
         if mouse_button.pressed(MouseButton::Right) {
             let mut angle_azimuth = forward.z.atan2(forward.x);
@@ -72,3 +144,51 @@ fn control_camera_system(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn velocity_decays_to_rest_after_keys_are_released() {
+        let mut controls = CameraControls::default();
+        for _ in 0..60 {
+            controls.update_velocity(Vec3::X * 512., 1. / 60.);
+        }
+        assert!(controls.velocity.x > 256.);
+
+        // One second of frames with no keys held.
+        for _ in 0..60 {
+            controls.update_velocity(Vec3::ZERO, 1. / 60.);
+        }
+        assert!(controls.velocity.length() < 1.0, "{}", controls.velocity);
+
+        // Without smoothing, the camera stops at once.
+        controls.smooth = false;
+        controls.update_velocity(Vec3::X * 512., 1. / 60.);
+        assert_eq!(controls.update_velocity(Vec3::ZERO, 1. / 60.), Vec3::ZERO);
+    }
+
+    #[test]
+    fn wheel_speed_stays_within_the_speed_range() {
+        let (min, max) = CameraControls::SPEED_RANGE;
+        let mut controls = CameraControls::default();
+
+        // Scrolling up many notches, as `control_camera_system` does.
+        for _ in 0..200 {
+            let speed = controls.speed() * 1.1;
+            controls.set_speed(speed);
+        }
+        assert_eq!(controls.speed(), max);
+
+        for _ in 0..200 {
+            let speed = controls.speed() * 0.9;
+            controls.set_speed(speed);
+        }
+        assert_eq!(controls.speed(), min);
+
+        // One notch back up leaves the minimum.
+        controls.set_speed(controls.speed() * 1.1);
+        assert!(controls.speed() > min);
+    }
+}
@@ -34,6 +34,81 @@ pub fn signed_polygon_area_2d(points: &[impl As2d]) -> f32 {
     }
     sum * 0.5
 }
+/// Returns the center of mass of the area enclosed by a polygon.
+/// Degenerate polygons (with no area) return the average of their points instead.
+pub fn polygon_centroid(points: &[impl As2d]) -> Vec2 {
+    let point = |i: usize| Vec2::new(points[i].coord_x(), points[i].coord_y());
+
+    let area = signed_polygon_area_2d(points);
+    if area == 0.0 {
+        let sum: Vec2 = (0..points.len()).map(point).sum();
+        return sum / points.len().max(1) as f32;
+    }
+
+    let mut sum = Vec2::ZERO;
+    for i in 0..points.len() {
+        let a = point(i);
+        let b = point((i + 1) % points.len());
+        sum += (a + b) * a.perp_dot(b);
+    }
+    sum / (6.0 * area)
+}
+
+/// Offsets each point of a polygon outline along the bisector of its corner, so that every edge
+/// moves by `width`. Positive widths move the edges outward (for a counter-clockwise outline).
+pub fn offset_polygon(points: &[IVec2], width: f32) -> Vec<Vec2> {
+    points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let center = p.as_vec2();
+            let next = points[(i + 1) % points.len()].as_vec2();
+            let prev = points[(i + points.len() - 1) % points.len()].as_vec2();
+
+            let delta_next = next - center;
+            let delta_prev = prev - center;
+            let angle_next = delta_next.to_angle();
+            let mut angle_prev = delta_prev.to_angle();
+            if angle_prev < angle_next {
+                angle_prev += std::f32::consts::PI * 2.;
+            }
+
+            let angle_middle = (angle_next + angle_prev) / 2.;
+            let offset =
+                Vec2::from_angle(angle_middle) * width / ((angle_next - angle_prev) / 2.).sin();
+
+            center + offset
+        })
+        .collect()
+}
+
+/// Returns whether `p` lies inside the polygon, using the even-odd rule.
+/// Points exactly on an edge or vertex count as inside.
+pub fn point_in_polygon(p: Vec2, polygon: &[impl As2d]) -> bool {
+    let point = |i: usize| {
+        let v = &polygon[i % polygon.len()];
+        Vec2::new(v.coord_x(), v.coord_y())
+    };
+
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let a = point(i);
+        let b = point(i + 1);
+        let on_edge =
+            (b - a).perp_dot(p - a) == 0.0 && p.cmpge(a.min(b)).all() && p.cmple(a.max(b)).all();
+        if on_edge {
+            return true;
+        }
+        if (a.y > p.y) != (b.y > p.y) {
+            let x_cross = a.x + (p.y - a.y) / (b.y - a.y) * (b.x - a.x);
+            if p.x < x_cross {
+                inside = !inside;
+            }
+        }
+    }
+    inside
+}
+
 pub trait BevyToNalgebra {
     type Point;
     fn to_point(&self) -> Self::Point;
@@ -67,40 +142,351 @@ impl BevyToNalgebra for Vec3 {
     }
 }
 
+/// Projects `a` onto the line through `p` and `q`. If `p` and `q` coincide, returns `p`.
 pub fn project_onto_v2(a: Vec2, (p, q): (Vec2, Vec2)) -> Vec2 {
-    (a - p).dot((q - p).normalize()) * (q - p).normalize() + p
+    let Some(d) = (q - p).try_normalize() else {
+        return p;
+    };
+    (a - p).dot(d) * d + p
 }
 
 pub fn project_onto_i2(a: IVec2, (p, q): (IVec2, IVec2)) -> Vec2 {
     project_onto_v2(a.as_vec2(), (p.as_vec2(), q.as_vec2()))
 }
+/// Returns the point on the segment nearest to `p`. A zero-length segment returns its endpoint.
 pub fn point_closest_to_segment(p: Vec2, line: (Vec2, Vec2)) -> Vec2 {
+    let Some(d) = (line.1 - line.0).try_normalize() else {
+        return line.0;
+    };
+
     // Project p onto the line.
     let p_on_line = project_onto_v2(p, line);
 
-    let d = (line.1 - line.0).normalize();
-
     let t = (p_on_line - line.0).dot(d) / (line.0.distance(line.1));
     let t = t.clamp(0.0, 1.0);
 
     line.0.lerp(line.1, t)
 }
-pub fn segments_cross(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+/// Returns the point where two segments cross, if they do.
+/// Parallel (and colinear) segments are never considered to cross.
+pub fn segment_intersection(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> Option<Vec2> {
     let (p1, p2) = a;
     let (q1, q2) = b;
 
     let r = p2 - p1;
     let s = q2 - q1;
-    let pq = q1 - p1;
     let rxs = r.perp_dot(s);
 
     if rxs == 0.0 {
-        // Lines are parallel (or colinear)
-        return false;
+        return None;
     }
 
+    let pq = q1 - p1;
     let t = pq.perp_dot(s) / rxs;
     let u = pq.perp_dot(r) / rxs;
 
-    (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)
+    if (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u) {
+        Some(p1 + r * t)
+    } else {
+        None
+    }
+}
+/// Returns whether two segments cross or touch, including at their endpoints.
+/// Colinear segments count as crossing when they overlap.
+pub fn segments_cross(a: (Vec2, Vec2), b: (Vec2, Vec2)) -> bool {
+    let (p1, p2) = a;
+    let (q1, q2) = b;
+
+    // The side of the line through `from` and `to` that `p` is on: -1, 0 (on the line), or 1.
+    let orientation = |from: Vec2, to: Vec2, p: Vec2| {
+        let cross = (to - from).perp_dot(p - from);
+        if cross > 0.0 {
+            1
+        } else if cross < 0.0 {
+            -1
+        } else {
+            0
+        }
+    };
+    // Whether `p`, which is colinear with the segment, lies within its bounding box.
+    let on_segment = |(from, to): (Vec2, Vec2), p: Vec2| {
+        p.cmpge(from.min(to)).all() && p.cmple(from.max(to)).all()
+    };
+
+    let o1 = orientation(p1, p2, q1);
+    let o2 = orientation(p1, p2, q2);
+    let o3 = orientation(q1, q2, p1);
+    let o4 = orientation(q1, q2, p2);
+
+    if o1 != o2 && o3 != o4 {
+        return true;
+    }
+
+    (o1 == 0 && on_segment(a, q1))
+        || (o2 == 0 && on_segment(a, q2))
+        || (o3 == 0 && on_segment(b, p1))
+        || (o4 == 0 && on_segment(b, p2))
+}
+
+/// Returns the distance along `ray` at which it hits the triangle `[a, b, c]`, if it does.
+/// Triangles are hit from either side.
+pub fn ray_triangle_intersection(ray: Ray3d, [a, b, c]: [Vec3; 3]) -> Option<f32> {
+    // Möller–Trumbore intersection.
+    let edge_1 = b - a;
+    let edge_2 = c - a;
+    let p = ray.direction.cross(edge_2);
+    let determinant = edge_1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        // The ray is parallel to the triangle.
+        return None;
+    }
+
+    let to_origin = ray.origin - a;
+    let u = to_origin.dot(p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(edge_1);
+    let v = ray.direction.dot(q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let distance = edge_2.dot(q) / determinant;
+    (distance >= 0.0).then_some(distance)
+}
+
+/// Splits a simple polygon with positive signed area into triangles by ear clipping, returning
+/// the indexes of each triangle's corners (with positive signed area).
+///
+/// Degenerate (colinear) corners are clipped without producing a triangle.
+pub fn triangulate_polygon(points: &[impl As2d]) -> Vec<[usize; 3]> {
+    let point = |i: usize| Vec2::new(points[i].coord_x(), points[i].coord_y());
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len().saturating_sub(2));
+    while remaining.len() >= 3 {
+        let len = remaining.len();
+        let ear = (0..len).find(|&i| {
+            let [a, b, c] = [
+                remaining[(i + len - 1) % len],
+                remaining[i],
+                remaining[(i + 1) % len],
+            ];
+            let (pa, pb, pc) = (point(a), point(b), point(c));
+            if (pb - pa).perp_dot(pc - pb) < 0.0 {
+                // A reflex corner is never an ear.
+                return false;
+            }
+            // No other corner may be inside (or on the edge of) the ear. Corners at the same
+            // position as the ear's own, as on the bridges to holes, don't count.
+            remaining.iter().all(|&other| {
+                let p = point(other);
+                p == pa
+                    || p == pb
+                    || p == pc
+                    || (pb - pa).perp_dot(p - pa) < 0.0
+                    || (pc - pb).perp_dot(p - pb) < 0.0
+                    || (pa - pc).perp_dot(p - pc) < 0.0
+            })
+        });
+        // A polygon that isn't simple may have no ears left.
+        let Some(i) = ear else {
+            break;
+        };
+
+        let [a, b, c] = [
+            remaining[(i + len - 1) % len],
+            remaining[i],
+            remaining[(i + 1) % len],
+        ];
+        if (point(b) - point(a)).perp_dot(point(c) - point(b)) > 0.0 {
+            triangles.push([a, b, c]);
+        }
+        remaining.remove(i);
+    }
+    triangles
+}
+
+/// Returns the edges of a closed ring of points, including the edge from the last to the first.
+fn ring_edges(ring: &[Vec2]) -> impl Iterator<Item = (Vec2, Vec2)> + '_ {
+    (0..ring.len()).map(|i| (ring[i], ring[(i + 1) % ring.len()]))
+}
+
+/// Splits a polygon with holes into triangles (with positive signed area), by joining each hole to
+/// the outline with a bridge and ear clipping the result.
+///
+/// The outline must have positive signed area, and the holes must be inside it without touching it
+/// or each other. A hole which can't be bridged is left filled.
+pub fn triangulate_polygon_with_holes(outline: &[Vec2], holes: &[Vec<Vec2>]) -> Vec<[Vec2; 3]> {
+    let mut merged: Vec<Vec2> = outline.to_vec();
+
+    // Bridge the holes from right to left, so that a bridge can't cross a hole bridged later.
+    let mut holes: Vec<Vec<Vec2>> = holes
+        .iter()
+        .filter(|hole| hole.len() >= 3)
+        .cloned()
+        .collect();
+    let max_x = |hole: &[Vec2]| hole.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max);
+    holes.sort_by(|a, b| max_x(b).total_cmp(&max_x(a)));
+
+    for hole_index in 0..holes.len() {
+        // Holes are walked clockwise, the opposite way to the outline.
+        let mut hole = holes[hole_index].clone();
+        if signed_polygon_area_2d(&hole) > 0.0 {
+            hole.reverse();
+        }
+        let Some(m) = (0..hole.len()).max_by(|&a, &b| hole[a].x.total_cmp(&hole[b].x)) else {
+            continue;
+        };
+        let from = hole[m];
+
+        let is_visible = |to: Vec2| {
+            let blocks = |(a, b): (Vec2, Vec2)| {
+                a != from && b != from && a != to && b != to && segments_cross((from, to), (a, b))
+            };
+            !ring_edges(&merged).any(blocks)
+                && !holes[hole_index..]
+                    .iter()
+                    .any(|other| ring_edges(other).any(blocks))
+        };
+        let by_distance = |&a: &usize, &b: &usize| {
+            merged[a]
+                .distance_squared(from)
+                .total_cmp(&merged[b].distance_squared(from))
+        };
+        // Bridging to the right of the hole's rightmost point keeps the bridge out of the hole.
+        let target = (0..merged.len())
+            .filter(|&i| merged[i].x > from.x && is_visible(merged[i]))
+            .min_by(by_distance)
+            .or_else(|| {
+                (0..merged.len())
+                    .filter(|&i| is_visible(merged[i]))
+                    .min_by(by_distance)
+            });
+        let Some(v) = target else {
+            continue;
+        };
+
+        // Walk across the bridge, around the hole, and back again.
+        let mut spliced = Vec::with_capacity(merged.len() + hole.len() + 2);
+        spliced.extend_from_slice(&merged[..=v]);
+        spliced.extend((0..=hole.len()).map(|k| hole[(m + k) % hole.len()]));
+        spliced.extend_from_slice(&merged[v..]);
+        merged = spliced;
+    }
+
+    triangulate_polygon(&merged)
+        .into_iter()
+        .map(|triangle| triangle.map(|i| merged[i]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle_area([a, b, c]: [Vec2; 3]) -> f32 {
+        signed_polygon_area_2d(&[a, b, c])
+    }
+
+    #[test]
+    fn triangulating_with_a_hole_leaves_the_hole_uncovered() {
+        let outline = [
+            Vec2::new(0., 0.),
+            Vec2::new(4., 0.),
+            Vec2::new(4., 4.),
+            Vec2::new(0., 4.),
+        ];
+        let hole = vec![
+            Vec2::new(1., 1.),
+            Vec2::new(3., 1.),
+            Vec2::new(3., 3.),
+            Vec2::new(1., 3.),
+        ];
+
+        let triangles = triangulate_polygon_with_holes(&outline, &[hole]);
+
+        assert!(
+            triangles
+                .iter()
+                .all(|&triangle| triangle_area(triangle) > 0.0)
+        );
+        let area: f32 = triangles
+            .iter()
+            .map(|&triangle| triangle_area(triangle))
+            .sum();
+        assert!((area - 12.0).abs() < 1e-4, "area was {area}");
+        let hole_center = Vec2::new(2., 2.);
+        assert!(
+            !triangles
+                .iter()
+                .any(|triangle| point_in_polygon(hole_center, triangle))
+        );
+    }
+
+    #[test]
+    fn segments_cross_handles_colinear_touching_and_disjoint_segments() {
+        let v = Vec2::new;
+
+        // Colinear and overlapping.
+        assert!(segments_cross(
+            (v(0., 0.), v(4., 0.)),
+            (v(2., 0.), v(6., 0.))
+        ));
+        // Colinear, but with a gap between them.
+        assert!(!segments_cross(
+            (v(0., 0.), v(2., 0.)),
+            (v(3., 0.), v(6., 0.))
+        ));
+        // A T-junction, where one segment ends on the other.
+        assert!(segments_cross(
+            (v(0., 0.), v(4., 0.)),
+            (v(2., 0.), v(2., 3.))
+        ));
+        // Disjoint, and not parallel.
+        assert!(!segments_cross(
+            (v(0., 0.), v(4., 0.)),
+            (v(5., 1.), v(7., 3.))
+        ));
+        // Parallel.
+        assert!(!segments_cross(
+            (v(0., 0.), v(4., 0.)),
+            (v(0., 1.), v(4., 1.))
+        ));
+    }
+
+    #[test]
+    fn point_in_polygon_handles_squares_concave_outlines_and_boundaries() {
+        let v = Vec2::new;
+        let square = [v(0., 0.), v(4., 0.), v(4., 4.), v(0., 4.)];
+        assert!(point_in_polygon(v(2., 2.), &square));
+        assert!(!point_in_polygon(v(5., 2.), &square));
+
+        // An L shape, missing its top right quarter.
+        let l_shape = [
+            v(0., 0.),
+            v(4., 0.),
+            v(4., 2.),
+            v(2., 2.),
+            v(2., 4.),
+            v(0., 4.),
+        ];
+        assert!(point_in_polygon(v(1., 3.), &l_shape));
+        assert!(point_in_polygon(v(3., 1.), &l_shape));
+        assert!(!point_in_polygon(v(3., 3.), &l_shape));
+
+        // Points on an edge or a vertex count as inside.
+        assert!(point_in_polygon(v(2., 0.), &square));
+        assert!(point_in_polygon(v(4., 4.), &square));
+        assert!(point_in_polygon(v(3., 2.), &l_shape));
+    }
+
+    #[test]
+    fn point_closest_to_a_zero_length_segment_is_its_endpoint() {
+        let end = Vec2::new(3., -2.);
+        let closest = point_closest_to_segment(Vec2::new(10., 7.), (end, end));
+        assert_eq!(closest, end);
+        assert!(closest.is_finite());
+    }
 }
@@ -0,0 +1,71 @@
+use std::sync::atomic::AtomicBool;
+
+use crate::editor_state::EditorWorld;
+use crate::export::{csg_to_obj, editor_world_to_vmf};
+use crate::vmf_import::import_vmf_buildings;
+use crate::{BuildingPrismCache, build_world_csg};
+
+/// The command line for converting a VMF file without opening a window:
+/// `tfblockeditor export <input.vmf> <output.obj|output.vmf>`.
+pub const USAGE: &str = "usage: tfblockeditor export <input.vmf> <output.obj|output.vmf>";
+
+/// Runs a headless command, if the command line asks for one. Returns `None` if the editor should
+/// start normally.
+pub fn run(args: &[String]) -> Option<Result<(), String>> {
+    match args {
+        [command, input, output] if command == "export" => Some(export(input, output)),
+        [command, ..] if command == "export" => Some(Err(USAGE.to_string())),
+        _ => None,
+    }
+}
+
+/// Loads the buildings of the VMF file at `input` and writes them to `output`: as world geometry if
+/// it ends in `.obj`, or as brushes if it ends in `.vmf`.
+fn export(input: &str, output: &str) -> Result<(), String> {
+    let text = std::fs::read_to_string(input).map_err(|err| format!("{input}: {err}"))?;
+    let level = vmf_forge::VmfFile::parse(&text).map_err(|err| format!("{input}: {err}"))?;
+
+    let mut editor_world = EditorWorld::new();
+    import_vmf_buildings(&mut editor_world, &level);
+
+    let exported = if output.ends_with(".obj") {
+        let csg = build_world_csg(
+            editor_world.buildings(),
+            &mut BuildingPrismCache::default(),
+            |_| {},
+            &AtomicBool::new(false),
+        )
+        .expect("an uncancelled build always finishes");
+        csg_to_obj(&csg)
+    } else if output.ends_with(".vmf") {
+        editor_world_to_vmf(&editor_world).to_vmf_string()
+    } else {
+        return Err(format!("{output}: unknown export format\n{USAGE}"));
+    };
+
+    std::fs::write(output, exported).map_err(|err| format!("{output}: {err}"))?;
+    println!(
+        "Exported {} buildings from {input} to {output}",
+        editor_world.buildings().len()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn only_export_commands_run_headless() {
+        assert!(run(&args(&[])).is_none());
+        assert!(run(&args(&["--verbose"])).is_none());
+        assert_eq!(
+            run(&args(&["export", "in.vmf"])),
+            Some(Err(USAGE.to_string()))
+        );
+    }
+}
@@ -1,10 +1,58 @@
-pub static EDITABLE_LEVEL: std::sync::Mutex<Option<vmf_forge::VmfFile>> =
-    std::sync::Mutex::new(None);
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{LazyLock, Mutex};
+
+/// Carries files parsed by `tfbe_ffi_load_file` to the Bevy world, where
+/// `receive_loaded_levels_system` turns them into `LevelLoadedEvent`s.
+static LOADED_LEVELS: LazyLock<(
+    Sender<vmf_forge::VmfFile>,
+    Mutex<Receiver<vmf_forge::VmfFile>>,
+)> = LazyLock::new(|| {
+    let (sender, receiver) = channel();
+    (sender, Mutex::new(receiver))
+});
+
+/// Returns the files loaded through `tfbe_ffi_load_file` since the last call, oldest first.
+pub fn take_loaded_levels() -> Vec<vmf_forge::VmfFile> {
+    LOADED_LEVELS.1.lock().unwrap().try_iter().collect()
+}
+
+/// Carries requests made by `tfbe_ffi_export_file` to the Bevy world, where
+/// `receive_export_requests_system` turns them into `VmfExportRequestedEvent`s.
+static EXPORT_REQUESTS: LazyLock<(Sender<()>, Mutex<Receiver<()>>)> = LazyLock::new(|| {
+    let (sender, receiver) = channel();
+    (sender, Mutex::new(receiver))
+});
+
+/// Returns the number of export requests made through `tfbe_ffi_export_file` since the last call.
+pub fn take_export_requests() -> usize {
+    EXPORT_REQUESTS.1.lock().unwrap().try_iter().count()
+}
+
+/// Carries the contents of OBJ files loaded by `tfbe_ffi_load_reference_obj` to the Bevy world,
+/// where `receive_reference_objs_system` turns them into `ReferenceObjLoadedEvent`s.
+static LOADED_REFERENCE_OBJS: LazyLock<(Sender<String>, Mutex<Receiver<String>>)> =
+    LazyLock::new(|| {
+        let (sender, receiver) = channel();
+        (sender, Mutex::new(receiver))
+    });
+
+/// Returns the OBJ files loaded through `tfbe_ffi_load_reference_obj` since the last call, oldest
+/// first.
+pub fn take_reference_objs() -> Vec<String> {
+    LOADED_REFERENCE_OBJS.1.lock().unwrap().try_iter().collect()
+}
 
 #[wasm_bindgen::prelude::wasm_bindgen]
 extern "C" {
     /// Send a message to the client.
     pub fn tfbe_ffi_alert(s: &str);
+
+    /// Report an error in a loaded file to the client, with the 1-based line it was found on, or
+    /// 0 if the line is not known.
+    pub fn tfbe_ffi_report_error(message: &str, line: u32);
+
+    /// Hand a file to the client to save, such as an exported map.
+    pub fn tfbe_ffi_save_file(file_name: &str, contents: &str);
 }
 
 #[wasm_bindgen::prelude::wasm_bindgen]
@@ -15,11 +63,140 @@ pub fn tfbe_ffi_load_file(file_contents: &str) {
 
     match parsed_file {
         Ok(parsed_file) => {
-            *EDITABLE_LEVEL.lock().unwrap() = Some(parsed_file);
+            // The receiver lives as long as the static, so sending can't fail.
+            LOADED_LEVELS.0.send(parsed_file).unwrap();
             tfbe_ffi_alert("Loaded file!");
         }
         Err(err) => {
-            tfbe_ffi_alert(&format!("Failed to parse file: {err}"));
+            // The client shows the error itself, so it isn't also sent with `tfbe_ffi_alert`.
+            match find_vmf_syntax_error(file_contents) {
+                Some((line, problem)) => tfbe_ffi_report_error(&format!("{problem} ({err})"), line),
+                None => tfbe_ffi_report_error(&err.to_string(), 0),
+            }
+        }
+    }
+}
+
+/// Scans the text of a VMF file for the first syntax error, returning its 1-based line and a
+/// description. `vmf_forge` errors don't say where the problem is, so this points mappers at the
+/// right line.
+///
+/// VMF files are blocks (`name { ... }`) of `"key" "value"` lines, with `//` comments.
+fn find_vmf_syntax_error(text: &str) -> Option<(u32, &'static str)> {
+    // The lines that each currently open block started on.
+    let mut open_blocks: Vec<u32> = Vec::new();
+    // Set after a block name, which must be followed by `{`.
+    let mut expecting_block_start: Option<u32> = None;
+
+    for (line_index, line) in text.lines().enumerate() {
+        let line_number = line_index as u32 + 1;
+        let mut rest = line.trim_start();
+        // The number of quoted strings so far on this line.
+        let mut strings = 0;
+        while !rest.is_empty() {
+            if rest.starts_with("//") {
+                break;
+            }
+            if expecting_block_start.is_some() && !rest.starts_with('{') {
+                return Some((line_number, "Expected `{` after block name"));
+            }
+            if let Some(after) = rest.strip_prefix('"') {
+                let Some(end) = after.find('"') else {
+                    return Some((line_number, "Unterminated string"));
+                };
+                strings += 1;
+                if strings > 2 {
+                    return Some((line_number, "Too many strings for a key and value"));
+                }
+                rest = &after[end + 1..];
+            } else if let Some(after) = rest.strip_prefix('{') {
+                if expecting_block_start.take().is_none() {
+                    return Some((line_number, "Block has no name"));
+                }
+                open_blocks.push(line_number);
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix('}') {
+                if open_blocks.pop().is_none() {
+                    return Some((line_number, "Unmatched `}`"));
+                }
+                rest = after;
+            } else {
+                let name_len = rest
+                    .find(|c: char| c.is_whitespace() || c == '{' || c == '}' || c == '"')
+                    .unwrap_or(rest.len());
+                if name_len == 0 || strings > 0 {
+                    return Some((line_number, "Unexpected text"));
+                }
+                expecting_block_start = Some(line_number);
+                rest = &rest[name_len..];
+            }
+            rest = rest.trim_start();
+        }
+        if strings == 1 {
+            return Some((line_number, "Key has no value"));
         }
     }
+
+    if let Some(block_line) = expecting_block_start {
+        return Some((block_line, "Expected `{` after block name"));
+    }
+    open_blocks
+        .last()
+        .map(|&block_line| (block_line, "Block is never closed"))
+}
+
+/// Loads an OBJ file to display as non-editable reference geometry, replacing any previous one.
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn tfbe_ffi_load_reference_obj(file_contents: &str) {
+    tfbe_ffi_alert(&format!(
+        "Loading reference OBJ with {} bytes",
+        file_contents.len()
+    ));
+
+    // The receiver lives as long as the static, so sending can't fail.
+    LOADED_REFERENCE_OBJS
+        .0
+        .send(file_contents.to_string())
+        .unwrap();
+}
+
+/// Asks for the editor's buildings as a VMF map. The map is handed to the client with
+/// `tfbe_ffi_save_file` once the editor has handled the request, on the next frame.
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn tfbe_ffi_export_file() {
+    // The receiver lives as long as the static, so sending can't fail.
+    EXPORT_REQUESTS.0.send(()).unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_an_unbalanced_brace() {
+        let text = "world\n{\n\t\"id\" \"1\"\n\tsolid\n\t{\n\t\t\"id\" \"2\"\n}\n";
+        assert_eq!(
+            find_vmf_syntax_error(text),
+            Some((2, "Block is never closed"))
+        );
+        assert_eq!(
+            find_vmf_syntax_error("world\n{\n}\n}\n"),
+            Some((4, "Unmatched `}`"))
+        );
+    }
+
+    #[test]
+    fn finds_an_unterminated_string() {
+        let text = "world\n{\n\t\"id\" \"1\n}\n";
+        assert_eq!(
+            find_vmf_syntax_error(text),
+            Some((3, "Unterminated string"))
+        );
+    }
+
+    #[test]
+    fn finds_nothing_in_a_well_formed_file() {
+        let text = "// A comment\nworld\n{\n\t\"id\" \"1\"\n\tsolid\n\t{\n\t}\n}\n";
+        assert_eq!(find_vmf_syntax_error(text), None);
+    }
 }
@@ -0,0 +1,172 @@
+use bevy::prelude::*;
+
+/// The keys which trigger editor actions, so that they can be remapped (e.g. for non-QWERTY
+/// layouts).
+///
+/// Modifier keys (`Ctrl`, `Shift`, and `Alt`) are not remappable.
+#[derive(Resource, Clone, Debug)]
+pub struct Keybindings {
+    /// Switches to the `EditorTool::SelectBuilding` tool.
+    pub tool_select: KeyCode,
+    /// Switches to the `EditorTool::CreateBuilding` tool.
+    pub tool_create: KeyCode,
+    /// Switches to the `EditorTool::DeleteBuilding` tool.
+    pub tool_delete: KeyCode,
+    /// Switches to the `EditorTool::Sightline` tool.
+    pub tool_sightline: KeyCode,
+    /// Switches to the `EditorTool::SelectFace` tool.
+    pub tool_select_face: KeyCode,
+    /// Switches to the `EditorTool::EditVoxels` tool.
+    pub tool_voxels: KeyCode,
+
+    /// Abandons the building being drawn, or the point being dragged.
+    pub cancel: KeyCode,
+    /// Deletes the current selection.
+    pub delete_selected: KeyCode,
+    /// Undoes the last edit when pressed with `Ctrl`, or redoes it with `Ctrl+Shift`.
+    pub undo: KeyCode,
+    /// Copies the selected building when pressed with `Ctrl`.
+    pub copy: KeyCode,
+    /// Pastes the copied building when pressed with `Ctrl`.
+    pub paste: KeyCode,
+    /// Halves the grid snapping step.
+    pub snap_finer: KeyCode,
+    /// Doubles the grid snapping step.
+    pub snap_coarser: KeyCode,
+    /// Cycles the symmetry applied to voxel edits.
+    pub cycle_symmetry: KeyCode,
+    /// Raises the floor of the hovered building, or its ceiling when pressed with `Shift`.
+    pub raise_floor: KeyCode,
+    /// Lowers the floor of the hovered building, or its ceiling when pressed with `Shift`.
+    pub lower_floor: KeyCode,
+    /// Rotates the selected buildings a quarter turn clockwise.
+    pub rotate_building: KeyCode,
+    /// Cycles the limit on the number of points per building.
+    pub cycle_vertex_limit: KeyCode,
+
+    /// Extrudes the selected voxel faces by one voxel.
+    pub voxel_extrude: KeyCode,
+    /// Pushes the selected voxel faces in by one voxel, keeping a rim around them.
+    pub voxel_depress: KeyCode,
+    /// Deletes the selected voxels.
+    pub voxel_delete: KeyCode,
+    /// Shifts the columns of the selected voxels down.
+    pub shift_column_down: KeyCode,
+    /// Shifts the columns of the selected voxels up.
+    pub shift_column_up: KeyCode,
+    /// Fills the brush around each selected voxel, or erases it when pressed with `Alt`.
+    pub voxel_fill: KeyCode,
+    /// Recolors the connected region of each selected voxel with the current palette material.
+    pub voxel_flood_fill: KeyCode,
+    /// Grows the voxel brush.
+    pub brush_larger: KeyCode,
+    /// Shrinks the voxel brush.
+    pub brush_smaller: KeyCode,
+    /// Switches the voxel brush between a cube and a sphere.
+    pub brush_shape: KeyCode,
+    /// Selects each entry of the voxel palette, in order.
+    pub palette: [KeyCode; 9],
+
+    pub camera_forward: KeyCode,
+    pub camera_back: KeyCode,
+    pub camera_left: KeyCode,
+    pub camera_right: KeyCode,
+
+    pub toggle_interior_outlines: KeyCode,
+    pub toggle_tooltip: KeyCode,
+    pub toggle_preview_stats: KeyCode,
+    pub toggle_status_line: KeyCode,
+    pub toggle_leaks: KeyCode,
+    pub toggle_manifold_defects: KeyCode,
+    /// Shows or hides the grid drawn on the ground.
+    pub toggle_grid: KeyCode,
+    /// Shows or hides the x-ray overlay of building outlines and previews.
+    pub toggle_xray: KeyCode,
+    /// Makes the x-ray overlay more transparent.
+    pub xray_dimmer: KeyCode,
+    /// Makes the x-ray overlay more opaque.
+    pub xray_brighter: KeyCode,
+    /// Switches voxels between solid meshes and wireframe outlines.
+    pub toggle_voxel_wireframe: KeyCode,
+
+    pub toggle_reference: KeyCode,
+    pub reference_dimmer: KeyCode,
+    pub reference_brighter: KeyCode,
+
+    /// Exports the world geometry as an OBJ file.
+    pub export_obj: KeyCode,
+    /// Exports the buildings as a VMF map.
+    pub export_vmf: KeyCode,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        Self {
+            tool_select: KeyCode::Digit1,
+            tool_create: KeyCode::Digit2,
+            tool_delete: KeyCode::Digit3,
+            tool_sightline: KeyCode::Digit4,
+            tool_select_face: KeyCode::Digit5,
+            tool_voxels: KeyCode::Digit6,
+
+            cancel: KeyCode::Escape,
+            delete_selected: KeyCode::Delete,
+            undo: KeyCode::KeyZ,
+            copy: KeyCode::KeyC,
+            paste: KeyCode::KeyV,
+            snap_finer: KeyCode::BracketLeft,
+            snap_coarser: KeyCode::BracketRight,
+            cycle_symmetry: KeyCode::KeyM,
+            raise_floor: KeyCode::PageUp,
+            lower_floor: KeyCode::PageDown,
+            rotate_building: KeyCode::KeyR,
+            cycle_vertex_limit: KeyCode::KeyL,
+
+            voxel_extrude: KeyCode::KeyE,
+            voxel_depress: KeyCode::KeyQ,
+            voxel_delete: KeyCode::Backspace,
+            shift_column_down: KeyCode::Semicolon,
+            shift_column_up: KeyCode::Quote,
+            voxel_fill: KeyCode::KeyF,
+            voxel_flood_fill: KeyCode::KeyG,
+            brush_larger: KeyCode::Period,
+            brush_smaller: KeyCode::Comma,
+            brush_shape: KeyCode::KeyB,
+            palette: [
+                KeyCode::Numpad1,
+                KeyCode::Numpad2,
+                KeyCode::Numpad3,
+                KeyCode::Numpad4,
+                KeyCode::Numpad5,
+                KeyCode::Numpad6,
+                KeyCode::Numpad7,
+                KeyCode::Numpad8,
+                KeyCode::Numpad9,
+            ],
+
+            camera_forward: KeyCode::KeyW,
+            camera_back: KeyCode::KeyS,
+            camera_left: KeyCode::KeyA,
+            camera_right: KeyCode::KeyD,
+
+            toggle_interior_outlines: KeyCode::F1,
+            toggle_tooltip: KeyCode::F2,
+            toggle_preview_stats: KeyCode::F3,
+            toggle_status_line: KeyCode::F9,
+            toggle_leaks: KeyCode::F4,
+            toggle_manifold_defects: KeyCode::F5,
+            toggle_grid: KeyCode::F8,
+            toggle_xray: KeyCode::KeyX,
+            xray_dimmer: KeyCode::Digit9,
+            xray_brighter: KeyCode::Digit0,
+            toggle_voxel_wireframe: KeyCode::F10,
+
+            toggle_reference: KeyCode::F6,
+            reference_dimmer: KeyCode::Minus,
+            reference_brighter: KeyCode::Equal,
+
+            export_obj: KeyCode::F7,
+            export_vmf: KeyCode::F11,
+        }
+    }
+}
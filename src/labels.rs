@@ -0,0 +1,96 @@
+use bevy::{prelude::*, ui::UiSystem};
+
+use crate::flycam::CameraControls;
+
+/// Text labels anchored to points in the world.
+///
+/// Like gizmos, labels only last for a single frame, so they must be added every frame they
+/// should be visible.
+#[derive(Resource, Default)]
+pub struct WorldLabels {
+    labels: Vec<WorldLabel>,
+}
+
+struct WorldLabel {
+    position: Vec3,
+    text: String,
+    color: Color,
+}
+
+impl WorldLabels {
+    /// Draws `text` at a world-space position for this frame.
+    pub fn add(&mut self, position: Vec3, text: impl Into<String>, color: Color) {
+        self.labels.push(WorldLabel {
+            position,
+            text: text.into(),
+            color,
+        });
+    }
+}
+
+pub struct WorldLabelPlugin;
+
+impl Plugin for WorldLabelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WorldLabels>().add_systems(
+            PostUpdate,
+            draw_world_labels_system.before(UiSystem::Layout),
+        );
+    }
+}
+
+/// A UI text node used to display a world label.
+#[derive(Component)]
+struct WorldLabelNode;
+
+/// Positions a pool of UI text nodes over the world labels added this frame.
+fn draw_world_labels_system(
+    mut commands: Commands,
+    mut world_labels: ResMut<WorldLabels>,
+    camera: Query<(&Camera, &GlobalTransform), With<CameraControls>>,
+    mut nodes: Query<(&mut Text, &mut TextColor, &mut Node, &mut Visibility), With<WorldLabelNode>>,
+) {
+    let labels = std::mem::take(&mut world_labels.labels);
+    let Ok((camera, camera_transform)) = camera.single() else {
+        return;
+    };
+
+    let mut on_screen = labels.into_iter().filter_map(|label| {
+        let viewport = camera
+            .world_to_viewport(camera_transform, label.position)
+            .ok()?;
+        Some((viewport, label))
+    });
+
+    for (mut text, mut text_color, mut node, mut visibility) in nodes.iter_mut() {
+        let Some((viewport, label)) = on_screen.next() else {
+            *visibility = Visibility::Hidden;
+            continue;
+        };
+        *visibility = Visibility::Inherited;
+        node.left = Val::Px(viewport.x);
+        node.top = Val::Px(viewport.y);
+        text.0 = label.text;
+        text_color.0 = label.color;
+    }
+
+    // Grow the pool for any labels that didn't fit.
+    for (viewport, label) in on_screen {
+        commands.spawn((
+            WorldLabelNode,
+            Text::new(label.text),
+            TextFont {
+                font_size: 12.,
+                ..default()
+            },
+            TextColor(label.color),
+            Node {
+                position_type: PositionType::Absolute,
+                left: Val::Px(viewport.x),
+                top: Val::Px(viewport.y),
+                ..default()
+            },
+            Pickable::IGNORE,
+        ));
+    }
+}
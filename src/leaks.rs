@@ -0,0 +1,268 @@
+use std::collections::VecDeque;
+
+use bevy::{platform::collections::HashSet, prelude::*};
+
+use crate::building::{Building, SHELL_SLAB_THICKNESS};
+use crate::debug_flags::DebugFlags;
+use crate::editor_state::{EditorWorld, grid_to_world};
+use crate::geometry_utils::{offset_polygon, point_closest_to_segment, point_in_polygon};
+
+/// The horizontal size of a flood-fill cell, in grid units.
+const CELL_SIZE_XZ: f32 = 0.25;
+/// The vertical size of a flood-fill cell, in grid units.
+const CELL_SIZE_Y: f32 = 0.5;
+
+pub struct LeakCheckPlugin;
+
+impl Plugin for LeakCheckPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Leaks>()
+            .add_systems(Update, (find_leaks_system, draw_leaks_system).chain());
+    }
+}
+
+/// The grid cells where a room interior is open to the outside of the level.
+#[derive(Resource, Default)]
+pub struct Leaks {
+    pub cells: Vec<IVec3>,
+}
+
+/// The solid shell of a building, in grid units.
+struct BuildingShell {
+    floor: f32,
+    ceiling: f32,
+    outline: Vec<Vec2>,
+    /// The outlines of the courtyards, which are open to the sky.
+    holes: Vec<Vec<Vec2>>,
+    interior: Vec<Vec2>,
+    /// The courtyards grown by the thickness of their walls, which are cut out of `interior`.
+    interior_holes: Vec<Vec<Vec2>>,
+}
+
+impl BuildingShell {
+    fn new(building: &Building) -> Self {
+        Self {
+            floor: building.floor_y() as f32,
+            ceiling: building.ceiling_y() as f32,
+            outline: building.points().iter().map(|p| p.as_vec2()).collect(),
+            holes: building
+                .holes()
+                .iter()
+                .map(|hole| hole.iter().map(|p| p.as_vec2()).collect())
+                .collect(),
+            interior: offset_polygon(building.points(), building.interior_offset()),
+            // Holes are wound clockwise, so the same offset grows them into the room.
+            interior_holes: building
+                .holes()
+                .iter()
+                .map(|hole| offset_polygon(hole, building.interior_offset()))
+                .collect(),
+        }
+    }
+
+    /// Returns whether the point is inside the room's interior, which doesn't include the
+    /// courtyards or their walls.
+    fn in_interior(&self, p: Vec3) -> bool {
+        p.y >= self.floor
+            && p.y <= self.ceiling
+            && point_in_polygon(p.xz(), &self.interior)
+            && !self
+                .interior_holes
+                .iter()
+                .any(|hole| point_in_polygon(p.xz(), hole))
+    }
+
+    /// Returns whether the point is inside the outer shell of the room. The courtyards are not part
+    /// of the shell, but their walls are.
+    ///
+    /// The shell is grown by one cell so that walls thinner than a cell still block the flood fill.
+    fn in_shell(&self, p: Vec3) -> bool {
+        let margin_y = SHELL_SLAB_THICKNESS + CELL_SIZE_Y;
+        if p.y < self.floor - margin_y || p.y > self.ceiling + margin_y {
+            return false;
+        }
+        if point_in_polygon(p.xz(), &self.outline)
+            && !self.holes.iter().any(|hole| point_in_polygon(p.xz(), hole))
+        {
+            return true;
+        }
+        std::iter::once(&self.outline)
+            .chain(self.holes.iter())
+            .any(|ring| {
+                (0..ring.len()).any(|i| {
+                    let edge = (ring[i], ring[(i + 1) % ring.len()]);
+                    point_closest_to_segment(p.xz(), edge).distance(p.xz()) < CELL_SIZE_XZ
+                })
+            })
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq)]
+enum Cell {
+    Open,
+    Wall,
+    Interior,
+}
+
+/// Flood-fills the space around the buildings from the edge of the level, and returns the grid
+/// cells of any room interior that the fill reaches.
+///
+/// An empty result means that every room is sealed.
+pub fn find_leaks(buildings: &[Building]) -> Vec<IVec3> {
+    if buildings.is_empty() {
+        return Vec::new();
+    }
+
+    let shells: Vec<BuildingShell> = buildings.iter().map(BuildingShell::new).collect();
+
+    // Surround the buildings with at least one voxel of open space.
+    let mut min = Vec3::splat(f32::INFINITY);
+    let mut max = Vec3::splat(f32::NEG_INFINITY);
+    for shell in shells.iter() {
+        for p in shell.outline.iter() {
+            min = min.min(Vec3::new(p.x, shell.floor, p.y));
+            max = max.max(Vec3::new(p.x, shell.ceiling, p.y));
+        }
+    }
+    let min = min - Vec3::ONE;
+    let max = max + Vec3::ONE;
+
+    let cell_size = Vec3::new(CELL_SIZE_XZ, CELL_SIZE_Y, CELL_SIZE_XZ);
+    let counts = ((max - min) / cell_size).ceil().as_ivec3();
+    let index_of = |c: IVec3| ((c.y * counts.z + c.z) * counts.x + c.x) as usize;
+    let center_of = |c: IVec3| min + (c.as_vec3() + Vec3::splat(0.5)) * cell_size;
+
+    let mut cells = vec![Cell::Open; (counts.x * counts.y * counts.z) as usize];
+    for y in 0..counts.y {
+        for z in 0..counts.z {
+            for x in 0..counts.x {
+                let c = IVec3::new(x, y, z);
+                let p = center_of(c);
+                // Interiors are carved out of every shell, including those of other buildings.
+                cells[index_of(c)] = if shells.iter().any(|shell| shell.in_interior(p)) {
+                    Cell::Interior
+                } else if shells.iter().any(|shell| shell.in_shell(p)) {
+                    Cell::Wall
+                } else {
+                    Cell::Open
+                };
+            }
+        }
+    }
+
+    // Flood from every open cell on the boundary of the region.
+    let mut visited = vec![false; cells.len()];
+    let mut queue: VecDeque<IVec3> = VecDeque::new();
+    for y in 0..counts.y {
+        for z in 0..counts.z {
+            for x in 0..counts.x {
+                let c = IVec3::new(x, y, z);
+                let on_boundary = c.cmpeq(IVec3::ZERO).any() || c.cmpeq(counts - 1).any();
+                if on_boundary && cells[index_of(c)] != Cell::Wall {
+                    visited[index_of(c)] = true;
+                    queue.push_back(c);
+                }
+            }
+        }
+    }
+
+    let mut leaks: HashSet<IVec3> = HashSet::new();
+    while let Some(c) = queue.pop_front() {
+        if cells[index_of(c)] == Cell::Interior {
+            leaks.insert(center_of(c).round().as_ivec3());
+        }
+        for d in [
+            IVec3::X,
+            IVec3::Y,
+            IVec3::Z,
+            IVec3::NEG_X,
+            IVec3::NEG_Y,
+            IVec3::NEG_Z,
+        ] {
+            let n = c + d;
+            if n.cmplt(IVec3::ZERO).any() || n.cmpge(counts).any() {
+                continue;
+            }
+            let n_index = index_of(n);
+            if visited[n_index] || cells[n_index] == Cell::Wall {
+                continue;
+            }
+            visited[n_index] = true;
+            queue.push_back(n);
+        }
+    }
+
+    let mut leaks: Vec<IVec3> = leaks.into_iter().collect();
+    leaks.sort_by_key(|p| (p.x, p.y, p.z));
+    leaks
+}
+
+fn find_leaks_system(
+    editor_world: Res<EditorWorld>,
+    debug_flags: Res<DebugFlags>,
+    mut leaks: ResMut<Leaks>,
+) {
+    if !debug_flags.show_leaks {
+        return;
+    }
+    if !editor_world.is_changed() && !debug_flags.is_changed() {
+        return;
+    }
+    leaks.cells = find_leaks(editor_world.buildings());
+}
+
+fn draw_leaks_system(mut gizmos: Gizmos, debug_flags: Res<DebugFlags>, leaks: Res<Leaks>) {
+    if !debug_flags.show_leaks {
+        return;
+    }
+    for &cell in leaks.cells.iter() {
+        gizmos.sphere(grid_to_world(cell), 24., Color::linear_rgb(1., 0., 0.));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An 8 by 8 room with a 2 by 2 courtyard in the middle.
+    fn courtyard_room() -> Building {
+        let mut building = Building::new(
+            0,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(8, 0),
+                IVec2::new(8, 8),
+                IVec2::new(0, 8),
+            ],
+        );
+        assert!(building.add_hole(vec![
+            IVec2::new(3, 3),
+            IVec2::new(3, 5),
+            IVec2::new(5, 5),
+            IVec2::new(5, 3),
+        ]));
+        building
+    }
+
+    #[test]
+    fn courtyards_are_not_leaks() {
+        assert_eq!(find_leaks(&[courtyard_room()]), Vec::<IVec3>::new());
+    }
+
+    #[test]
+    fn courtyards_are_open_and_their_walls_are_solid() {
+        let shell = BuildingShell::new(&courtyard_room());
+        let y = 1.;
+
+        let in_room = Vec3::new(1., y, 1.);
+        assert!(shell.in_interior(in_room));
+
+        let in_courtyard = Vec3::new(4., y, 4.);
+        assert!(!shell.in_interior(in_courtyard));
+        assert!(!shell.in_shell(in_courtyard));
+
+        let in_courtyard_wall = Vec3::new(3., y, 4.);
+        assert!(!shell.in_interior(in_courtyard_wall));
+        assert!(shell.in_shell(in_courtyard_wall));
+    }
+}
@@ -1,33 +1,63 @@
 #![allow(clippy::too_many_arguments)]
 
 use bevy::{
+    platform::collections::{HashMap, HashSet},
     prelude::*,
     render::{mesh::Indices, view::RenderLayers},
+    tasks::{AsyncComputeTaskPool, Task, block_on, futures_lite::future},
 };
 use common_assets::Common;
 use csgrs::{csg::CSG as GenericCSG, polygon::Polygon};
 use flycam::CameraControls;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 pub type CSG = GenericCSG<SurfaceDetail>;
 
 use voxels::VOXEL_SIZE;
 
 use crate::{
+    building::{Building, SHELL_SLAB_THICKNESS},
+    csg_progress::CsgBuildProgress,
+    debug_flags::{CsgShells, DebugFlags},
     editor_state::{EditorWorld, from_flat, grid_to_world},
-    geometry_utils::BevyToNalgebra,
+    geometry_utils::{BevyToNalgebra, offset_polygon},
+    keybindings::Keybindings,
+    labels::WorldLabels,
 };
 pub mod building;
 pub mod common_assets;
+pub mod csg_progress;
+pub mod debug_flags;
 pub mod editor_actions;
 pub mod editor_state;
+pub mod export;
 pub mod flycam;
 pub mod geometry_utils;
+pub mod headless;
 pub mod js_ffi;
+pub mod keybindings;
+pub mod labels;
+pub mod leaks;
+pub mod manifold;
 pub mod preview;
+pub mod reference;
+pub mod status_line;
+pub mod tooltip;
+pub mod vmf_import;
 pub mod voxel_editor;
 pub mod voxels;
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(result) = headless::run(&args) {
+        if let Err(err) = result {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
     App::new()
         .add_plugins(DefaultPlugins.set(AssetPlugin {
             // Wasm builds will check for meta files (that don't exist) if this isn't set.
@@ -36,28 +66,139 @@ fn main() {
             meta_check: bevy::asset::AssetMetaCheck::Never,
             ..default()
         }))
+        .init_resource::<keybindings::Keybindings>()
+        .init_resource::<CsgRebuildSettings>()
+        .init_resource::<GridSettings>()
+        .init_resource::<XRaySettings>()
         .add_plugins(common_assets::CommonPlugin)
+        .add_plugins(debug_flags::DebugFlagsPlugin)
         .add_plugins(crate::editor_actions::EditorActionPlugin)
+        .add_plugins(tooltip::TooltipPlugin)
+        .add_plugins(labels::WorldLabelPlugin)
+        .add_plugins(leaks::LeakCheckPlugin)
+        .add_plugins(manifold::ManifoldCheckPlugin)
+        .add_plugins(reference::ReferenceGeometryPlugin)
+        .add_plugins(export::ExportPlugin)
+        .add_plugins(vmf_import::VmfImportPlugin)
+        .add_plugins(preview::PreviewStatsPlugin)
+        .add_plugins(status_line::StatusLinePlugin)
+        .add_plugins(csg_progress::CsgProgressPlugin)
+        .add_plugins(voxel_editor::VoxelEditorPlugin)
         .add_systems(Startup, setup)
+        .add_systems(
+            Startup,
+            spawn_ground_plane_system.after(common_assets::setup_common),
+        )
         .add_systems(
             Update,
             (
                 draw_grid_system,
+                draw_axes_system,
+                draw_grid_labels_system,
                 draw_building_outlines_system,
+                draw_interior_outlines_system,
                 render_world_system,
                 debug_csg_system,
             )
                 .chain(),
         )
+        .add_systems(
+            Update,
+            (xray_settings_system, apply_xray_settings_system).chain(),
+        )
         .add_plugins(flycam::FlyCameraPlugin)
         .run();
 }
 
-fn draw_grid_system(mut gizmos: Gizmos) {
-    for x in -20..=20 {
-        for z in -20..=20 {
+/// The width of the ground plane, in voxels.
+const GROUND_PLANE_SIZE: f32 = 256.;
+
+/// Spawns a ground plane textured with the grid, tiled once per voxel.
+///
+/// The plane sits just below y = 0, so that it doesn't z-fight with building floors.
+fn spawn_ground_plane_system(
+    mut commands: Commands,
+    common: Res<Common>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let Some(gray_material) = materials.get(&common.gray_material).cloned() else {
+        return;
+    };
+    // The plane mesh has UVs from 0 to 1, so scale them to repeat the grid for each voxel.
+    let ground_material = materials.add(StandardMaterial {
+        uv_transform: bevy::math::Affine2::from_scale(Vec2::splat(GROUND_PLANE_SIZE)),
+        ..gray_material
+    });
+
+    commands.spawn((
+        Mesh3d(common.plane_mesh.clone()),
+        MeshMaterial3d(ground_material),
+        // The plane mesh faces +Z, so turn it to face up.
+        Transform::from_xyz(0., -1., 0.)
+            .with_rotation(Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2))
+            .with_scale(Vec3::new(
+                GROUND_PLANE_SIZE * VOXEL_SIZE,
+                GROUND_PLANE_SIZE * VOXEL_SIZE,
+                1.,
+            )),
+        Pickable::IGNORE,
+    ));
+}
+
+/// Controls the grid of crosses drawn on the ground.
+#[derive(Resource)]
+pub struct GridSettings {
+    pub enabled: bool,
+    /// How many grid cells the grid extends from the origin along each axis, at least.
+    pub half_extent: i32,
+    /// Half the length of each line of the crosses drawn at each grid point.
+    pub line_length: f32,
+}
+
+impl Default for GridSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            half_extent: 20,
+            line_length: 8.,
+        }
+    }
+}
+
+/// How many grid cells the grid extends past the furthest building.
+const GRID_BUILDING_MARGIN: i32 = 4;
+
+/// The furthest the grid grows to cover buildings, in grid cells from the origin along each axis,
+/// so that a far-away building can't make the grid draw millions of lines.
+const GRID_MAX_HALF_EXTENT: i32 = 200;
+
+fn draw_grid_system(
+    mut gizmos: Gizmos,
+    mut settings: ResMut<GridSettings>,
+    editor_world: Res<EditorWorld>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_grid) {
+        settings.enabled = !settings.enabled;
+    }
+    if !settings.enabled {
+        return;
+    }
+
+    // Grow the grid to cover every building, up to a limit.
+    let half_extent = editor_world
+        .buildings()
+        .iter()
+        .flat_map(|building| building.points())
+        .map(|p| p.abs().max_element().saturating_add(GRID_BUILDING_MARGIN))
+        .fold(settings.half_extent, i32::max)
+        .min(GRID_MAX_HALF_EXTENT.max(settings.half_extent));
+
+    for x in -half_extent..=half_extent {
+        for z in -half_extent..=half_extent {
             let p = Vec3::splat(VOXEL_SIZE) * Vec3::new(x as f32, 0.0, z as f32);
-            let k = 8.;
+            let k = settings.line_length;
             gizmos.line(
                 p - k * Vec3::X,
                 p + k * Vec3::X,
@@ -72,6 +213,94 @@ fn draw_grid_system(mut gizmos: Gizmos) {
     }
 }
 
+/// How many voxels long each of the coordinate axes drawn at the origin is.
+const AXES_LENGTH: i32 = 3;
+
+/// Draws the positive X (red), Y (green), and Z (blue) axes from the origin, with a tick at each
+/// voxel. They are hidden along with the grid.
+fn draw_axes_system(mut gizmos: Gizmos, settings: Res<GridSettings>) {
+    if !settings.enabled {
+        return;
+    }
+    for (axis, tick, color) in [
+        (Vec3::X, Vec3::Z, Color::linear_rgb(1., 0., 0.)),
+        (Vec3::Y, Vec3::X, Color::linear_rgb(0., 1., 0.)),
+        (Vec3::Z, Vec3::X, Color::linear_rgb(0., 0., 1.)),
+    ] {
+        gizmos.line(Vec3::ZERO, axis * AXES_LENGTH as f32 * VOXEL_SIZE, color);
+        for i in 1..=AXES_LENGTH {
+            let p = axis * i as f32 * VOXEL_SIZE;
+            let k = settings.line_length;
+            gizmos.line(p - k * tick, p + k * tick, color);
+        }
+    }
+}
+
+/// How many grid cells away from the camera the labels may be, for each grid cell between them.
+const GRID_LABEL_DISTANCE_PER_CELL: f32 = 2.;
+
+/// How many label intervals the labels reach on each side of where the camera is looking.
+const GRID_LABEL_COUNT: i32 = 3;
+
+/// Returns the spacing between grid labels, in grid cells, when the camera is `camera_distance`
+/// grid cells from them. The spacing is the snapping step times a power of two, so that labels sit
+/// on snapped points and stay roughly the same distance apart on screen as the camera zooms.
+fn grid_label_interval(camera_distance: f32, snap_step: i32) -> i32 {
+    let mut interval = snap_step.max(1);
+    while (interval as f32) < camera_distance / GRID_LABEL_DISTANCE_PER_CELL
+        && interval < i32::MAX / 2
+    {
+        interval *= 2;
+    }
+    interval
+}
+
+/// Labels grid coordinates along the X and Z axes, near where the camera is looking, more sparsely
+/// the further away the camera is.
+fn draw_grid_labels_system(
+    mut labels: ResMut<WorldLabels>,
+    editor_world: Res<EditorWorld>,
+    camera: Query<&Transform, With<CameraControls>>,
+) {
+    let Ok(camera_transform) = camera.single() else {
+        return;
+    };
+
+    // Label around the point on the ground the camera is looking at, or below the camera if it
+    // is looking up.
+    let focus = Ray3d::new(camera_transform.translation, camera_transform.forward())
+        .intersect_plane(Vec3::ZERO, InfinitePlane3d::new(Vec3::Y))
+        .map(|distance| camera_transform.translation + camera_transform.forward() * distance)
+        .unwrap_or(camera_transform.translation);
+    let camera_distance = camera_transform.translation.distance(focus) / VOXEL_SIZE;
+    let focus = (focus / VOXEL_SIZE).round().as_ivec3();
+
+    let interval = grid_label_interval(camera_distance, editor_world.snap_step());
+    let range = interval.saturating_mul(GRID_LABEL_COUNT);
+    let color = Color::linear_rgba(1., 1., 1., 0.8);
+
+    // Only label the axes that pass near the focus.
+    let x_axis_visible = focus.z.abs() <= range;
+    let z_axis_visible = focus.x.abs() <= range;
+
+    if x_axis_visible {
+        let first_x = focus.x.saturating_sub(range).div_euclid(interval) * interval;
+        for x in (first_x..=focus.x.saturating_add(range)).step_by(interval as usize) {
+            labels.add(grid_to_world(IVec3::new(x, 0, 0)), format!("x={x}"), color);
+        }
+    }
+    if z_axis_visible {
+        let first_z = focus.z.saturating_sub(range).div_euclid(interval) * interval;
+        for z in (first_z..=focus.z.saturating_add(range)).step_by(interval as usize) {
+            if z == 0 && x_axis_visible {
+                // The origin is already labeled along the X axis.
+                continue;
+            }
+            labels.add(grid_to_world(IVec3::new(0, 0, z)), format!("z={z}"), color);
+        }
+    }
+}
+
 fn draw_building_outlines_system(mut gizmos: Gizmos, editor_world: Res<EditorWorld>) {
     let color_active = Color::linear_rgb(1., 1., 0.5);
 
@@ -89,158 +318,347 @@ fn draw_building_outlines_system(mut gizmos: Gizmos, editor_world: Res<EditorWor
         }
     }
 }
+
+/// Draws the inset outline of each building's interior, where the inner wall surface lands.
+fn draw_interior_outlines_system(
+    mut gizmos: Gizmos,
+    editor_world: Res<EditorWorld>,
+    debug_flags: Res<DebugFlags>,
+) {
+    if !debug_flags.show_interior_outlines {
+        return;
+    }
+
+    let color_interior = Color::linear_rgb(0.3, 1., 0.6);
+
+    for building in editor_world.buildings().iter() {
+        let floor_y = building.floor_y() as f32 * VOXEL_SIZE;
+        let inset = offset_polygon(building.points(), building.interior_offset());
+
+        for i in 0..inset.len() {
+            let point_a = inset[i] * VOXEL_SIZE;
+            let point_b = inset[(i + 1) % inset.len()] * VOXEL_SIZE;
+            gizmos.line(
+                Vec3::new(point_a.x, floor_y, point_a.y),
+                Vec3::new(point_b.x, floor_y, point_b.y),
+                color_interior,
+            );
+        }
+    }
+}
+
+/// The world geometry, in world units.
 #[derive(Resource)]
-struct RenderedCsg(CSG);
+pub struct RenderedCsg(pub CSG);
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub struct SurfaceDetail {
     pub outside: bool,
 }
 
-fn render_world_system(world: Res<EditorWorld>, mut rendered_csg: ResMut<RenderedCsg>) {
-    if !world.is_changed() {
+/// Controls how often the world CSG is rebuilt.
+#[derive(Resource)]
+pub struct CsgRebuildSettings {
+    /// How long the world must go unchanged before the CSG is rebuilt, so that it is not rebuilt
+    /// on every frame of a drag.
+    pub debounce_seconds: f32,
+}
+
+impl Default for CsgRebuildSettings {
+    fn default() -> Self {
+        Self {
+            debounce_seconds: 0.1,
+        }
+    }
+}
+
+/// A world CSG build running in the background, started by `render_world_system`.
+struct CsgBuildTask {
+    /// Returns the built CSG, or `None` if it was cancelled, along with the updated prism cache.
+    task: Task<(Option<CSG>, BuildingPrismCache)>,
+    /// Set when the world changes, so that the build stops early and its result is discarded.
+    cancel: Arc<AtomicBool>,
+}
+
+/// Rebuilds the world CSG in a background task once the world has stopped changing, cancelling any
+/// build which the latest change has made stale.
+fn render_world_system(
+    world: Res<EditorWorld>,
+    mut rendered_csg: ResMut<RenderedCsg>,
+    mut prism_cache: Local<BuildingPrismCache>,
+    settings: Res<CsgRebuildSettings>,
+    progress: Res<CsgBuildProgress>,
+    time: Res<Time>,
+    // The time of the latest change that the rendered CSG does not include yet.
+    mut changed_at: Local<Option<f32>>,
+    mut running: Local<Option<CsgBuildTask>>,
+) {
+    let now = time.elapsed_secs();
+    if world.is_changed() {
+        *changed_at = Some(now);
+        if let Some(build) = running.as_ref() {
+            build.cancel.store(true, Ordering::Relaxed);
+        }
+    }
+
+    if let Some(build) = running.as_mut() {
+        let Some((csg, cache)) = block_on(future::poll_once(&mut build.task)) else {
+            return;
+        };
+        // The cache only holds prisms for the buildings they were built from, so it is kept even
+        // if the build was cancelled.
+        *prism_cache = cache;
+        if let Some(csg) = csg.filter(|_| !build.cancel.load(Ordering::Relaxed)) {
+            rendered_csg.0 = csg;
+        }
+        *running = None;
+        progress.set(None);
+    }
+
+    if changed_at.is_none_or(|changed_at| now - changed_at < settings.debounce_seconds) {
         return;
     }
+    *changed_at = None;
+
+    let buildings = world.buildings().to_vec();
+    let mut cache = std::mem::take(&mut *prism_cache);
+    let cancel = Arc::new(AtomicBool::new(false));
+    let task_cancel = cancel.clone();
+    let task_progress = progress.clone();
+    progress.set(Some(0.));
+    let task = AsyncComputeTaskPool::get().spawn(async move {
+        let csg = build_world_csg(
+            &buildings,
+            &mut cache,
+            |fraction| task_progress.set(Some(fraction)),
+            &task_cancel,
+        );
+        (csg, cache)
+    });
+    *running = Some(CsgBuildTask { task, cancel });
+}
 
-    let mut out_buffer_csg: Vec<CSG> = Vec::new();
-    let mut room_interior_csg: Vec<CSG> = Vec::new();
+/// The prisms built for each building by `build_world_csg`, so that only buildings which have
+/// changed since the last build need new prisms.
+#[derive(Default)]
+pub struct BuildingPrismCache {
+    /// The tessellated outer shell and room interior prisms, keyed by the building they were built
+    /// from.
+    prisms: HashMap<Building, [CSG; 2]>,
+}
+
+/// Builds the CSG for the walls, floors, and ceilings of all of the buildings, in world units.
+///
+/// The prisms for each building are reused from `cache` if the building is unchanged, and the
+/// cache is updated to hold exactly the prisms for `buildings`.
+///
+/// `progress` is called with the fraction of the work completed (from 0 to 1) as each building is
+/// added to or carved out of the world.
+///
+/// `cancel` is checked between buildings. If it is set, the build stops and returns `None`, so that
+/// a build made stale by further edits can be abandoned.
+pub fn build_world_csg(
+    buildings: &[Building],
+    cache: &mut BuildingPrismCache,
+    mut progress: impl FnMut(f32),
+    cancel: &AtomicBool,
+) -> Option<CSG> {
+    for room in buildings.iter() {
+        if cache.prisms.contains_key(room) {
+            continue;
+        }
+        let prisms = building_prisms(room).map(|csg| csg.tessellate());
+        cache.prisms.insert(room.clone(), prisms);
+
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+    }
+    let current: HashSet<&Building> = buildings.iter().collect();
+    cache
+        .prisms
+        .retain(|building, _| current.contains(building));
+
+    let out_buffer_csg: Vec<&CSG> = buildings
+        .iter()
+        .map(|room| &cache.prisms[room][0])
+        .collect();
+    let room_interior_csg: Vec<&CSG> = buildings
+        .iter()
+        .map(|room| &cache.prisms[room][1])
+        .collect();
+
+    let total_steps = (out_buffer_csg.len() + room_interior_csg.len()) as f32;
+    let mut steps = 0;
+
+    let mut world_csg: CSG = CSG::new();
+    for outer_csg in out_buffer_csg {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        world_csg = world_csg.union(outer_csg);
+        steps += 1;
+        progress(steps as f32 / total_steps);
+    }
+
+    for inner_csg in room_interior_csg {
+        if cancel.load(Ordering::Relaxed) {
+            return None;
+        }
+        world_csg = world_csg.difference(inner_csg);
+        steps += 1;
+        progress(steps as f32 / total_steps);
+    }
 
-    struct RoomLayer<'a> {
+    Some(weld_csg(&world_csg, CSG_WELD_TOLERANCE).tessellate().scale(
+        VOXEL_SIZE as f64,
+        VOXEL_SIZE as f64,
+        VOXEL_SIZE as f64,
+    ))
+}
+
+/// Builds the prisms for one building: the outer shell, which is unioned into the world, and the
+/// room interior, which is carved out of it.
+fn building_prisms(room: &Building) -> [CSG; 2] {
+    struct RoomLayer {
         shift_y_floor: f64,
         shift_y_ceiling: f64,
         outside: bool,
         wall_width: f32,
-        out: &'a mut Vec<CSG>,
     }
 
     let layers = [
         RoomLayer {
-            shift_y_floor: -0.1,
-            shift_y_ceiling: 0.1,
+            shift_y_floor: -SHELL_SLAB_THICKNESS as f64,
+            shift_y_ceiling: SHELL_SLAB_THICKNESS as f64,
             wall_width: 0.,
             outside: true,
-            out: &mut out_buffer_csg,
         },
         RoomLayer {
             shift_y_floor: 0.0,
             shift_y_ceiling: 0.0,
-            wall_width: -0.1,
+            wall_width: room.interior_offset(),
             outside: false,
-            out: &mut room_interior_csg,
         },
     ];
 
-    for layer in layers {
-        for room in world.buildings().iter() {
-            let y_top = (room.floor_y() + 2) as f64 + layer.shift_y_ceiling;
-            let y_bot = room.floor_y() as f64 + layer.shift_y_floor;
-            let points = room.points();
-            let mut polygons: Vec<csgrs::polygon::Polygon<SurfaceDetail>> = Vec::new();
+    /// Builds a vertical prism over the counterclockwise `points`, between `y_bot` and `y_top`.
+    fn prism(points: &[Vec2], y_bot: f64, y_top: f64, outside: bool) -> CSG {
+        let mut polygons: Vec<csgrs::polygon::Polygon<SurfaceDetail>> = Vec::new();
 
-            fn from_flat(v: Vec2, y: f64) -> Vec3 {
-                Vec3::new(v.x, y as f32, v.y)
-            }
+        fn from_flat(v: Vec2, y: f64) -> Vec3 {
+            Vec3::new(v.x, y as f32, v.y)
+        }
 
-            let shifted_points: Vec<Vec2> = points
+        for (y, flip) in [(y_bot, false), (y_top, true)] {
+            let mut vertices: Vec<csgrs::vertex::Vertex> = points
                 .iter()
-                .enumerate()
-                .map(|(i, p)| {
-                    let center = p.as_vec2();
-                    let next = points[(i + 1) % points.len()].as_vec2();
-                    let prev = points[(i + points.len() - 1) % points.len()].as_vec2();
-
-                    let delta_next = next - center;
-                    let delta_prev = prev - center;
-                    // let angle_next = delta_next
-                    let angle_next = delta_next.to_angle();
-                    let mut angle_prev = delta_prev.to_angle();
-                    if angle_prev < angle_next {
-                        angle_prev += std::f32::consts::PI * 2.;
-                    }
-
-                    let angle_middle = (angle_next + angle_prev) / 2.;
-                    let offset = Vec2::from_angle(angle_middle) * layer.wall_width
-                        / ((angle_next - angle_prev) / 2.).sin();
-
-                    center + offset
+                .map(|p: &Vec2| {
+                    csgrs::vertex::Vertex::new(
+                        from_flat(*p, y).to_point(),
+                        if flip { Vec3::Y } else { Vec3::NEG_Y }.to_vector(),
+                    )
                 })
-                .collect::<Vec<Vec2>>();
-
-            for (y, flip) in [(y_bot, false), (y_top, true)] {
-                let mut vertices: Vec<csgrs::vertex::Vertex> = shifted_points
-                    .iter()
-                    .map(|p: &Vec2| {
-                        csgrs::vertex::Vertex::new(
-                            from_flat(*p, y).to_point(),
-                            if flip { Vec3::Y } else { Vec3::NEG_Y }.to_vector(),
-                        )
-                    })
-                    .collect();
-
-                if flip {
-                    vertices.reverse();
-                }
+                .collect();
 
-                polygons.push(Polygon::new(
-                    vertices,
-                    Some(SurfaceDetail {
-                        outside: layer.outside,
-                    }),
-                ));
+            if flip {
+                vertices.reverse();
             }
 
-            for i in 0..shifted_points.len() {
-                let a = shifted_points[i];
-                let b = shifted_points[(i + 1) % shifted_points.len()];
-
-                let a0 = from_flat(a, y_bot);
-                let b0 = from_flat(b, y_bot);
-                let a1 = from_flat(a, y_top);
-                let b1 = from_flat(b, y_top);
-
-                let normal = (b0 - a0).cross(a1 - a0).normalize().to_vector();
-
-                polygons.push(Polygon::new(
-                    vec![
-                        csgrs::vertex::Vertex::new(a0.to_point(), normal),
-                        csgrs::vertex::Vertex::new(a1.to_point(), normal),
-                        csgrs::vertex::Vertex::new(b1.to_point(), normal),
-                        csgrs::vertex::Vertex::new(b0.to_point(), normal),
-                    ],
-                    Some(SurfaceDetail {
-                        outside: layer.outside,
-                    }),
-                ));
-            }
+            polygons.push(Polygon::new(vertices, Some(SurfaceDetail { outside })));
+        }
 
-            layer.out.push(CSG::from_polygons(&polygons));
+        for i in 0..points.len() {
+            let a = points[i];
+            let b = points[(i + 1) % points.len()];
+
+            let a0 = from_flat(a, y_bot);
+            let b0 = from_flat(b, y_bot);
+            let a1 = from_flat(a, y_top);
+            let b1 = from_flat(b, y_top);
+
+            let normal = (b0 - a0).cross(a1 - a0).normalize().to_vector();
+
+            polygons.push(Polygon::new(
+                vec![
+                    csgrs::vertex::Vertex::new(a0.to_point(), normal),
+                    csgrs::vertex::Vertex::new(a1.to_point(), normal),
+                    csgrs::vertex::Vertex::new(b1.to_point(), normal),
+                    csgrs::vertex::Vertex::new(b0.to_point(), normal),
+                ],
+                Some(SurfaceDetail { outside }),
+            ));
         }
-    }
 
-    let mut world_csg: CSG = CSG::new();
-    for outer_csg in &out_buffer_csg {
-        world_csg = world_csg.union(&outer_csg.tessellate());
+        CSG::from_polygons(&polygons)
     }
 
-    for inner_csg in &room_interior_csg {
-        world_csg = world_csg.difference(&inner_csg.tessellate());
-    }
+    layers.map(|layer| {
+        let y_top = room.ceiling_y() as f64 + layer.shift_y_ceiling;
+        let y_bot = room.floor_y() as f64 + layer.shift_y_floor;
 
-    rendered_csg.0 =
-        world_csg
-            .tessellate()
-            .scale(VOXEL_SIZE as f64, VOXEL_SIZE as f64, VOXEL_SIZE as f64);
+        let shifted_points: Vec<Vec2> = offset_polygon(room.points(), layer.wall_width);
+        let mut room_csg = prism(&shifted_points, y_bot, y_top, layer.outside);
+
+        // Holes are wound clockwise, so the same offset moves their walls into the room.
+        // They extend past the room so that no faces are coplanar with its floor or ceiling.
+        for hole in room.holes() {
+            let mut hole_points: Vec<Vec2> = offset_polygon(hole, layer.wall_width);
+            hole_points.reverse();
+            let hole_csg = prism(&hole_points, y_bot - 1.0, y_top + 1.0, layer.outside);
+            room_csg = room_csg.difference(&hole_csg);
+        }
+
+        room_csg
+    })
+}
+
+/// The grid (in grid units) that world CSG vertices are snapped to, so that vertices shared by
+/// adjacent buildings are exactly coincident.
+const CSG_WELD_TOLERANCE: f64 = 1.0 / 1024.0;
+
+/// Snaps every vertex of the CSG to a grid of size `tolerance`, so that vertices which should be
+/// coincident but differ by floating-point error become identical.
+///
+/// Polygons that collapse to fewer than 3 distinct vertices are removed.
+pub fn weld_csg(csg: &CSG, tolerance: f64) -> CSG {
+    let polygons: Vec<Polygon<SurfaceDetail>> = csg
+        .polygons
+        .iter()
+        .filter_map(|polygon| {
+            let mut vertices: Vec<csgrs::vertex::Vertex> =
+                Vec::with_capacity(polygon.vertices.len());
+            for vertex in polygon.vertices.iter() {
+                let pos = vertex.pos.map(|c| (c / tolerance).round() * tolerance);
+                if vertices.last().is_some_and(|last| last.pos == pos) {
+                    continue;
+                }
+                vertices.push(csgrs::vertex::Vertex::new(pos, vertex.normal));
+            }
+            while vertices.len() > 1 && vertices[0].pos == vertices[vertices.len() - 1].pos {
+                vertices.pop();
+            }
+
+            if vertices.len() < 3 {
+                return None;
+            }
+            Some(Polygon::new(vertices, polygon.metadata))
+        })
+        .collect();
+
+    CSG::from_polygons(&polygons)
 }
 fn debug_csg_system(
     mut commands: Commands,
     // mut gizmos: Gizmos,
     world_csg: Res<RenderedCsg>,
+    debug_flags: Res<DebugFlags>,
     mut meshes: ResMut<Assets<Mesh>>,
     common: Res<Common>,
 
     mut rendered: Local<Option<Entity>>,
 ) {
-    if !world_csg.is_changed() {
+    if !world_csg.is_changed() && !debug_flags.is_changed() {
         return;
     }
     let world_csg = &world_csg.0;
@@ -254,28 +672,75 @@ fn debug_csg_system(
         .id();
     *rendered = Some(rendered_group);
 
-    let mesh_inside = to_bevy_mesh(world_csg, |face| !face.outside);
-    let mesh_inside_handle = meshes.add(mesh_inside);
+    spawn_csg_shells(
+        &mut commands.entity(rendered_group),
+        &mut meshes,
+        &common,
+        world_csg,
+        debug_flags.shells,
+    );
+}
 
-    commands.entity(rendered_group).with_children(|children| {
-        children.spawn((
-            Mesh3d(mesh_inside_handle),
-            MeshMaterial3d(common.red_material.clone()),
-            Transform::from_scale(Vec3::splat(1.)),
-        ));
+/// Spawns the chosen shells of `csg` as children of `parent`: the inside faces in the main view,
+/// and the outside faces in the x-ray view.
+fn spawn_csg_shells(
+    parent: &mut EntityCommands,
+    meshes: &mut Assets<Mesh>,
+    common: &Common,
+    csg: &CSG,
+    shells: CsgShells,
+) {
+    let mesh_inside = shells
+        .inside
+        .then(|| meshes.add(to_bevy_mesh(csg, false, |face| !face.outside)));
+    let mesh_outside = shells
+        .outside
+        .then(|| meshes.add(to_bevy_mesh(csg, false, |face| face.outside)));
+
+    parent.with_children(|children| {
+        if let Some(mesh_inside_handle) = mesh_inside {
+            children.spawn((
+                Mesh3d(mesh_inside_handle),
+                MeshMaterial3d(common.red_material.clone()),
+                Transform::from_scale(Vec3::splat(1.)),
+            ));
+        }
+        if let Some(mesh_outside_handle) = mesh_outside {
+            children.spawn((
+                Mesh3d(mesh_outside_handle),
+                MeshMaterial3d(common.outside_material.clone()),
+                Transform::from_scale(Vec3::splat(1.)),
+                RenderLayers::layer(7),
+            ));
+        }
     });
 }
 
-fn to_bevy_mesh(csg: &CSG, mut filter_faces: impl FnMut(&SurfaceDetail) -> bool) -> Mesh {
+/// Vertex positions and normals closer together than this are treated as equal, so that the
+/// vertex is shared between triangles.
+const WELD_EPSILON: f32 = 1e-3;
+
+/// Builds a triangle mesh from the faces of `csg` accepted by `filter_faces`.
+///
+/// Vertices with the same position and normal are shared between triangles. If `smooth` is set,
+/// vertices at the same position are shared regardless of their normals, which are averaged, so
+/// that the mesh is shaded smoothly.
+fn to_bevy_mesh(
+    csg: &CSG,
+    smooth: bool,
+    mut filter_faces: impl FnMut(&SurfaceDetail) -> bool,
+) -> Mesh {
     let tessellated_csg = &csg.tessellate();
     let polygons = &tessellated_csg.polygons;
 
     // Prepare buffers
     let mut positions_32 = Vec::new();
-    let mut normals_32 = Vec::new();
+    let mut normals_32: Vec<[f32; 3]> = Vec::new();
     let mut indices = Vec::with_capacity(polygons.len() * 3);
 
-    let mut index_start = 0u32;
+    // The index of the vertex at each (quantized) position and normal. Smooth meshes ignore the
+    // normal.
+    let mut welded: HashMap<(IVec3, IVec3), u32> = HashMap::new();
 
     // Each polygon is assumed to have exactly 3 vertices after tessellation.
     for poly in polygons {
@@ -291,17 +756,41 @@ fn to_bevy_mesh(csg: &CSG, mut filter_faces: impl FnMut(&SurfaceDetail) -> bool)
             continue;
         }
 
-        // push 3 positions/normals
+        // push 3 positions/normals, or add to the normals of already-pushed vertices
         for v in &poly.vertices {
-            positions_32.push([v.pos.x as f32, v.pos.y as f32, v.pos.z as f32]);
-            normals_32.push([v.normal.x as f32, v.normal.y as f32, v.normal.z as f32]);
+            let position = Vec3::new(v.pos.x as f32, v.pos.y as f32, v.pos.z as f32);
+            let normal = Vec3::new(v.normal.x as f32, v.normal.y as f32, v.normal.z as f32);
+
+            let quantize = |v: Vec3| (v / WELD_EPSILON).round().as_ivec3();
+            let key = (
+                quantize(position),
+                if smooth {
+                    IVec3::ZERO
+                } else {
+                    quantize(normal)
+                },
+            );
+            if let Some(&index) = welded.get(&key) {
+                if smooth {
+                    let sum = Vec3::from(normals_32[index as usize]) + normal;
+                    normals_32[index as usize] = sum.into();
+                }
+                indices.push(index);
+                continue;
+            }
+
+            let index = positions_32.len() as u32;
+            positions_32.push(position.to_array());
+            normals_32.push(normal.to_array());
+            indices.push(index);
+            welded.insert(key, index);
         }
+    }
 
-        // triangle indices
-        indices.push(index_start);
-        indices.push(index_start + 1);
-        indices.push(index_start + 2);
-        index_start += 3;
+    if smooth {
+        for normal in normals_32.iter_mut() {
+            *normal = Vec3::from(*normal).normalize_or_zero().into();
+        }
     }
 
     // Create the mesh with the new 2-argument constructor
@@ -323,6 +812,81 @@ fn to_bevy_mesh(csg: &CSG, mut filter_faces: impl FnMut(&SurfaceDetail) -> bool)
 #[derive(Component)]
 struct XRayCamera;
 
+/// Controls the x-ray overlay, which draws render layer 7 (building outlines and previews) on top
+/// of the world.
+#[derive(Resource)]
+pub struct XRaySettings {
+    /// Whether the x-ray camera renders at all.
+    pub enabled: bool,
+    /// The alpha of `Common::xray_blue_material`, from 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+}
+
+impl Default for XRaySettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// How much each press of the x-ray dimmer and brighter keys changes the overlay's opacity.
+const XRAY_OPACITY_STEP: f32 = 0.1;
+
+/// Toggles the x-ray overlay (`X` by default), and changes its opacity (`9` and `0` by default).
+fn xray_settings_system(
+    mut settings: ResMut<XRaySettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_xray) {
+        settings.enabled = !settings.enabled;
+    }
+    if keys.just_pressed(bindings.xray_dimmer) {
+        settings.opacity = (settings.opacity - XRAY_OPACITY_STEP).max(0.0);
+    }
+    if keys.just_pressed(bindings.xray_brighter) {
+        settings.opacity = (settings.opacity + XRAY_OPACITY_STEP).min(1.0);
+    }
+}
+
+/// Updates the x-ray camera and material to match the settings.
+fn apply_xray_settings_system(
+    settings: Res<XRaySettings>,
+    common: Res<Common>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut cameras: Query<&mut Camera, With<XRayCamera>>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    // Deactivating the camera skips rendering layer 7 entirely.
+    for mut camera in cameras.iter_mut() {
+        camera.is_active = settings.enabled;
+    }
+    if let Some(material) = materials.get_mut(&common.xray_blue_material) {
+        let opacity = settings.opacity.clamp(0.0, 1.0);
+        material.base_color.set_alpha(opacity);
+        material.alpha_mode = if opacity < 1.0 {
+            AlphaMode::Blend
+        } else {
+            AlphaMode::Opaque
+        };
+    }
+}
+
+/// How far the cameras can see, which must include the sky dome.
+pub const CAMERA_FAR: f32 = 200_000.;
+
+/// The projection shared by the main camera and the x-ray camera, so that their views line up.
+fn camera_projection() -> Projection {
+    Projection::Perspective(PerspectiveProjection {
+        far: CAMERA_FAR,
+        ..default()
+    })
+}
+
 fn setup(mut commands: Commands) {
     commands.insert_resource(EditorWorld::new());
     commands.insert_resource(RenderedCsg(CSG::new()));
@@ -334,12 +898,16 @@ fn setup(mut commands: Commands) {
     // Camera in 3D space.
     commands.spawn((
         Camera3d::default(),
+        camera_projection(),
         camera_and_light_transform,
         CameraControls::default(),
+        // Draw UI with the main camera, rather than the x-ray overlay.
+        bevy::ui::IsDefaultUiCamera,
         children![
             // Insert a child camera which shows x-ray mode
             (
                 Camera3d::default(),
+                camera_projection(),
                 XRayCamera,
                 RenderLayers::layer(7),
                 Camera {
@@ -366,3 +934,188 @@ fn setup(mut commands: Commands) {
         Transform::from_xyz(-1786. / 3., 768. / 2., 900.).looking_at(Vec3::ZERO, Vec3::Y),
     ));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancelled_build_returns_no_csg() {
+        let buildings = [
+            Building::square(IVec2::ZERO, 4),
+            Building::square(IVec2::new(10, 0), 4),
+        ];
+        let mut cache = BuildingPrismCache::default();
+
+        let cancel = AtomicBool::new(true);
+        assert!(build_world_csg(&buildings, &mut cache, |_| {}, &cancel).is_none());
+
+        let cancel = AtomicBool::new(false);
+        let mut fractions = Vec::new();
+        let csg = build_world_csg(
+            &buildings,
+            &mut cache,
+            |fraction| fractions.push(fraction),
+            &cancel,
+        );
+        assert!(csg.is_some_and(|csg| !csg.polygons.is_empty()));
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+
+    #[test]
+    fn grid_label_interval_grows_with_distance_in_snap_steps() {
+        assert_eq!(grid_label_interval(0., 1), 1);
+        assert_eq!(grid_label_interval(11., 1), 8);
+        assert_eq!(grid_label_interval(100., 1), 64);
+        assert_eq!(grid_label_interval(11., 4), 8);
+        assert_eq!(grid_label_interval(1., 4), 4);
+        assert!(grid_label_interval(f32::MAX, 1) > 0);
+    }
+
+    #[test]
+    fn unchanged_buildings_reuse_their_cached_prisms() {
+        let moved = Building::square(IVec2::ZERO, 4);
+        let unrelated = Building::square(IVec2::new(10, 0), 4);
+        let never_cancelled = AtomicBool::new(false);
+        let mut cache = BuildingPrismCache::default();
+        build_world_csg(
+            &[moved.clone(), unrelated.clone()],
+            &mut cache,
+            |_| {},
+            &never_cancelled,
+        )
+        .unwrap();
+
+        // Swap in a marker, which a rebuilt prism wouldn't match.
+        cache
+            .prisms
+            .insert(unrelated.clone(), [CSG::new(), CSG::new()]);
+
+        let mut moved_again = moved.clone();
+        moved_again.translate(IVec2::new(0, -10));
+        build_world_csg(
+            &[moved_again.clone(), unrelated.clone()],
+            &mut cache,
+            |_| {},
+            &never_cancelled,
+        )
+        .unwrap();
+
+        assert!(cache.prisms[&unrelated][0].polygons.is_empty());
+        assert!(!cache.prisms[&moved_again][0].polygons.is_empty());
+        assert!(!cache.prisms.contains_key(&moved));
+    }
+
+    fn triangle(corners: [Vec3; 3], normal: Vec3) -> Polygon<SurfaceDetail> {
+        Polygon::new(
+            corners
+                .iter()
+                .map(|corner| csgrs::vertex::Vertex::new(corner.to_point(), normal.to_vector()))
+                .collect(),
+            Some(SurfaceDetail { outside: true }),
+        )
+    }
+
+    /// The position and normal of each vertex of the mesh.
+    fn mesh_vertices(mesh: &Mesh) -> Vec<(Vec3, Vec3)> {
+        let positions = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .and_then(|values| values.as_float3())
+            .unwrap();
+        let normals = mesh
+            .attribute(Mesh::ATTRIBUTE_NORMAL)
+            .and_then(|values| values.as_float3())
+            .unwrap();
+        positions
+            .iter()
+            .zip(normals)
+            .map(|(&position, &normal)| (Vec3::from(position), Vec3::from(normal)))
+            .collect()
+    }
+
+    #[test]
+    fn smooth_mesh_averages_normals_along_a_shared_edge() {
+        // Two triangles folded along the edge from the origin to +Z, one facing +Y and one +X.
+        let csg = CSG::from_polygons(&[
+            triangle([Vec3::ZERO, Vec3::Z, Vec3::X], Vec3::Y),
+            triangle([Vec3::ZERO, Vec3::Y, Vec3::Z], Vec3::X),
+        ]);
+
+        let flat = mesh_vertices(&to_bevy_mesh(&csg, false, |_| true));
+        assert_eq!(flat.len(), 6);
+
+        let smooth = mesh_vertices(&to_bevy_mesh(&csg, true, |_| true));
+        assert_eq!(smooth.len(), 4);
+        let averaged = (Vec3::X + Vec3::Y).normalize();
+        for (position, normal) in smooth {
+            let expected = if position.x > 0.5 {
+                Vec3::Y
+            } else if position.y > 0.5 {
+                Vec3::X
+            } else {
+                averaged
+            };
+            assert!(
+                normal.abs_diff_eq(expected, 1e-5),
+                "{position}: {normal} != {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn flat_box_shares_vertices_within_each_face() {
+        let [outer, _] = building_prisms(&Building::square(IVec2::ZERO, 4));
+
+        // Each of the 6 faces needs its own copy of its 4 corners, since the normals differ.
+        let flat = mesh_vertices(&to_bevy_mesh(&outer, false, |_| true));
+        assert_eq!(flat.len(), 24);
+
+        let smooth = mesh_vertices(&to_bevy_mesh(&outer, true, |_| true));
+        assert_eq!(smooth.len(), 8);
+    }
+
+    #[test]
+    fn adjacent_rooms_weld_their_shared_wall() {
+        let buildings = [
+            Building::square(IVec2::ZERO, 4),
+            Building::square(IVec2::new(4, 0), 4),
+        ];
+        let csg = build_world_csg(
+            &buildings,
+            &mut BuildingPrismCache::default(),
+            |_| {},
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+
+        // Every vertex is on the weld grid, so vertices from the two rooms along their shared wall
+        // are either identical or clearly apart.
+        let step = CSG_WELD_TOLERANCE * VOXEL_SIZE as f64;
+        let mut positions: Vec<Vec3> = Vec::new();
+        for vertex in csg.polygons.iter().flat_map(|poly| poly.vertices.iter()) {
+            for c in vertex.pos.coords.iter() {
+                assert!(
+                    ((c / step).round() * step - c).abs() < 1e-6,
+                    "{} is off the weld grid",
+                    vertex.pos
+                );
+            }
+            let position = Vec3::new(
+                vertex.pos.x as f32,
+                vertex.pos.y as f32,
+                vertex.pos.z as f32,
+            );
+            if !positions.contains(&position) {
+                positions.push(position);
+            }
+        }
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!(
+                    a.distance(*b) > step as f32 / 2.,
+                    "{a} and {b} weren't welded"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+use bevy::{platform::collections::HashMap, prelude::*};
+
+use crate::debug_flags::DebugFlags;
+use crate::{CSG, RenderedCsg};
+
+/// Positions closer than this are treated as the same vertex when matching up edges.
+const VERTEX_MATCH_TOLERANCE: f64 = 1e-3;
+
+pub struct ManifoldCheckPlugin;
+
+impl Plugin for ManifoldCheckPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ManifoldDefects>().add_systems(
+            Update,
+            (find_manifold_defects_system, draw_manifold_defects_system).chain(),
+        );
+    }
+}
+
+/// An edge of a mesh which is not shared by exactly two triangles.
+#[derive(Clone, Debug)]
+pub struct ManifoldDefect {
+    pub a: Vec3,
+    pub b: Vec3,
+    /// The number of triangles using this edge.
+    pub triangle_count: usize,
+}
+
+/// The manifold defects in the rendered world geometry.
+#[derive(Resource, Default)]
+pub struct ManifoldDefects(pub Vec<ManifoldDefect>);
+
+/// Tessellates the CSG and returns every edge that is not shared by exactly two triangles.
+/// A closed, manifold mesh has no defects.
+///
+/// CSG output has T-junctions, where a vertex of one triangle lies partway along an edge of its
+/// neighbor. Each edge is split at any vertices lying on it before the edges are matched up, so
+/// that T-junctions on closed geometry aren't reported as open edges.
+pub fn check_manifold(csg: &CSG) -> Vec<ManifoldDefect> {
+    let tessellated = csg.tessellate();
+
+    type Key = [i64; 3];
+    let key_of = |p: &nalgebra::Point3<f64>| -> Key {
+        [p.x, p.y, p.z].map(|c| (c / VERTEX_MATCH_TOLERANCE).round() as i64)
+    };
+
+    let triangles: Vec<[nalgebra::Point3<f64>; 3]> = tessellated
+        .polygons
+        .iter()
+        .filter(|polygon| polygon.vertices.len() == 3)
+        .map(|polygon| [0, 1, 2].map(|i| polygon.vertices[i].pos))
+        .collect();
+    let mut vertices: HashMap<Key, nalgebra::Point3<f64>> = HashMap::new();
+    for &p in triangles.iter().flatten() {
+        vertices.entry(key_of(&p)).or_insert(p);
+    }
+
+    // Maps each undirected edge (by its quantized endpoints) to its position and triangle count.
+    let mut edges: HashMap<(Key, Key), (Vec3, Vec3, usize)> = HashMap::new();
+    for triangle in triangles.iter() {
+        for i in 0..3 {
+            let (a, b) = (triangle[i], triangle[(i + 1) % 3]);
+            let (key_a, key_b) = (key_of(&a), key_of(&b));
+            if key_a == key_b {
+                continue;
+            }
+
+            // The vertices lying partway along the edge, ordered from `a` to `b`.
+            let ab = b - a;
+            let mut on_edge: Vec<(f64, nalgebra::Point3<f64>)> = vertices
+                .iter()
+                .filter(|&(&key, _)| key != key_a && key != key_b)
+                .filter_map(|(_, &p)| {
+                    let t = (p - a).dot(&ab) / ab.norm_squared();
+                    let on_segment =
+                        t > 0.0 && t < 1.0 && (a + ab * t - p).norm() <= VERTEX_MATCH_TOLERANCE;
+                    on_segment.then_some((t, p))
+                })
+                .collect();
+            on_edge.sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+
+            let points: Vec<nalgebra::Point3<f64>> = std::iter::once(a)
+                .chain(on_edge.into_iter().map(|(_, p)| p))
+                .chain(std::iter::once(b))
+                .collect();
+            for pair in points.windows(2) {
+                let (key_a, key_b) = (key_of(&pair[0]), key_of(&pair[1]));
+                let key = if key_a < key_b {
+                    (key_a, key_b)
+                } else {
+                    (key_b, key_a)
+                };
+                let to_vec3 =
+                    |p: &nalgebra::Point3<f64>| Vec3::new(p.x as f32, p.y as f32, p.z as f32);
+                edges
+                    .entry(key)
+                    .or_insert_with(|| (to_vec3(&pair[0]), to_vec3(&pair[1]), 0))
+                    .2 += 1;
+            }
+        }
+    }
+
+    edges
+        .into_values()
+        .filter(|&(_, _, triangle_count)| triangle_count != 2)
+        .map(|(a, b, triangle_count)| ManifoldDefect {
+            a,
+            b,
+            triangle_count,
+        })
+        .collect()
+}
+
+fn find_manifold_defects_system(
+    debug_flags: Res<DebugFlags>,
+    rendered_csg: Res<RenderedCsg>,
+    mut defects: ResMut<ManifoldDefects>,
+) {
+    if !debug_flags.show_manifold_defects {
+        return;
+    }
+    if !rendered_csg.is_changed() && !debug_flags.is_changed() {
+        return;
+    }
+    defects.0 = check_manifold(&rendered_csg.0);
+    if !defects.0.is_empty() {
+        warn!("World geometry has {} non-manifold edges", defects.0.len());
+    }
+}
+
+fn draw_manifold_defects_system(
+    mut gizmos: Gizmos,
+    debug_flags: Res<DebugFlags>,
+    defects: Res<ManifoldDefects>,
+) {
+    if !debug_flags.show_manifold_defects {
+        return;
+    }
+    for defect in defects.0.iter() {
+        let color = if defect.triangle_count < 2 {
+            // An open edge, where there is a hole in the mesh.
+            Color::linear_rgb(1., 0., 1.)
+        } else {
+            // An edge shared by too many triangles.
+            Color::linear_rgb(1., 0.5, 0.)
+        };
+        gizmos.line(defect.a, defect.b, color);
+        gizmos.sphere(defect.a.lerp(defect.b, 0.5), 4., color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::Building;
+    use crate::geometry_utils::BevyToNalgebra;
+    use crate::{BuildingPrismCache, SurfaceDetail, build_world_csg};
+    use csgrs::{polygon::Polygon, vertex::Vertex};
+    use std::sync::atomic::AtomicBool;
+
+    fn triangle(corners: [Vec3; 3]) -> Polygon<SurfaceDetail> {
+        Polygon::new(
+            corners
+                .iter()
+                .map(|corner| Vertex::new(corner.to_point(), Vec3::Y.to_vector()))
+                .collect(),
+            Some(SurfaceDetail { outside: true }),
+        )
+    }
+
+    #[test]
+    fn a_single_box_room_has_no_defects() {
+        let csg = build_world_csg(
+            &[Building::square(IVec2::ZERO, 4)],
+            &mut BuildingPrismCache::default(),
+            |_| {},
+            &AtomicBool::new(false),
+        )
+        .unwrap();
+        let defects = check_manifold(&csg);
+        assert!(defects.is_empty(), "{defects:?}");
+    }
+
+    #[test]
+    fn t_junctions_are_not_open_edges() {
+        // A tetrahedron, with one face split in two at the middle of an edge it shares.
+        let [a, b, c, d] = [Vec3::ZERO, Vec3::X * 2., Vec3::Z * 2., Vec3::Y * 2.];
+        let middle = (a + b) / 2.;
+        let csg = CSG::from_polygons(&[
+            triangle([a, c, b]),
+            triangle([a, middle, d]),
+            triangle([middle, b, d]),
+            triangle([b, c, d]),
+            triangle([c, a, d]),
+        ]);
+        let defects = check_manifold(&csg);
+        assert!(defects.is_empty(), "{defects:?}");
+
+        // Without the face against `a` and `c`, those edges are open.
+        let open = CSG::from_polygons(&[
+            triangle([a, c, b]),
+            triangle([a, middle, d]),
+            triangle([middle, b, d]),
+            triangle([b, c, d]),
+        ]);
+        assert_eq!(check_manifold(&open).len(), 3);
+    }
+}
@@ -1,9 +1,12 @@
 use std::hash::Hash;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
+use bevy::ecs::bundle::NoBundleEffect;
 use bevy::prelude::*;
 
+use crate::debug_flags::DebugFlags;
+
 struct PreviewState {
     epoch: u64,
     entity: Entity,
@@ -12,6 +15,11 @@ struct PreviewState {
 pub struct Previewer<K> {
     epoch: u64,
     cache: HashMap<K, PreviewState>,
+    /// Hidden entities that are no longer used by any key, waiting to be reused by
+    /// `PreviewCollector::render_bundle`.
+    pool: Vec<Entity>,
+    /// The most entities to keep in `pool`. Any others are despawned.
+    max_pooled: usize,
 }
 
 pub struct PreviewCollector<'w, 's, 'a, K> {
@@ -29,14 +37,76 @@ impl<K: Eq + Clone + Hash> PreviewCollector<'_, '_, '_, K> {
     pub fn render(&mut self, key: &K, render: impl FnOnce(&mut Commands) -> Entity) {
         self.previewer.render(key, || render(self.commands))
     }
+
+    /// Like `render`, but the entity is made from a bundle, so that a pooled entity can be reused
+    /// (by inserting the bundle over its old components) instead of spawning a new one.
+    ///
+    /// Pooled entities keep any components the new bundle doesn't replace, so each previewer should
+    /// only render one shape of bundle.
+    pub fn render_bundle<B: Bundle>(&mut self, key: &K, bundle: impl FnOnce() -> B) {
+        if self.previewer.refresh(key) {
+            return;
+        }
+        let entity = match self.previewer.pool.pop() {
+            Some(entity) => {
+                self.commands
+                    .entity(entity)
+                    .insert(Visibility::Inherited)
+                    .insert(bundle());
+                entity
+            }
+            None => self.commands.spawn(bundle()).id(),
+        };
+        self.previewer.track(key.clone(), entity);
+    }
+
+    /// Like `render_bundle`, for many keys at once. The new entities are inserted with a single
+    /// batch command, rather than one command per entity, which matters when there are thousands.
+    pub fn render_many<B: Bundle<Effect: NoBundleEffect>>(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+        mut bundle: impl FnMut(&K) -> B,
+    ) {
+        let mut reused = Vec::new();
+        let mut batch = Vec::new();
+        for key in keys {
+            if self.previewer.refresh(&key) {
+                continue;
+            }
+            let entity = match self.previewer.pool.pop() {
+                Some(entity) => {
+                    reused.push((entity, Visibility::Inherited));
+                    entity
+                }
+                // Reserving an entity doesn't queue a command; the batch below spawns it.
+                None => self.commands.spawn_empty().id(),
+            };
+            batch.push((entity, bundle(&key)));
+            self.previewer.track(key, entity);
+        }
+        if !reused.is_empty() {
+            self.commands.insert_batch(reused);
+        }
+        if !batch.is_empty() {
+            self.commands.insert_batch(batch);
+        }
+    }
 }
 
 impl<K> Previewer<K> {
     /// Create a new empty previewer.
     pub fn new() -> Self {
+        Self::with_pooling(0)
+    }
+
+    /// Create a new empty previewer which hides up to `max` out-of-date entities instead of
+    /// despawning them, so that `PreviewCollector::render_bundle` can reuse them.
+    pub fn with_pooling(max: usize) -> Self {
         Self {
             epoch: 0,
             cache: HashMap::new(),
+            pool: Vec::new(),
+            max_pooled: max,
         }
     }
 
@@ -48,28 +118,51 @@ impl<K> Previewer<K> {
     where
         K: Eq + Hash + Clone,
     {
-        if !self.cache.contains_key(key) {
+        if !self.refresh(key) {
             let new_entity = render();
-            self.cache.insert(
-                key.clone(),
-                PreviewState {
-                    epoch: self.epoch + 1,
-                    entity: new_entity,
-                },
-            );
-            return;
+            self.track(key.clone(), new_entity);
         }
-        // Refresh the epoch of the existing entry.
-        self.cache.get_mut(key).unwrap().epoch = self.epoch + 1;
     }
 
-    /// Despawn all of the entites not refreshed in the last epoch.
+    /// Refresh the epoch of the entry for `key`, returning `false` if there is none.
+    fn refresh(&mut self, key: &K) -> bool
+    where
+        K: Eq + Hash,
+    {
+        let next_epoch = self.epoch + 1;
+        let Some(state) = self.cache.get_mut(key) else {
+            return false;
+        };
+        state.epoch = next_epoch;
+        true
+    }
+
+    /// Start tracking a newly rendered entity for `key`.
+    fn track(&mut self, key: K, entity: Entity)
+    where
+        K: Eq + Hash,
+    {
+        self.cache.insert(
+            key,
+            PreviewState {
+                epoch: self.epoch + 1,
+                entity,
+            },
+        );
+    }
+
+    /// Despawn all of the entites not refreshed in the last epoch, or hide them for reuse if
+    /// pooling is enabled and the pool has room.
     pub fn collect_garbage(&mut self, commands: &mut Commands) {
         self.epoch += 1;
         let keep_epoch = self.epoch;
         self.cache.retain(|_, value| {
             if value.epoch == keep_epoch {
                 true
+            } else if self.pool.len() < self.max_pooled {
+                commands.entity(value.entity).insert(Visibility::Hidden);
+                self.pool.push(value.entity);
+                false
             } else {
                 commands.entity(value.entity).despawn();
                 false
@@ -77,6 +170,21 @@ impl<K> Previewer<K> {
         });
     }
 
+    /// The number of preview entities currently tracked, not counting hidden pooled entities.
+    pub fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cache.is_empty()
+    }
+
+    /// The keys of the preview entities currently tracked, in no particular order.
+    #[allow(unused)]
+    pub fn live_keys(&self) -> impl Iterator<Item = &K> {
+        self.cache.keys()
+    }
+
     pub fn collect_scope<'a, 'w, 's>(
         &'a mut self,
         commands: &'a mut Commands<'w, 's>,
@@ -93,3 +201,139 @@ impl<K> Default for Previewer<K> {
         Self::new()
     }
 }
+
+/// Shows how many entities each previewer is tracking, for diagnosing preview leaks.
+pub struct PreviewStatsPlugin;
+
+impl Plugin for PreviewStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PreviewStats>()
+            .add_systems(Startup, setup_preview_stats)
+            .add_systems(Update, preview_stats_overlay_system);
+    }
+}
+
+/// The number of entities tracked by each previewer, by name.
+///
+/// Previewers live in system `Local`s, so each system records its own count with `record`.
+#[derive(Resource, Default)]
+pub struct PreviewStats {
+    counts: BTreeMap<&'static str, usize>,
+}
+
+impl PreviewStats {
+    /// Records the number of entities `previewer` is tracking under `name`.
+    pub fn record<K>(&mut self, name: &'static str, previewer: &Previewer<K>) {
+        self.counts.insert(name, previewer.len());
+    }
+}
+
+#[derive(Component)]
+struct PreviewStatsOverlay;
+
+fn setup_preview_stats(mut commands: Commands) {
+    commands.spawn((
+        PreviewStatsOverlay,
+        Text::new(""),
+        TextFont {
+            font_size: 14.,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::linear_rgba(0., 0., 0., 0.7)),
+        Node {
+            position_type: PositionType::Absolute,
+            right: Val::Px(8.),
+            top: Val::Px(8.),
+            padding: UiRect::all(Val::Px(4.)),
+            ..default()
+        },
+        Visibility::Hidden,
+        Pickable::IGNORE,
+    ));
+}
+
+fn preview_stats_overlay_system(
+    flags: Res<DebugFlags>,
+    stats: Res<PreviewStats>,
+    mut overlay: Query<(&mut Text, &mut Visibility), With<PreviewStatsOverlay>>,
+) {
+    let Ok((mut text, mut visibility)) = overlay.single_mut() else {
+        return;
+    };
+    if !flags.show_preview_stats {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+    text.0 = stats
+        .counts
+        .iter()
+        .map(|(name, count)| format!("{name}: {count}"))
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::world::CommandQueue;
+
+    #[derive(Component)]
+    struct Marker(u32);
+
+    /// Renders one frame showing exactly `keys`, then applies the commands to `world`.
+    fn render_frame(world: &mut World, previewer: &mut Previewer<u32>, keys: &[u32]) {
+        let mut queue = CommandQueue::default();
+        {
+            let mut commands = Commands::new(&mut queue, world);
+            let mut scope = previewer.collect_scope(&mut commands);
+            for &key in keys {
+                scope.render_bundle(&key, || Marker(key));
+            }
+        }
+        queue.apply(world);
+    }
+
+    fn spawned(world: &mut World) -> usize {
+        world.query::<&Marker>().iter(world).count()
+    }
+
+    #[test]
+    fn pooled_entities_are_reused_and_the_pool_is_bounded() {
+        let mut world = World::new();
+        let mut previewer = Previewer::with_pooling(2);
+
+        render_frame(&mut world, &mut previewer, &[0, 1, 2, 3]);
+        assert_eq!(spawned(&mut world), 4);
+
+        // Three entities go stale, but only two fit in the pool.
+        render_frame(&mut world, &mut previewer, &[0]);
+        assert_eq!(previewer.pool.len(), 2);
+        assert_eq!(spawned(&mut world), 3);
+        let pooled = previewer.pool.clone();
+        for &entity in pooled.iter() {
+            assert_eq!(world.get::<Visibility>(entity), Some(&Visibility::Hidden));
+        }
+
+        // New keys take the pooled entities before any more are spawned.
+        render_frame(&mut world, &mut previewer, &[0, 10, 11, 12]);
+        assert!(previewer.pool.is_empty());
+        assert_eq!(spawned(&mut world), 4);
+        let live: Vec<Entity> = previewer.cache.values().map(|state| state.entity).collect();
+        for entity in pooled {
+            assert!(live.contains(&entity));
+            assert_eq!(
+                world.get::<Visibility>(entity),
+                Some(&Visibility::Inherited)
+            );
+        }
+        for key in [10, 11, 12] {
+            let entity = previewer.cache[&key].entity;
+            assert_eq!(
+                world.get::<Marker>(entity).map(|marker| marker.0),
+                Some(key)
+            );
+        }
+    }
+}
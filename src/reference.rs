@@ -0,0 +1,241 @@
+use bevy::prelude::*;
+
+use crate::js_ffi::take_reference_objs;
+use crate::keybindings::Keybindings;
+
+/// Spawns reference geometry loaded through `tfbe_ffi_load_reference_obj`.
+pub struct ReferenceGeometryPlugin;
+
+impl Plugin for ReferenceGeometryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ReferenceGeometry>()
+            .add_event::<ReferenceObjLoadedEvent>()
+            .add_systems(
+                Update,
+                (
+                    receive_reference_objs_system,
+                    spawn_reference_obj_system,
+                    reference_settings_system,
+                    apply_reference_settings_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+/// A backdrop mesh to trace over. It is drawn dimmed, and is ignored by picking and editing.
+#[derive(Resource)]
+pub struct ReferenceGeometry {
+    /// The entity showing the loaded reference mesh, if any.
+    entity: Option<Entity>,
+    /// The vertex positions of the reference mesh, in world space.
+    vertices: Vec<Vec3>,
+    /// Whether the reference mesh is shown.
+    pub visible: bool,
+    /// The alpha of the reference mesh, from 0 (invisible) to 1 (opaque).
+    pub opacity: f32,
+}
+
+impl Default for ReferenceGeometry {
+    fn default() -> Self {
+        Self {
+            entity: None,
+            vertices: Vec::new(),
+            visible: true,
+            opacity: 0.35,
+        }
+    }
+}
+
+impl ReferenceGeometry {
+    /// Returns the vertex positions of the reference mesh, in world space.
+    pub fn vertices(&self) -> &[Vec3] {
+        &self.vertices
+    }
+}
+
+/// Sent with the contents of each OBJ file loaded through `tfbe_ffi_load_reference_obj`.
+#[derive(Event)]
+pub struct ReferenceObjLoadedEvent(pub String);
+
+/// Sends a `ReferenceObjLoadedEvent` for each file loaded through `tfbe_ffi_load_reference_obj`.
+fn receive_reference_objs_system(mut events: EventWriter<ReferenceObjLoadedEvent>) {
+    for contents in take_reference_objs() {
+        events.write(ReferenceObjLoadedEvent(contents));
+    }
+}
+
+/// How much each press of the dimmer and brighter keys changes the opacity of the reference mesh.
+const OPACITY_STEP: f32 = 0.1;
+
+/// Replaces the current reference geometry when a new OBJ file has been loaded. If several were
+/// loaded at once, only the last is kept.
+fn spawn_reference_obj_system(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut reference: ResMut<ReferenceGeometry>,
+    mut events: EventReader<ReferenceObjLoadedEvent>,
+) {
+    let Some(ReferenceObjLoadedEvent(contents)) = events.read().last() else {
+        return;
+    };
+
+    if let Some(entity) = reference.entity.take() {
+        commands.entity(entity).despawn();
+    }
+
+    let mesh = load_obj_reference(&contents);
+    reference.vertices = mesh
+        .attribute(Mesh::ATTRIBUTE_POSITION)
+        .and_then(|positions| positions.as_float3())
+        .unwrap_or_default()
+        .iter()
+        .map(|&p| Vec3::from_array(p))
+        .collect();
+
+    let entity = commands
+        .spawn((
+            Mesh3d(meshes.add(mesh)),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::linear_rgba(0.7, 0.7, 0.7, reference.opacity),
+                alpha_mode: AlphaMode::Blend,
+                unlit: true,
+                double_sided: true,
+                cull_mode: None,
+                ..default()
+            })),
+            Transform::default(),
+            Pickable::IGNORE,
+        ))
+        .id();
+    reference.entity = Some(entity);
+}
+
+/// Toggles the reference mesh (`F6` by default), and dims or brightens it (`-` and `=`).
+fn reference_settings_system(
+    mut reference: ResMut<ReferenceGeometry>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_reference) {
+        reference.visible = !reference.visible;
+    }
+    if keys.just_pressed(bindings.reference_dimmer) {
+        reference.opacity = (reference.opacity - OPACITY_STEP).max(0.0);
+    }
+    if keys.just_pressed(bindings.reference_brighter) {
+        reference.opacity = (reference.opacity + OPACITY_STEP).min(1.0);
+    }
+}
+
+/// Updates the visibility and material of the reference mesh to match the settings.
+fn apply_reference_settings_system(
+    reference: Res<ReferenceGeometry>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut query: Query<(&mut Visibility, &MeshMaterial3d<StandardMaterial>)>,
+) {
+    if !reference.is_changed() {
+        return;
+    }
+    let Some(Ok((mut visibility, material))) = reference.entity.map(|entity| query.get_mut(entity))
+    else {
+        return;
+    };
+
+    *visibility = if reference.visible {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if let Some(material) = materials.get_mut(&material.0) {
+        material.base_color.set_alpha(reference.opacity);
+    }
+}
+
+/// Parses the contents of a Wavefront OBJ file into a mesh.
+///
+/// Only `v`, `vn`, and `f` lines are read, and all other directives are skipped. Faces with more
+/// than three vertices are split into a fan of triangles, and faces without normals are shaded
+/// flat. Faces which refer to missing vertices are dropped.
+pub fn load_obj_reference(contents: &str) -> Mesh {
+    let mut obj_positions: Vec<Vec3> = Vec::new();
+    let mut obj_normals: Vec<Vec3> = Vec::new();
+
+    let mut positions: Vec<[f32; 3]> = Vec::new();
+    let mut normals: Vec<[f32; 3]> = Vec::new();
+
+    for line in contents.lines() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("v") => {
+                if let Some(p) = parse_vec3(words) {
+                    obj_positions.push(p);
+                }
+            }
+            Some("vn") => {
+                if let Some(n) = parse_vec3(words) {
+                    obj_normals.push(n.normalize_or_zero());
+                }
+            }
+            Some("f") => {
+                let Some(corners) = words
+                    .map(|word| parse_face_corner(word, &obj_positions, &obj_normals))
+                    .collect::<Option<Vec<(Vec3, Option<Vec3>)>>>()
+                else {
+                    continue;
+                };
+
+                for i in 1..corners.len().saturating_sub(1) {
+                    let triangle = [corners[0], corners[i], corners[i + 1]];
+                    let flat_normal = (triangle[1].0 - triangle[0].0)
+                        .cross(triangle[2].0 - triangle[0].0)
+                        .normalize_or_zero();
+                    for (p, n) in triangle {
+                        positions.push(p.to_array());
+                        normals.push(n.unwrap_or(flat_normal).to_array());
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut mesh = Mesh::new(
+        bevy::render::mesh::PrimitiveTopology::TriangleList,
+        bevy::asset::RenderAssetUsages::default(),
+    );
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    mesh
+}
+
+fn parse_vec3<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Vec3> {
+    let mut coord = || -> Option<f32> { words.next()?.parse().ok() };
+    Some(Vec3::new(coord()?, coord()?, coord()?))
+}
+
+/// Parses one `v`, `v/vt`, `v//vn`, or `v/vt/vn` corner of a face.
+fn parse_face_corner(
+    word: &str,
+    positions: &[Vec3],
+    normals: &[Vec3],
+) -> Option<(Vec3, Option<Vec3>)> {
+    let mut indexes = word.split('/');
+    let position = resolve_index(indexes.next()?, positions)?;
+    let normal = indexes
+        .nth(1)
+        .and_then(|index| resolve_index(index, normals));
+    Some((position, normal))
+}
+
+/// Looks up a 1-based OBJ index, where negative indexes count back from the most recent item.
+fn resolve_index<T: Copy>(index: &str, items: &[T]) -> Option<T> {
+    let index: i64 = index.parse().ok()?;
+    let index = if index < 0 {
+        items.len() as i64 + index
+    } else {
+        index - 1
+    };
+    items.get(usize::try_from(index).ok()?).copied()
+}
@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+
+use crate::editor_actions::MouseGrid;
+use crate::editor_state::EditorWorld;
+use crate::keybindings::Keybindings;
+
+pub struct StatusLinePlugin;
+
+impl Plugin for StatusLinePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<StatusLineSettings>()
+            .add_systems(Startup, setup_status_line)
+            .add_systems(
+                Update,
+                (toggle_status_line_system, status_line_system).chain(),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct StatusLineSettings {
+    /// Whether to show the status line.
+    pub enabled: bool,
+}
+
+impl Default for StatusLineSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Component)]
+struct StatusLine;
+
+fn setup_status_line(mut commands: Commands) {
+    commands.spawn((
+        StatusLine,
+        Text::new(""),
+        TextFont {
+            font_size: 14.,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::linear_rgba(0., 0., 0., 0.7)),
+        Node {
+            position_type: PositionType::Absolute,
+            left: Val::Px(8.),
+            bottom: Val::Px(8.),
+            padding: UiRect::all(Val::Px(4.)),
+            ..default()
+        },
+        Pickable::IGNORE,
+    ));
+}
+
+fn toggle_status_line_system(
+    mut settings: ResMut<StatusLineSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_status_line) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+/// Shows the active tool, the grid point under the mouse, the snapping step, the number of
+/// buildings, the limit on points per building, and the reason the last edit was refused in a
+/// corner of the screen.
+fn status_line_system(
+    settings: Res<StatusLineSettings>,
+    editor_world: Res<EditorWorld>,
+    mouse_grid: MouseGrid,
+    mut status_line: Query<(&mut Text, &mut Visibility), With<StatusLine>>,
+) {
+    let Ok((mut text, mut visibility)) = status_line.single_mut() else {
+        return;
+    };
+    if !settings.enabled {
+        *visibility = Visibility::Hidden;
+        return;
+    }
+    *visibility = Visibility::Inherited;
+
+    let editing_plane_y = 0;
+    let cursor = match mouse_grid.pick_grid(editing_plane_y) {
+        Some(p) => format!("({}, {}, {})", p.x, p.y, p.z),
+        None => "-".to_string(),
+    };
+    let point_limit = match editor_world.max_vertices() {
+        Some(max_vertices) => max_vertices.to_string(),
+        None => "none".to_string(),
+    };
+    text.0 = format!(
+        "Tool: {:?} | Cursor: {cursor} | Snap: {} | Buildings: {} | Point limit: {point_limit}",
+        editor_world.tool(),
+        editor_world.snap_step(),
+        editor_world.buildings().len(),
+    );
+    if let Some(message) = editor_world.status_message() {
+        text.0 += &format!(" | {message}");
+    }
+}
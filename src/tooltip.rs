@@ -0,0 +1,102 @@
+use bevy::{prelude::*, window::PrimaryWindow};
+
+use crate::editor_actions::HoveredBuilding;
+use crate::editor_state::EditorWorld;
+use crate::keybindings::Keybindings;
+
+pub struct TooltipPlugin;
+
+impl Plugin for TooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<TooltipSettings>()
+            .add_systems(Startup, setup_tooltip)
+            .add_systems(
+                Update,
+                (toggle_tooltip_system, building_tooltip_system).chain(),
+            );
+    }
+}
+
+#[derive(Resource)]
+pub struct TooltipSettings {
+    /// Whether to show a tooltip for the hovered building.
+    pub enabled: bool,
+}
+
+impl Default for TooltipSettings {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+#[derive(Component)]
+struct BuildingTooltip;
+
+fn setup_tooltip(mut commands: Commands) {
+    commands.spawn((
+        BuildingTooltip,
+        Text::new(""),
+        TextFont {
+            font_size: 14.,
+            ..default()
+        },
+        TextColor(Color::WHITE),
+        BackgroundColor(Color::linear_rgba(0., 0., 0., 0.7)),
+        Node {
+            position_type: PositionType::Absolute,
+            padding: UiRect::all(Val::Px(4.)),
+            ..default()
+        },
+        Visibility::Hidden,
+        Pickable::IGNORE,
+    ));
+}
+
+fn toggle_tooltip_system(
+    mut settings: ResMut<TooltipSettings>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+) {
+    if keys.just_pressed(bindings.toggle_tooltip) {
+        settings.enabled = !settings.enabled;
+    }
+}
+
+/// Shows information about the hovered building next to the mouse cursor.
+fn building_tooltip_system(
+    settings: Res<TooltipSettings>,
+    hovered: Res<HoveredBuilding>,
+    editor_world: Res<EditorWorld>,
+    window: Query<&Window, With<PrimaryWindow>>,
+    mut tooltip: Query<(&mut Text, &mut Node, &mut Visibility), With<BuildingTooltip>>,
+) {
+    let Ok((mut text, mut node, mut visibility)) = tooltip.single_mut() else {
+        return;
+    };
+
+    let cursor = window
+        .single()
+        .ok()
+        .and_then(|window| window.cursor_position());
+    let building = hovered
+        .building
+        .and_then(|index| Some((index, editor_world.buildings().get(index)?)));
+
+    let (Some(cursor), Some((index, building)), true) = (cursor, building, settings.enabled) else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+
+    *visibility = Visibility::Inherited;
+    node.left = Val::Px(cursor.x + 16.);
+    node.top = Val::Px(cursor.y + 16.);
+    let signed_area = building.signed_area();
+    let winding = if signed_area > 0.0 { "CCW" } else { "CW" };
+    text.0 = format!(
+        "Building {index}\nFloor: {}\nHeight: {}\nVertices: {}\nArea: {:.1}\nWinding: {winding} ({signed_area:+.1})",
+        building.floor_y(),
+        building.height,
+        building.points().len(),
+        building.area(),
+    );
+}
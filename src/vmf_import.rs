@@ -0,0 +1,413 @@
+use bevy::prelude::*;
+
+use crate::building::{Building, BuildingValidity};
+use crate::editor_state::{EditorWorld, from_flat, grid_to_world};
+use crate::flycam::CameraControls;
+use crate::geometry_utils::signed_polygon_area_2d;
+use crate::js_ffi::take_loaded_levels;
+use crate::voxels::VOXEL_SIZE;
+
+/// Turns the solids of a VMF file loaded through `tfbe_ffi_load_file` into buildings.
+pub struct VmfImportPlugin;
+
+impl Plugin for VmfImportPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelLoadedEvent>().add_systems(
+            Update,
+            (
+                receive_loaded_levels_system,
+                import_vmf_buildings_system,
+                frame_camera_on_load_system,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Sent when a VMF file has been loaded through `tfbe_ffi_load_file`.
+#[derive(Event)]
+pub struct LevelLoadedEvent(pub vmf_forge::VmfFile);
+
+/// Sends a `LevelLoadedEvent` for each file loaded through `tfbe_ffi_load_file`.
+fn receive_loaded_levels_system(mut events: EventWriter<LevelLoadedEvent>) {
+    for level in take_loaded_levels() {
+        events.write(LevelLoadedEvent(level));
+    }
+}
+
+/// How far a side's unit normal may be from vertical or horizontal for the solid to still count as
+/// a vertical prism.
+const PRISM_NORMAL_TOLERANCE: f32 = 1e-3;
+
+/// Parses a VMF plane, written as three points `(x y z) (x y z) (x y z)`.
+fn parse_vmf_plane(plane: &str) -> Option<[Vec3; 3]> {
+    let mut points = plane
+        .split(['(', ')'])
+        .filter(|part| !part.trim().is_empty());
+    let mut next_point = || -> Option<Vec3> {
+        let mut coords = points.next()?.split_whitespace().map(str::parse::<f32>);
+        Some(Vec3::new(
+            coords.next()?.ok()?,
+            coords.next()?.ok()?,
+            coords.next()?.ok()?,
+        ))
+    };
+    Some([next_point()?, next_point()?, next_point()?])
+}
+
+/// The footprint of a solid that is a vertical prism, in Hammer units.
+struct PrismFootprint {
+    /// The corners of the footprint in Hammer's horizontal plane, counterclockwise from above.
+    points: Vec<Vec2>,
+    bottom: f32,
+    top: f32,
+}
+
+/// Finds the footprint of a solid from the planes of its sides. Returns `None` unless the solid is
+/// a vertical prism: exactly one side facing up, one facing down, and the rest vertical.
+///
+/// Each plane's points are clockwise as seen from outside the solid, as Hammer writes them.
+fn prism_footprint(planes: &[[Vec3; 3]]) -> Option<PrismFootprint> {
+    let mut bottom = None;
+    let mut top = None;
+    // Each vertical side, as a point on it and its outward normal in the horizontal plane.
+    let mut walls: Vec<(Vec2, Vec2)> = Vec::new();
+    for &[p1, p2, p3] in planes {
+        let normal = (p3 - p1).cross(p2 - p1).try_normalize()?;
+        if normal.z.abs() >= 1.0 - PRISM_NORMAL_TOLERANCE {
+            let slot = if normal.z > 0.0 {
+                &mut top
+            } else {
+                &mut bottom
+            };
+            if slot.replace(p1.z).is_some() {
+                return None;
+            }
+        } else if normal.z.abs() <= PRISM_NORMAL_TOLERANCE {
+            walls.push((p1.xy(), normal.xy().normalize()));
+        } else {
+            return None;
+        }
+    }
+    let (bottom, top) = (bottom?, top?);
+
+    // Clip a square bigger than any map by each wall.
+    let size = 1.0e6;
+    let mut points = vec![
+        Vec2::new(-size, -size),
+        Vec2::new(size, -size),
+        Vec2::new(size, size),
+        Vec2::new(-size, size),
+    ];
+    for (on_wall, normal) in walls {
+        let outside = |p: Vec2| (p - on_wall).dot(normal);
+        let mut clipped = Vec::with_capacity(points.len() + 1);
+        for i in 0..points.len() {
+            let (a, b) = (points[i], points[(i + 1) % points.len()]);
+            if outside(a) <= 0.0 {
+                clipped.push(a);
+            }
+            if (outside(a) <= 0.0) != (outside(b) <= 0.0) {
+                let t = outside(a) / (outside(a) - outside(b));
+                clipped.push(a.lerp(b, t));
+            }
+        }
+        points = clipped;
+    }
+    if points.len() < 3 || points.iter().any(|p| p.abs().max_element() >= size) {
+        return None;
+    }
+
+    Some(PrismFootprint {
+        points,
+        bottom,
+        top,
+    })
+}
+
+/// Converts a solid's footprint to a building on the grid, if the result is a valid building.
+fn footprint_to_building(footprint: &PrismFootprint) -> Option<Building> {
+    // Hammer's Y axis is the editor's negative Z axis.
+    let mut outline: Vec<IVec2> = footprint
+        .points
+        .iter()
+        .map(|p| (Vec2::new(p.x, -p.y) / VOXEL_SIZE).round().as_ivec2())
+        .collect();
+    if signed_polygon_area_2d(&outline) < 0.0 {
+        outline.reverse();
+    }
+
+    let floor_y = (footprint.bottom / VOXEL_SIZE).round() as i32;
+    let ceiling_y = (footprint.top / VOXEL_SIZE).round() as i32;
+    let mut building = Building::try_new(floor_y, outline)?;
+    building.height = ceiling_y - floor_y;
+    building
+        .is_valid(BuildingValidity::default())
+        .then_some(building)
+}
+
+/// Adds a building for each solid of each newly loaded VMF file, as a single undoable edit per
+/// file.
+///
+/// Only solids which are vertical prisms (such as boxes) are converted. Other solids, and solids
+/// too small to be a valid building on the grid, are skipped with a warning.
+fn import_vmf_buildings_system(
+    mut editor_world: ResMut<EditorWorld>,
+    mut events: EventReader<LevelLoadedEvent>,
+) {
+    for LevelLoadedEvent(level) in events.read() {
+        import_vmf_buildings(&mut editor_world, level);
+    }
+}
+
+/// Adds a building for each prism-shaped solid of `level`, as a single undoable edit.
+pub fn import_vmf_buildings(editor_world: &mut EditorWorld, level: &vmf_forge::VmfFile) {
+    let mut imported = 0;
+    editor_world.begin_edit_group();
+    for solid in level.world.solids.iter() {
+        let planes: Option<Vec<[Vec3; 3]>> = solid
+            .sides
+            .iter()
+            .map(|side| parse_vmf_plane(&side.plane))
+            .collect();
+        let Some(footprint) = planes.as_deref().and_then(prism_footprint) else {
+            warn!("Skipping VMF solid {}: not a vertical prism", solid.id);
+            continue;
+        };
+        let Some(building) = footprint_to_building(&footprint) else {
+            warn!(
+                "Skipping VMF solid {}: not a valid building on the grid",
+                solid.id
+            );
+            continue;
+        };
+        if editor_world.insert_building(building) {
+            imported += 1;
+        }
+    }
+    editor_world.end_edit_group();
+
+    info!(
+        "Imported {imported} of {} VMF solids as buildings",
+        level.world.solids.len()
+    );
+}
+
+/// The direction from the framed content back to the camera, matching the startup view.
+const FRAMING_DIRECTION: Vec3 = Vec3::new(786., 768., 900.);
+
+/// Returns a camera transform which looks at the box from `min` to `max` (in world units) from the
+/// startup view's direction, far enough away that the whole box fits in a view of `fov` radians.
+fn framing_transform(min: Vec3, max: Vec3, fov: f32) -> Transform {
+    let center = (min + max) / 2.;
+    let radius = (max - min).length() / 2.;
+    let distance = radius / (fov / 2.).sin();
+    Transform::from_translation(center + FRAMING_DIRECTION.normalize() * distance)
+        .looking_at(center, Vec3::Y)
+}
+
+/// Moves the camera to frame all of the buildings after a VMF file is loaded.
+///
+/// Does nothing if the world has no buildings.
+fn frame_camera_on_load_system(
+    editor_world: Res<EditorWorld>,
+    mut events: EventReader<LevelLoadedEvent>,
+    mut camera: Query<(&mut Transform, &Projection), With<CameraControls>>,
+) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let Some((min, max)) = editor_world
+        .buildings()
+        .iter()
+        .map(|building| {
+            let (min, max) = building.bounds();
+            (
+                grid_to_world(from_flat(min, building.floor_y())),
+                grid_to_world(from_flat(max, building.ceiling_y())),
+            )
+        })
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)))
+    else {
+        return;
+    };
+
+    for (mut transform, projection) in camera.iter_mut() {
+        let fov = match projection {
+            Projection::Perspective(perspective) => perspective.fov,
+            _ => PerspectiveProjection::default().fov,
+        };
+        *transform = framing_transform(min, max, fov);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The planes of an axis-aligned box, in Hammer units, clockwise as seen from outside.
+    fn box_planes(min: Vec3, max: Vec3) -> Vec<[Vec3; 3]> {
+        let (x0, y0, z0) = min.into();
+        let (x1, y1, z1) = max.into();
+        vec![
+            [
+                Vec3::new(x0, y1, z1),
+                Vec3::new(x1, y1, z1),
+                Vec3::new(x1, y0, z1),
+            ],
+            [
+                Vec3::new(x0, y0, z0),
+                Vec3::new(x1, y0, z0),
+                Vec3::new(x1, y1, z0),
+            ],
+            [
+                Vec3::new(x0, y1, z1),
+                Vec3::new(x0, y0, z1),
+                Vec3::new(x0, y0, z0),
+            ],
+            [
+                Vec3::new(x1, y1, z0),
+                Vec3::new(x1, y0, z0),
+                Vec3::new(x1, y0, z1),
+            ],
+            [
+                Vec3::new(x1, y1, z1),
+                Vec3::new(x0, y1, z1),
+                Vec3::new(x0, y1, z0),
+            ],
+            [
+                Vec3::new(x1, y0, z0),
+                Vec3::new(x0, y0, z0),
+                Vec3::new(x0, y0, z1),
+            ],
+        ]
+    }
+
+    /// The text of a VMF map with one solid in the world for each list of planes.
+    fn vmf_text(solids: &[Vec<[Vec3; 3]>]) -> String {
+        let mut vmf = String::from(
+            "versioninfo\n{\n\t\"editorversion\" \"400\"\n\t\"mapversion\" \"1\"\n\
+             \t\"formatversion\" \"100\"\n\t\"prefab\" \"0\"\n}\n\
+             world\n{\n\t\"id\" \"1\"\n\t\"mapversion\" \"1\"\n\t\"classname\" \"worldspawn\"\n",
+        );
+        let mut next_side = 1;
+        for (solid_id, planes) in (2..).zip(solids) {
+            vmf += &format!("\tsolid\n\t{{\n\t\t\"id\" \"{solid_id}\"\n");
+            for plane in planes {
+                let [p1, p2, p3] = plane.map(|p| format!("({} {} {})", p.x, p.y, p.z));
+                vmf += &format!(
+                    "\t\tside\n\t\t{{\n\t\t\t\"id\" \"{next_side}\"\n\
+                     \t\t\t\"plane\" \"{p1} {p2} {p3}\"\n\
+                     \t\t\t\"material\" \"DEV/DEV_MEASUREGENERIC01B\"\n\
+                     \t\t\t\"uaxis\" \"[1 0 0 0] 0.25\"\n\
+                     \t\t\t\"vaxis\" \"[0 -1 0 0] 0.25\"\n\
+                     \t\t\t\"rotation\" \"0\"\n\
+                     \t\t\t\"lightmapscale\" \"16\"\n\
+                     \t\t\t\"smoothing_groups\" \"0\"\n\t\t}}\n"
+                );
+                next_side += 1;
+            }
+            vmf += "\t}\n";
+        }
+        vmf += "}\n";
+        vmf
+    }
+
+    #[test]
+    fn imports_one_building_per_prism_solid() {
+        let first = box_planes(Vec3::ZERO, Vec3::new(512., 512., 256.));
+        let second = box_planes(Vec3::new(2048., 0., 0.), Vec3::new(2560., 512., 384.));
+        // A box with a sloped top isn't a vertical prism.
+        let mut sloped = box_planes(Vec3::new(0., 2048., 0.), Vec3::new(512., 2560., 256.));
+        sloped[0][1].z += 64.;
+        sloped[0][2].z += 64.;
+        // A box thinner than a voxel doesn't fit on the grid.
+        let thin = box_planes(Vec3::new(0., -2048., 0.), Vec3::new(16., -1536., 256.));
+
+        let level = vmf_forge::VmfFile::parse(&vmf_text(&[first, second, sloped, thin])).unwrap();
+        let mut editor_world = EditorWorld::default();
+        import_vmf_buildings(&mut editor_world, &level);
+
+        let buildings = editor_world.buildings();
+        assert_eq!(buildings.len(), 2);
+        assert_eq!(buildings[0].height, 2);
+        assert_eq!(buildings[1].height, 3);
+
+        // The whole import is one undo step.
+        editor_world.undo();
+        assert!(editor_world.buildings().is_empty());
+    }
+
+    #[test]
+    fn framing_keeps_the_whole_box_in_view() {
+        let (min, max) = (Vec3::new(-512., 0., 256.), Vec3::new(1024., 384., 2048.));
+        let fov = PerspectiveProjection::default().fov;
+        let transform = framing_transform(min, max, fov);
+
+        let center = (min + max) / 2.;
+        assert!(
+            transform
+                .forward()
+                .dot((center - transform.translation).normalize())
+                > 0.999
+        );
+        for corner in [
+            min,
+            max,
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, min.y, min.z),
+        ] {
+            let to_corner = (corner - transform.translation).normalize();
+            assert!(transform.forward().angle_between(to_corner) <= fov / 2. + 1e-4);
+        }
+    }
+
+    #[test]
+    fn exported_walls_import_as_buildings() {
+        // Walls this thick are whole grid units, so that they survive the import.
+        let mut room = Building::new(
+            0,
+            vec![
+                IVec2::new(0, 0),
+                IVec2::new(8, 0),
+                IVec2::new(8, 8),
+                IVec2::new(0, 8),
+            ],
+        );
+        room.wall_thickness = 2.5;
+
+        let text = crate::export::buildings_to_vmf(&[room]).to_vmf_string();
+        let level = vmf_forge::VmfFile::parse(&text).unwrap();
+        // Four walls, and a floor and ceiling slab of two triangles each.
+        assert_eq!(level.world.solids.len(), 8);
+
+        // The slabs are thinner than a voxel, so only the walls become buildings.
+        let mut editor_world = EditorWorld::default();
+        import_vmf_buildings(&mut editor_world, &level);
+        let mut footprints: Vec<Vec<[i32; 2]>> = editor_world
+            .buildings()
+            .iter()
+            .map(|building| {
+                assert_eq!((building.floor_y(), building.ceiling_y()), (0, 2));
+                let mut points: Vec<[i32; 2]> =
+                    building.points().iter().map(|p| p.to_array()).collect();
+                points.sort();
+                points
+            })
+            .collect();
+        footprints.sort();
+
+        let wall = |mut points: [[i32; 2]; 4]| {
+            points.sort();
+            points.to_vec()
+        };
+        let mut expected = vec![
+            wall([[0, 0], [8, 0], [6, 2], [2, 2]]),
+            wall([[8, 0], [8, 8], [6, 6], [6, 2]]),
+            wall([[8, 8], [0, 8], [2, 6], [6, 6]]),
+            wall([[0, 8], [0, 0], [2, 2], [2, 6]]),
+        ];
+        expected.sort();
+        assert_eq!(footprints, expected);
+    }
+}
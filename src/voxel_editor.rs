@@ -1,23 +1,169 @@
-#![allow(clippy::too_many_arguments, unused)]
-
-use crate::common_assets::Common;
-use crate::voxels::{CommittedEditorState, SelectedFace, VOXEL_SIZE, VoxelMarker, Voxels};
+use crate::common_assets::{Common, setup_common};
+use crate::editor_actions::SelectedTriangle;
+use crate::editor_state::{EditorTool, EditorWorld};
+use crate::flycam::CameraControls;
+use crate::keybindings::Keybindings;
+use crate::preview::{PreviewStats, Previewer};
+use crate::voxels::{
+    CommittedEditorState, SelectedFace, VOXEL_SIZE, VoxelMarker, VoxelTags, Voxels,
+};
 use bevy::{
     picking::backend::ray::RayMap,
     platform::collections::{HashMap, HashSet},
     prelude::*,
+    render::view::RenderLayers,
 };
 use std::hash::Hash;
 
-#[derive(Resource)]
+pub struct VoxelEditorPlugin;
+
+impl Plugin for VoxelEditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Voxels::new_empty())
+            .add_systems(Startup, setup_voxel_palette.after(setup_common))
+            .init_resource::<EditorSelected>()
+            .init_resource::<EditorBrush>()
+            .add_systems(
+                Update,
+                (
+                    editor_record_system,
+                    (
+                        seed_origin_voxel_system,
+                        editor_select_system,
+                        editor_box_fill_system,
+                        editor_select_preview_system,
+                    )
+                        .chain()
+                        .run_if(voxel_tool_active),
+                    editor_undo_system,
+                    editor_ghost_preview_system,
+                    editor_voxel_wireframe_system,
+                    editor_voxel_tags_system,
+                    editor_visualize_area_system,
+                )
+                    .chain(),
+            );
+    }
+}
+
+fn setup_voxel_palette(mut commands: Commands, common: Res<Common>) {
+    commands.insert_resource(VoxelPalette::new(&common));
+}
+
+/// Whether the `EditorTool::EditVoxels` tool is active, which the voxel editing systems need.
+fn voxel_tool_active(editor_world: Res<EditorWorld>) -> bool {
+    matches!(editor_world.tool(), EditorTool::EditVoxels)
+}
+
+/// Adds the origin voxel, which is needed to click on to start building, when it is missing.
+fn seed_origin_voxel_system(
+    mut commands: Commands,
+    common: Res<Common>,
+    mut voxels: ResMut<Voxels>,
+) {
+    if !voxels.has_voxel(IVec3::ZERO) {
+        voxels.add_origin_voxel(&mut commands, &common, common.gray_material.clone());
+    }
+}
+
+#[derive(Resource, Default)]
 struct EditorSelected(HashSet<SelectedFace>);
 
+/// The materials which can be painted onto voxels, selected with `Keybindings::palette`.
 #[derive(Resource)]
-struct EditorCurrentMaterial(Handle<StandardMaterial>);
+pub struct VoxelPalette {
+    pub materials: Vec<Handle<StandardMaterial>>,
+    /// The index of the material new voxels are painted with.
+    pub selected: usize,
+}
+
+impl VoxelPalette {
+    /// Creates a palette of the voxel materials in `Common`, with the first one selected.
+    pub fn new(common: &Common) -> Self {
+        Self {
+            materials: vec![
+                common.gray_material.clone(),
+                common.red_material.clone(),
+                common.blue_material.clone(),
+                common.outside_material.clone(),
+            ],
+            selected: 0,
+        }
+    }
+
+    /// The material new voxels are painted with.
+    pub fn current(&self) -> Handle<StandardMaterial> {
+        self.materials[self.selected].clone()
+    }
+}
+
+/// The shape of the voxel brush.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum BrushShape {
+    Cube,
+    Sphere,
+}
+
+/// The brush used to fill or erase voxels around each selected voxel.
+#[derive(Resource)]
+struct EditorBrush {
+    /// The width of a cube brush, in voxels.
+    /// For a sphere brush, the radius is one less than this, so that size 1 is a single voxel.
+    size: i32,
+    shape: BrushShape,
+}
+
+impl Default for EditorBrush {
+    fn default() -> Self {
+        Self {
+            size: 1,
+            shape: BrushShape::Cube,
+        }
+    }
+}
+
+impl EditorBrush {
+    const MAX_SIZE: i32 = 5;
+
+    /// Returns the inclusive range of offsets covered by the brush along each axis.
+    /// Even-sized cube brushes extend one further in the positive direction.
+    fn extent(&self) -> (i32, i32) {
+        match self.shape {
+            BrushShape::Cube => {
+                let low = -(self.size - 1) / 2;
+                (low, low + self.size - 1)
+            }
+            BrushShape::Sphere => (-self.radius(), self.radius()),
+        }
+    }
+
+    /// The radius of a sphere brush, in voxels.
+    fn radius(&self) -> i32 {
+        self.size - 1
+    }
+
+    /// Returns the offsets of every cell covered by the brush, relative to its center cell.
+    fn offsets(&self) -> Vec<IVec3> {
+        let (low, high) = self.extent();
+        let mut offsets = Vec::new();
+        for x in low..=high {
+            for y in low..=high {
+                for z in low..=high {
+                    let offset = IVec3::new(x, y, z);
+                    if self.shape == BrushShape::Sphere
+                        && offset.length_squared() > self.radius() * self.radius()
+                    {
+                        continue;
+                    }
+                    offsets.push(offset);
+                }
+            }
+        }
+        offsets
+    }
+}
 
 fn editor_select_system(
-    // mut commands: Commands,
-    // common: Res<Common>,
     mut gizmos: Gizmos,
     mut cast: MeshRayCast,
     ray_map: Res<RayMap>, // The ray map stores rays cast by the cursor
@@ -25,6 +171,9 @@ fn editor_select_system(
     mouse_button: Res<ButtonInput<MouseButton>>,
     keys: Res<ButtonInput<KeyCode>>,
     mut selected: ResMut<EditorSelected>,
+    brush: Res<EditorBrush>,
+    palette: Res<VoxelPalette>,
+    materials: Res<Assets<StandardMaterial>>,
 ) {
     if mouse_button.just_pressed(MouseButton::Left)
         && !keys.pressed(KeyCode::ShiftLeft)
@@ -51,16 +200,54 @@ fn editor_select_system(
     let marked_voxel = voxel_marker.get(hit_entity).expect("must exist");
     let hit_normal = hit_info.normal.normalize();
 
+    let cursor_color = if is_erasing(&keys) {
+        Color::linear_rgb(1., 0., 0.)
+    } else {
+        Color::linear_rgb(0., 0., 1.)
+    };
+
     for s in [0.9, 0.5] {
         gizmos.cuboid(
             Transform::from_translation(
                 marked_voxel.center() + Vec3::splat(VOXEL_SIZE) * 0.5 * hit_normal,
             )
             .with_scale(VOXEL_SIZE * s * (Vec3::splat(1.) - hit_normal.abs() * 0.9)),
-            Color::linear_rgb(0., 0., 1.),
+            cursor_color,
         );
     }
 
+    // A swatch of the current palette material, just off the hovered face.
+    if let Some(material) = materials.get(&palette.current()) {
+        gizmos.sphere(
+            marked_voxel.center() + VOXEL_SIZE * 0.8 * hit_normal,
+            VOXEL_SIZE * 0.1,
+            material.base_color,
+        );
+    }
+
+    if brush.size > 1 {
+        // Outline the cells the brush will cover around the hovered voxel.
+        match brush.shape {
+            BrushShape::Cube => {
+                let (low, high) = brush.extent();
+                gizmos.cuboid(
+                    Transform::from_translation(
+                        marked_voxel.center() + Vec3::splat((low + high) as f32 / 2. * VOXEL_SIZE),
+                    )
+                    .with_scale(Vec3::splat(brush.size as f32 * VOXEL_SIZE)),
+                    cursor_color,
+                );
+            }
+            BrushShape::Sphere => {
+                gizmos.sphere(
+                    marked_voxel.center(),
+                    (brush.radius() as f32 + 0.5) * VOXEL_SIZE,
+                    cursor_color,
+                );
+            }
+        }
+    }
+
     if mouse_button.pressed(MouseButton::Left) {
         selected.0.insert(SelectedFace {
             voxel: marked_voxel.0,
@@ -69,26 +256,153 @@ fn editor_select_system(
     }
 }
 
+/// Shows ghosts where extruding the hovered voxel face would place voxels, including symmetric
+/// copies. The ghosts are removed when the `EditorTool::EditVoxels` tool is not active.
+fn editor_ghost_preview_system(
+    mut commands: Commands,
+    common: Res<Common>,
+    voxels: Res<Voxels>,
+    editor_world: Res<EditorWorld>,
+    mut preview: Local<Option<Previewer<IVec3>>>,
+    mut preview_stats: ResMut<PreviewStats>,
+    mut cast: MeshRayCast,
+    ray_map: Res<RayMap>,
+    voxel_marker: Query<&VoxelMarker>,
+) {
+    let preview = preview.get_or_insert_with(|| Previewer::with_pooling(8));
+    preview_stats.record("voxel ghosts", preview);
+    let mut preview = preview.collect_scope(&mut commands);
+
+    if !matches!(editor_world.tool(), EditorTool::EditVoxels) {
+        return;
+    }
+    let Some((_, mouse_ray)) = ray_map.iter().next() else {
+        return;
+    };
+    let hit = cast
+        .cast_ray(
+            *mouse_ray,
+            &MeshRayCastSettings::default()
+                .with_filter(&|hit_entity| voxel_marker.contains(hit_entity)),
+        )
+        .first()
+        .cloned();
+    let Some((hit_entity, hit_info)) = hit else {
+        return;
+    };
+    let marked_voxel = voxel_marker.get(hit_entity).expect("must exist");
+
+    let extrude_target = marked_voxel.0 + hit_info.normal.normalize().round().as_ivec3();
+    for ghost in std::iter::once(extrude_target).chain(voxels.apply_symmetry(extrude_target)) {
+        preview.render_bundle(&ghost, || {
+            (
+                Transform::from_translation(voxels.voxel_center(ghost))
+                    .with_scale(Vec3::splat(VOXEL_SIZE * 0.5)),
+                Mesh3d(common.cube_mesh.clone()),
+                MeshMaterial3d(common.ui_gold_material.clone()),
+                RenderLayers::layer(7),
+            )
+        });
+    }
+}
+
+/// Fills a box of voxels between two corners. `Ctrl`-clicking a voxel face sets the first corner
+/// in front of it, and `Ctrl`-clicking again fills the box to the second corner. The box is
+/// previewed in between, and `Keybindings::cancel` abandons it.
+fn editor_box_fill_system(
+    mut commands: Commands,
+    common: Res<Common>,
+    mut voxels: ResMut<Voxels>,
+    mut gizmos: Gizmos,
+    mut cast: MeshRayCast,
+    ray_map: Res<RayMap>,
+    voxel_marker: Query<&VoxelMarker>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+    palette: Res<VoxelPalette>,
+    mut first_corner: Local<Option<IVec3>>,
+) {
+    if keys.just_pressed(bindings.cancel) {
+        *first_corner = None;
+    }
+
+    let Some((_, mouse_ray)) = ray_map.iter().next() else {
+        return;
+    };
+    let hit = cast
+        .cast_ray(
+            *mouse_ray,
+            &MeshRayCastSettings::default()
+                .with_filter(&|hit_entity| voxel_marker.contains(hit_entity)),
+        )
+        .first()
+        .cloned();
+    let Some((hit_entity, hit_info)) = hit else {
+        return;
+    };
+    let marked_voxel = voxel_marker.get(hit_entity).expect("must exist");
+    let corner = marked_voxel.0 + hit_info.normal.normalize().round().as_ivec3();
+
+    if let Some(first) = *first_corner {
+        let (min, max) = (first.min(corner), first.max(corner));
+        gizmos.cuboid(
+            Transform::from_translation(
+                (VoxelMarker(min).center() + VoxelMarker(max).center()) / 2.,
+            )
+            .with_scale((max - min + IVec3::ONE).as_vec3() * VOXEL_SIZE),
+            Color::linear_rgb(1., 1., 0.3),
+        );
+    }
+
+    let ctrl = keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight);
+    if !ctrl || !mouse_button.just_pressed(MouseButton::Left) {
+        return;
+    }
+    match first_corner.take() {
+        None => *first_corner = Some(corner),
+        Some(first) => {
+            voxels.fill_box(&mut commands, &common, first, corner, palette.current());
+        }
+    }
+}
+
+/// While `Alt` is held, painting voxels erases them instead.
+fn is_erasing(keys: &ButtonInput<KeyCode>) -> bool {
+    keys.pressed(KeyCode::AltLeft) || keys.pressed(KeyCode::AltRight)
+}
+
 fn editor_select_preview_system(
     mut commands: Commands,
     common: Res<Common>,
     mut gizmos: Gizmos,
     mut voxels: ResMut<Voxels>,
     keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
     mut selected: ResMut<EditorSelected>,
-    mut current_material: ResMut<EditorCurrentMaterial>,
+    mut palette: ResMut<VoxelPalette>,
+    mut brush: ResMut<EditorBrush>,
 ) {
-    if keys.just_pressed(KeyCode::Digit1) {
-        current_material.0 = common.gray_material.clone();
+    if keys.just_pressed(bindings.brush_larger) {
+        brush.size = (brush.size + 1).min(EditorBrush::MAX_SIZE);
     }
-    if keys.just_pressed(KeyCode::Digit2) {
-        current_material.0 = common.red_material.clone();
+    if keys.just_pressed(bindings.brush_smaller) {
+        brush.size = (brush.size - 1).max(1);
     }
-    if keys.just_pressed(KeyCode::Digit3) {
-        current_material.0 = common.blue_material.clone();
+    if keys.just_pressed(bindings.brush_shape) {
+        brush.shape = match brush.shape {
+            BrushShape::Cube => BrushShape::Sphere,
+            BrushShape::Sphere => BrushShape::Cube,
+        };
     }
-    if keys.just_pressed(KeyCode::Digit4) {
-        current_material.0 = common.outside_material.clone();
+
+    if let Some(index) = bindings
+        .palette
+        .iter()
+        .take(palette.materials.len())
+        .position(|&key| keys.just_pressed(key))
+    {
+        palette.selected = index;
     }
 
     for face in selected.0.iter() {
@@ -104,7 +418,7 @@ fn editor_select_preview_system(
         }
     }
 
-    if keys.just_pressed(KeyCode::KeyE) {
+    if keys.just_pressed(bindings.voxel_extrude) {
         // Extrude all selected voxel faces.
         let mut new_selected: Vec<SelectedFace> = Vec::new();
         let mut new_extrusions: HashSet<IVec3> = HashSet::new();
@@ -120,16 +434,11 @@ fn editor_select_preview_system(
                 normal: face.normal,
             });
             if !voxels.has_voxel(new_voxel) {
-                voxels.add_voxel(
-                    &mut commands,
-                    &common,
-                    new_voxel,
-                    current_material.0.clone(),
-                );
+                voxels.add_voxel(&mut commands, &common, new_voxel, palette.current());
             }
         }
         selected.0 = new_selected.into_iter().collect();
-    } else if keys.just_pressed(KeyCode::Delete) {
+    } else if keys.just_pressed(bindings.voxel_delete) {
         // Delete all selected voxel faces, and back up to the faces behind them.
         let mut new_selected: Vec<SelectedFace> = Vec::new();
         for face in selected.0.iter() {
@@ -144,7 +453,7 @@ fn editor_select_preview_system(
             .into_iter()
             .filter(|face| voxels.has_voxel(face.voxel))
             .collect();
-    } else if keys.just_pressed(KeyCode::KeyQ) {
+    } else if keys.just_pressed(bindings.voxel_depress) {
         // Depress the selected voxel faces, creating new geometry behind them if needed.
         // This will probably have some weird edge cases.
 
@@ -160,12 +469,7 @@ fn editor_select_preview_system(
 
         for new_face in new_selected.iter() {
             if !voxels.has_voxel(new_face.voxel) {
-                voxels.add_voxel(
-                    &mut commands,
-                    &common,
-                    new_face.voxel,
-                    current_material.0.clone(),
-                );
+                voxels.add_voxel(&mut commands, &common, new_face.voxel, palette.current());
             }
         }
 
@@ -196,19 +500,36 @@ fn editor_select_preview_system(
             .into_iter()
             .filter(|face| voxels.has_voxel(face.voxel))
             .collect();
-    } else if keys.just_pressed(KeyCode::KeyF) {
-        // Fill the selected voxels with the current color.
+    } else if keys.just_pressed(bindings.voxel_fill) && is_erasing(&keys) {
+        // Erase the brush around each selected voxel.
+        for face in selected.0.iter() {
+            for offset in brush.offsets() {
+                voxels.remove_voxel(&mut commands, face.voxel + offset);
+            }
+        }
+        selected.0.clear();
+    } else if keys.just_pressed(bindings.voxel_fill) {
+        // Fill the brush around each selected voxel with the current color.
 
         for face in selected.0.iter() {
-            voxels.add_voxel(
-                &mut commands,
-                &common,
-                face.voxel,
-                current_material.0.clone(),
-            );
+            for offset in brush.offsets() {
+                voxels.add_voxel(
+                    &mut commands,
+                    &common,
+                    face.voxel + offset,
+                    palette.current(),
+                );
+            }
         }
-    } else if keys.just_pressed(KeyCode::BracketLeft) || keys.just_pressed(KeyCode::BracketRight) {
-        let column_shift = if keys.just_pressed(KeyCode::BracketLeft) {
+    } else if keys.just_pressed(bindings.voxel_flood_fill) {
+        // Recolor the connected region of each selected voxel with the current color.
+        for face in selected.0.iter() {
+            voxels.flood_fill_material(&mut commands, &common, face.voxel, palette.current());
+        }
+    } else if keys.just_pressed(bindings.shift_column_down)
+        || keys.just_pressed(bindings.shift_column_up)
+    {
+        let column_shift = if keys.just_pressed(bindings.shift_column_down) {
             -32
         } else {
             32
@@ -223,33 +544,120 @@ fn editor_select_preview_system(
         }
     }
 }
-fn editor_record_system(mut voxels: ResMut<Voxels>, editor_selected: ResMut<EditorSelected>) {
+/// Toggles wireframe voxel display (`F10` by default), and draws the wireframes near the camera.
+fn editor_voxel_wireframe_system(
+    mut commands: Commands,
+    common: Res<Common>,
+    mut gizmos: Gizmos,
+    mut voxels: ResMut<Voxels>,
+    keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+    camera: Query<&Transform, With<CameraControls>>,
+) {
+    if keys.just_pressed(bindings.toggle_voxel_wireframe) {
+        let wireframe = !voxels.wireframe();
+        voxels.set_wireframe(&mut commands, &common, wireframe);
+    }
+
+    if !voxels.wireframe() {
+        return;
+    }
+
+    // Only draw the voxels near the camera, so that large builds stay fast.
+    let max_distance = 40. * VOXEL_SIZE;
+    let Some(camera_position) = camera.iter().next().map(|t| t.translation) else {
+        return;
+    };
+
+    for (voxel, voxel_info) in voxels.iter_voxels() {
+        let center = voxels.voxel_center(voxel);
+        if center.distance(camera_position) > max_distance {
+            continue;
+        }
+        let color = if voxel_info.material == common.outside_material {
+            Color::linear_rgb(0.5, 0.5, 0.5)
+        } else {
+            Color::linear_rgb(0.9, 0.9, 0.9)
+        };
+        gizmos.cuboid(
+            Transform::from_translation(center).with_scale(Vec3::splat(VOXEL_SIZE)),
+            color,
+        );
+    }
+}
+
+/// Marks tagged voxels with a small colored cube in their center.
+fn editor_voxel_tags_system(mut gizmos: Gizmos, voxels: Res<Voxels>) {
+    for (voxel, voxel_info) in voxels.iter_voxels() {
+        if voxel_info.tags.is_empty() {
+            continue;
+        }
+
+        let tag_colors = [
+            (VoxelTags::NO_BUILD, Color::linear_rgb(1., 0.2, 0.2)),
+            (VoxelTags::WATER, Color::linear_rgb(0.2, 0.5, 1.)),
+            (VoxelTags::TEAM_RED_ONLY, Color::linear_rgb(1., 0.5, 0.)),
+            (VoxelTags::TEAM_BLUE_ONLY, Color::linear_rgb(0., 0.8, 1.)),
+        ];
+
+        let center = voxels.voxel_center(voxel);
+        for (i, (tag, color)) in tag_colors.into_iter().enumerate() {
+            if voxel_info.tags.contains(tag) {
+                gizmos.cuboid(
+                    Transform::from_translation(center)
+                        .with_scale(Vec3::splat(VOXEL_SIZE * (0.3 + 0.1 * i as f32))),
+                    color,
+                );
+            }
+        }
+    }
+}
+
+fn editor_record_system(
+    mut voxels: ResMut<Voxels>,
+    editor_selected: ResMut<EditorSelected>,
+    selected_triangle: Res<SelectedTriangle>,
+) {
     let new_commited_state = Some(CommittedEditorState {
         selection: editor_selected.0.iter().copied().collect(),
+        world_face: selected_triangle.0,
     });
     if voxels.editor_state_before != new_commited_state {
         voxels.editor_state_before = new_commited_state;
     }
 }
+
+/// Commits voxel edits made this frame as one action. Undoes the last action with `Ctrl+Z`, and
+/// redoes it with `Ctrl+Shift+Z`, while a tool which edits voxels is active.
 fn editor_undo_system(
     mut commands: Commands,
     common: Res<Common>,
     mut voxels: ResMut<Voxels>,
     keys: Res<ButtonInput<KeyCode>>,
+    bindings: Res<Keybindings>,
+    editor_world: Res<EditorWorld>,
     mut editor_selected: ResMut<EditorSelected>,
+    mut selected_triangle: ResMut<SelectedTriangle>,
 ) {
     if voxels.has_changes_to_commit() {
         let editor_state_before = voxels.editor_state_before.take().unwrap();
         voxels.commit_changes(editor_state_before);
     }
 
-    if keys.just_pressed(KeyCode::KeyZ)
+    if editor_world.tool().undoes_voxels()
+        && keys.just_pressed(bindings.undo)
         && (keys.pressed(KeyCode::ControlLeft) || keys.pressed(KeyCode::ControlRight))
     {
-        let undo_editor = voxels.undo_last_action(&mut commands, &common);
+        if keys.pressed(KeyCode::ShiftLeft) || keys.pressed(KeyCode::ShiftRight) {
+            // The selection is left as it is, since only the state before the action is known.
+            voxels.redo_last_action(&mut commands, &common);
+        } else {
+            let undo_editor = voxels.undo_last_action(&mut commands, &common);
 
-        // Revert the editor state to how it was before the action that was just undone.
-        editor_selected.0 = undo_editor.selection.into_iter().collect();
+            // Revert the editor state to how it was before the action that was just undone.
+            editor_selected.0 = undo_editor.selection.into_iter().collect();
+            selected_triangle.0 = undo_editor.world_face;
+        }
     }
 }
 
@@ -414,28 +822,3 @@ fn editor_visualize_area_system(
 
     vizu_map.draw(&mut commands, &common);
 }
-
-static EDITABLE_LEVEL: std::sync::Mutex<Option<vmf_forge::VmfFile>> = std::sync::Mutex::new(None);
-
-#[wasm_bindgen::prelude::wasm_bindgen]
-extern "C" {
-    /// Send a message to the client.
-    pub fn tfbe_ffi_alert(s: &str);
-}
-
-#[wasm_bindgen::prelude::wasm_bindgen]
-pub fn tfbe_ffi_load_file(file_contents: &str) {
-    tfbe_ffi_alert(&format!("Loading file with {} bytes", file_contents.len()));
-
-    let parsed_file: Result<vmf_forge::VmfFile, _> = vmf_forge::VmfFile::parse(file_contents);
-
-    match parsed_file {
-        Ok(parsed_file) => {
-            *EDITABLE_LEVEL.lock().unwrap() = Some(parsed_file);
-            tfbe_ffi_alert("Loaded file!");
-        }
-        Err(err) => {
-            tfbe_ffi_alert(&format!("Failed to parse file: {err}"));
-        }
-    }
-}
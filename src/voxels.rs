@@ -1,40 +1,145 @@
-use bevy::{platform::collections::HashMap, prelude::*};
+use bevy::{
+    platform::collections::{HashMap, HashSet},
+    prelude::*,
+};
 
+use std::collections::VecDeque;
+
+use crate::SurfaceDetail;
+use crate::building::Building;
 use crate::common_assets::Common;
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 pub struct VoxelInfo {
     pub material: Handle<StandardMaterial>,
     pub rendered: Option<Entity>,
+    pub tags: VoxelTags,
+}
+
+/// A set of gameplay tags attached to a voxel.
+#[derive(Copy, Clone, Default, Eq, PartialEq, Debug, Hash)]
+pub struct VoxelTags(u8);
+
+impl VoxelTags {
+    pub const NONE: Self = Self(0);
+    /// Players cannot build on this voxel.
+    pub const NO_BUILD: Self = Self(1 << 0);
+    /// This voxel is filled with water.
+    pub const WATER: Self = Self(1 << 1);
+    /// Only the red team can pass through this voxel.
+    pub const TEAM_RED_ONLY: Self = Self(1 << 2);
+    /// Only the blue team can pass through this voxel.
+    pub const TEAM_BLUE_ONLY: Self = Self(1 << 3);
+
+    /// Returns whether all of the tags in `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns whether no tags are set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Sets all of the tags in `other`.
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    /// Clears all of the tags in `other`.
+    pub fn remove(&mut self, other: Self) {
+        self.0 &= !other.0;
+    }
+}
+
+impl std::ops::BitOr for VoxelTags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
 }
 
-#[allow(unused)]
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Hash)]
 pub enum SymmetryKind {
     None,
+    /// A half turn about the vertical axis through the origin.
     Rotation,
+    /// A mirror across the plane `x = 0`.
     MirrorX,
+    /// A mirror across the plane `z = 0`.
+    MirrorZ,
+    /// Mirrors across both the `x = 0` and `z = 0` planes, giving four copies.
+    QuadMirror,
+}
+
+impl SymmetryKind {
+    /// Returns the next kind of symmetry, for cycling through all of them.
+    pub fn next(self) -> Self {
+        match self {
+            SymmetryKind::None => SymmetryKind::Rotation,
+            SymmetryKind::Rotation => SymmetryKind::MirrorX,
+            SymmetryKind::MirrorX => SymmetryKind::MirrorZ,
+            SymmetryKind::MirrorZ => SymmetryKind::QuadMirror,
+            SymmetryKind::QuadMirror => SymmetryKind::None,
+        }
+    }
 }
 
-/// A function to apply an undo.
-type UndoFunction = dyn FnOnce(&mut Voxels, &mut Commands, &Common) + 'static + Send + Sync;
+/// A single change to the voxel grid, with enough information to undo or redo it.
+#[derive(Clone, Debug)]
+enum VoxelEdit {
+    /// A voxel was added to an empty cell.
+    Add {
+        voxel: IVec3,
+        material: Handle<StandardMaterial>,
+        tags: VoxelTags,
+    },
+    /// A voxel was removed.
+    Remove {
+        voxel: IVec3,
+        material: Handle<StandardMaterial>,
+        tags: VoxelTags,
+    },
+    /// The tags of a voxel were changed.
+    SetTags {
+        voxel: IVec3,
+        from: VoxelTags,
+        to: VoxelTags,
+    },
+    /// The material of a voxel was changed.
+    SetMaterial {
+        voxel: IVec3,
+        from: Handle<StandardMaterial>,
+        to: Handle<StandardMaterial>,
+    },
+    /// A column was shifted up or down.
+    ShiftColumn { column: IVec2, by: i32 },
+}
 
 #[derive(Resource)]
 pub struct Voxels {
     symmetry: SymmetryKind,
     voxel_fill: HashMap<IVec3, VoxelInfo>,
-    /// A shift to apply to all voxels in a column.
-    /// This shift should be less than half the voxel grid size.
+    /// A shift to apply to all voxels in a column, in world units.
+    /// This shift is kept within `MAX_COLUMN_SHIFT`.
     column_shift: HashMap<IVec2, i32>,
 
-    /// Functions to undo operations to the voxel data.
-    undo_log: Vec<Box<UndoFunction>>,
+    /// If set, voxels are not spawned as solid meshes, and are drawn as wireframe outlines instead.
+    wireframe: bool,
+
+    /// Operations on the voxel data, which can be undone.
+    undo_log: Vec<VoxelEdit>,
 
     /// The editor state just before applying the last action.
     pub editor_state_before: Option<CommittedEditorState>,
 
     /// The index to roll back to when performing an 'undo'.
     undo_commit_indexes: Vec<(usize, CommittedEditorState)>,
+
+    /// Actions which have been undone, most recent last, along with the editor state committed
+    /// with them. Cleared when a new action is committed.
+    redo_log: Vec<(Vec<VoxelEdit>, CommittedEditorState)>,
 }
 
 #[derive(Component)]
@@ -52,19 +157,87 @@ pub struct SelectedFace {
     pub voxel: IVec3,
     pub normal: IVec3,
 }
+
+/// A triangle of the world geometry, selected with the `EditorTool::SelectFace` tool.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SelectedWorldFace {
+    /// The corners of the triangle, in world space.
+    pub triangle: [Vec3; 3],
+    pub normal: Vec3,
+    pub detail: SurfaceDetail,
+}
+
 /// A snapshot of the editor state, for applying undos.
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct CommittedEditorState {
     pub selection: Vec<SelectedFace>,
+    /// The selected triangle of the world geometry, if any.
+    pub world_face: Option<SelectedWorldFace>,
 }
 
 impl Voxels {
-    /// Adds a function to the undo log.
-    fn add_undo_log(
+    /// Adds an operation which has just been applied to the undo log.
+    fn add_undo_log(&mut self, edit: VoxelEdit) {
+        self.undo_log.push(edit);
+    }
+
+    /// Applies an operation (if `forward` is set) or its inverse, without logging it.
+    fn apply_edit(
         &mut self,
-        f: impl FnOnce(&mut Voxels, &mut Commands, &Common) + 'static + Send + Sync,
+        commands: &mut Commands,
+        common: &Common,
+        edit: &VoxelEdit,
+        forward: bool,
     ) {
-        self.undo_log.push(Box::new(f));
+        match edit {
+            VoxelEdit::Add {
+                voxel,
+                material,
+                tags,
+            }
+            | VoxelEdit::Remove {
+                voxel,
+                material,
+                tags,
+            } => {
+                let adding = match edit {
+                    VoxelEdit::Add { .. } => forward,
+                    _ => !forward,
+                };
+                if adding {
+                    self.voxel_fill.insert(
+                        *voxel,
+                        VoxelInfo {
+                            material: material.clone(),
+                            rendered: None,
+                            tags: *tags,
+                        },
+                    );
+                    self.redraw_voxel(commands, common, *voxel);
+                } else if let Some(rendered) = self
+                    .voxel_fill
+                    .remove(voxel)
+                    .and_then(|mut voxel_info| voxel_info.rendered.take())
+                {
+                    commands.entity(rendered).despawn();
+                }
+            }
+            &VoxelEdit::SetTags { voxel, from, to } => {
+                if let Some(voxel_info) = self.voxel_fill.get_mut(&voxel) {
+                    voxel_info.tags = if forward { to } else { from };
+                }
+            }
+            VoxelEdit::SetMaterial { voxel, from, to } => {
+                if let Some(voxel_info) = self.voxel_fill.get_mut(voxel) {
+                    voxel_info.material = if forward { to.clone() } else { from.clone() };
+                }
+                self.redraw_voxel(commands, common, *voxel);
+            }
+            &VoxelEdit::ShiftColumn { column, by } => {
+                *self.column_shift.entry(column).or_default() += if forward { by } else { -by };
+                self.redraw_column(commands, common, column);
+            }
+        }
     }
 
     pub fn new_empty() -> Self {
@@ -72,27 +245,55 @@ impl Voxels {
             symmetry: SymmetryKind::Rotation,
             voxel_fill: HashMap::new(),
             column_shift: HashMap::new(),
+            wireframe: false,
             undo_log: Vec::new(),
             editor_state_before: None,
             undo_commit_indexes: Vec::new(),
+            redo_log: Vec::new(),
         }
     }
 
-    pub fn apply_symmetry(&self, voxel: IVec3) -> Option<IVec3> {
+    /// Returns the symmetry applied to edits.
+    pub fn symmetry(&self) -> SymmetryKind {
+        self.symmetry
+    }
+
+    /// Changes the symmetry applied to future edits. Existing voxels are left as they are.
+    pub fn set_symmetry(&mut self, symmetry: SymmetryKind) {
+        self.symmetry = symmetry;
+    }
+
+    /// Returns the voxels which mirror `voxel` under the current symmetry, not including `voxel`
+    /// itself. Each partner is listed once.
+    pub fn apply_symmetry(&self, voxel: IVec3) -> Vec<IVec3> {
         if voxel.xz() == IVec2::ZERO {
-            return None;
+            return Vec::new();
         }
 
-        match self.symmetry {
-            SymmetryKind::None => None,
-            SymmetryKind::Rotation => Some(IVec3::new(-voxel.x, voxel.y, -voxel.z)),
-            SymmetryKind::MirrorX => Some(IVec3::new(-voxel.x, voxel.y, voxel.z)),
+        let mirror_x = IVec3::new(-voxel.x, voxel.y, voxel.z);
+        let mirror_z = IVec3::new(voxel.x, voxel.y, -voxel.z);
+        let rotation = IVec3::new(-voxel.x, voxel.y, -voxel.z);
+        let candidates = match self.symmetry {
+            SymmetryKind::None => vec![],
+            SymmetryKind::Rotation => vec![rotation],
+            SymmetryKind::MirrorX => vec![mirror_x],
+            SymmetryKind::MirrorZ => vec![mirror_z],
+            SymmetryKind::QuadMirror => vec![mirror_x, mirror_z, rotation],
+        };
+
+        // Voxels on a mirror plane are their own reflection.
+        let mut partners: Vec<IVec3> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if candidate != voxel && !partners.contains(&candidate) {
+                partners.push(candidate);
+            }
         }
+        partners
     }
 
     pub fn remove_voxel(&mut self, commands: &mut Commands, voxel: IVec3) {
         self.remove_voxel_internal(commands, voxel);
-        if let Some(voxel) = self.apply_symmetry(voxel) {
+        for voxel in self.apply_symmetry(voxel) {
             self.remove_voxel_internal(commands, voxel);
         }
     }
@@ -104,15 +305,14 @@ impl Voxels {
         let Some(mut voxel_info) = self.voxel_fill.remove(&voxel) else {
             return;
         };
-        let rendered_entity = voxel_info.rendered.take();
 
-        self.add_undo_log(move |voxels, commands, common| {
-            // Re-insert and re-render the removed voxel.
-            voxels.voxel_fill.insert(voxel, voxel_info);
-            voxels.redraw_voxel(commands, common, voxel);
+        self.add_undo_log(VoxelEdit::Remove {
+            voxel,
+            material: voxel_info.material,
+            tags: voxel_info.tags,
         });
 
-        if let Some(entity) = rendered_entity {
+        if let Some(entity) = voxel_info.rendered.take() {
             commands.entity(entity).despawn();
         }
     }
@@ -125,16 +325,16 @@ impl Voxels {
     ) {
         self.add_voxel_internal(commands, common, voxel, mat.clone());
 
-        if let Some(voxel) = self.apply_symmetry(voxel) {
-            let complement_material = if mat == common.red_material {
-                common.blue_material.clone()
-            } else if mat == common.blue_material {
-                common.red_material.clone()
-            } else {
-                mat
-            };
-
-            self.add_voxel_internal(commands, common, voxel, complement_material);
+        // Symmetric copies belong to the other team.
+        let complement_material = if mat == common.red_material {
+            common.blue_material.clone()
+        } else if mat == common.blue_material {
+            common.red_material.clone()
+        } else {
+            mat
+        };
+        for voxel in self.apply_symmetry(voxel) {
+            self.add_voxel_internal(commands, common, voxel, complement_material.clone());
         }
     }
     pub fn add_voxel_internal(
@@ -151,19 +351,36 @@ impl Voxels {
             VoxelInfo {
                 material: mat.clone(),
                 rendered: None,
+                tags: VoxelTags::NONE,
             },
         );
         self.redraw_voxel(commands, common, voxel);
 
-        self.add_undo_log(move |voxels, commands, _common| {
-            let Some(mut voxel_info) = voxels.voxel_fill.remove(&voxel) else {
-                return;
-            };
-            if let Some(rendered_entity) = voxel_info.rendered.take() {
-                commands.entity(rendered_entity).despawn();
-            }
+        self.add_undo_log(VoxelEdit::Add {
+            voxel,
+            material: mat,
+            tags: VoxelTags::NONE,
         });
     }
+    /// Adds the voxel at the origin, which every build grows from. It is not recorded in the undo
+    /// log, and can't be removed.
+    pub fn add_origin_voxel(
+        &mut self,
+        commands: &mut Commands,
+        common: &Common,
+        mat: Handle<StandardMaterial>,
+    ) {
+        self.voxel_fill.insert(
+            IVec3::ZERO,
+            VoxelInfo {
+                material: mat,
+                rendered: None,
+                tags: VoxelTags::NONE,
+            },
+        );
+        self.redraw_voxel(commands, common, IVec3::ZERO);
+    }
+
     pub fn has_voxel(&self, voxel: IVec3) -> bool {
         self.voxel_fill.contains_key(&voxel)
     }
@@ -177,9 +394,105 @@ impl Voxels {
         Some(self.get_voxel(voxel)?.material.clone())
     }
 
+    /// Gets the tags of the voxel at the specified location, if any.
+    pub fn get_tags(&self, voxel: IVec3) -> Option<VoxelTags> {
+        Some(self.get_voxel(voxel)?.tags)
+    }
+
+    /// Replaces the tags of the voxel at the specified location, if there is one.
+    pub fn set_tags(&mut self, voxel: IVec3, tags: VoxelTags) {
+        let Some(voxel_info) = self.voxel_fill.get_mut(&voxel) else {
+            return;
+        };
+        let previous_tags = voxel_info.tags;
+        if previous_tags == tags {
+            return;
+        }
+        voxel_info.tags = tags;
+
+        self.add_undo_log(VoxelEdit::SetTags {
+            voxel,
+            from: previous_tags,
+            to: tags,
+        });
+    }
+
+    /// Replaces the material of the voxel at `start`, and of every voxel connected to it through
+    /// faces which has the same material, with `new_mat`.
+    ///
+    /// Symmetry is not applied. The edits are recorded in the undo log to be committed together as
+    /// one action.
+    pub fn flood_fill_material(
+        &mut self,
+        commands: &mut Commands,
+        common: &Common,
+        start: IVec3,
+        new_mat: Handle<StandardMaterial>,
+    ) {
+        let Some(old_mat) = self.get_material(start) else {
+            return;
+        };
+        if old_mat == new_mat {
+            return;
+        }
+
+        let mut visited: HashSet<IVec3> = HashSet::new();
+        let mut queue: VecDeque<IVec3> = VecDeque::new();
+        visited.insert(start);
+        queue.push_back(start);
+        while let Some(voxel) = queue.pop_front() {
+            let edit = VoxelEdit::SetMaterial {
+                voxel,
+                from: old_mat.clone(),
+                to: new_mat.clone(),
+            };
+            self.apply_edit(commands, common, &edit, true);
+            self.add_undo_log(edit);
+
+            for offset in [
+                IVec3::X,
+                IVec3::Y,
+                IVec3::Z,
+                IVec3::NEG_X,
+                IVec3::NEG_Y,
+                IVec3::NEG_Z,
+            ] {
+                let neighbor = voxel + offset;
+                if self.get_material(neighbor).as_ref() == Some(&old_mat)
+                    && visited.insert(neighbor)
+                {
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Returns the center of the voxel in world space, including its column shift.
+    pub fn voxel_center(&self, voxel: IVec3) -> Vec3 {
+        let column_shift = self.column_shift.get(&voxel.xz()).copied().unwrap_or(0);
+        Vec3::splat(VOXEL_SIZE) * voxel.as_vec3() + Vec3::Y * column_shift as f32
+    }
+
+    /// Returns whether voxels are displayed as wireframes rather than solid meshes.
+    pub fn wireframe(&self) -> bool {
+        self.wireframe
+    }
+
+    /// Switches between displaying voxels as solid meshes or as wireframes, redrawing every voxel.
+    pub fn set_wireframe(&mut self, commands: &mut Commands, common: &Common, wireframe: bool) {
+        if self.wireframe == wireframe {
+            return;
+        }
+        self.wireframe = wireframe;
+        for voxel in self.voxel_fill.keys().copied().collect::<Vec<IVec3>>() {
+            self.redraw_voxel(commands, common, voxel);
+        }
+    }
+
     /// Despawns and re-spawns the voxel at the given location.
     pub fn redraw_voxel(&mut self, commands: &mut Commands, common: &Common, voxel: IVec3) {
-        let column_shift = self.column_shift.get(&voxel.xz()).copied().unwrap_or(0);
+        let center = self.voxel_center(voxel);
+        let wireframe = self.wireframe;
 
         let Some(voxel_info) = self.voxel_fill.get_mut(&voxel) else {
             return;
@@ -190,13 +503,15 @@ impl Voxels {
             commands.entity(already_rendered).despawn();
         }
 
+        if wireframe {
+            // Wireframe voxels are drawn with gizmos instead.
+            return;
+        }
+
         let rendered = commands
             .spawn((
                 VoxelMarker(voxel),
-                Transform::from_translation(
-                    Vec3::splat(VOXEL_SIZE) * voxel.as_vec3() + Vec3::Y * column_shift as f32,
-                )
-                .with_scale(Vec3::splat(VOXEL_SIZE)),
+                Transform::from_translation(center).with_scale(Vec3::splat(VOXEL_SIZE)),
                 Mesh3d(common.cube_mesh.clone()),
                 MeshMaterial3d(voxel_info.material.clone()),
             ))
@@ -205,29 +520,35 @@ impl Voxels {
         voxel_info.rendered = Some(rendered);
     }
 
+    /// Shifts a column, limiting its total shift to `MAX_COLUMN_SHIFT`. Returns the amount the
+    /// column actually moved.
     fn shift_column_internal(
         &mut self,
         commands: &mut Commands,
         common: &Common,
         column: IVec2,
         by: i32,
-    ) {
-        // TODO: Place a limit on this.
-        *self.column_shift.entry(column).or_default() += by;
-        self.add_undo_log(move |voxels, commands, common| {
-            *voxels.column_shift.entry(column).or_default() -= by;
-
-            for voxel in voxels
-                .voxel_fill
-                .keys()
-                .copied()
-                .filter(|v| v.xz() == column)
-                .collect::<Vec<IVec3>>()
-            {
-                voxels.redraw_voxel(commands, common, voxel);
-            }
-        });
+    ) -> i32 {
+        let current = self.column_shift.get(&column).copied().unwrap_or(0);
+        let applied = current
+            .saturating_add(by)
+            .clamp(-MAX_COLUMN_SHIFT, MAX_COLUMN_SHIFT)
+            - current;
+        if applied == 0 {
+            return 0;
+        }
+
+        let edit = VoxelEdit::ShiftColumn {
+            column,
+            by: applied,
+        };
+        self.apply_edit(commands, common, &edit, true);
+        self.add_undo_log(edit);
+        applied
+    }
 
+    /// Redraws every voxel in a column.
+    fn redraw_column(&mut self, commands: &mut Commands, common: &Common, column: IVec2) {
         for voxel in self
             .voxel_fill
             .keys()
@@ -239,18 +560,22 @@ impl Voxels {
         }
     }
 
-    /// Shifts the target column up or down.
+    /// Shifts the target column up or down, along with its symmetric partners.
+    ///
+    /// The total shift of a column is limited to `MAX_COLUMN_SHIFT` either way. Returns the amount
+    /// the target column actually moved, which is less than `by` once the limit is reached.
     pub fn shift_column(
         &mut self,
         commands: &mut Commands,
         common: &Common,
         column: IVec2,
         by: i32,
-    ) {
-        self.shift_column_internal(commands, common, column, by);
-        if let Some(symmetric_voxel) = self.apply_symmetry(IVec3::new(column.x, 0, column.y)) {
+    ) -> i32 {
+        let applied = self.shift_column_internal(commands, common, column, by);
+        for symmetric_voxel in self.apply_symmetry(IVec3::new(column.x, 0, column.y)) {
             self.shift_column_internal(commands, common, symmetric_voxel.xz(), by);
         }
+        applied
     }
 
     /// Returns whether there are changes to commit.
@@ -258,16 +583,18 @@ impl Voxels {
         self.undo_commit_indexes
             .last()
             .map(|record| record.0)
-            .map(|index| index != self.undo_log.len())
-            .unwrap_or(true)
+            .unwrap_or(0)
+            != self.undo_log.len()
     }
 
     /// Save all of the most-recent changes in the undo log, so that they will be undone as a unit.
+    /// Actions which were undone can no longer be redone.
     ///
     /// Call `has_changes_to_commit` before calling this function.
     pub fn commit_changes(&mut self, editor_state: CommittedEditorState) {
         self.undo_commit_indexes
             .push((self.undo_log.len(), editor_state));
+        self.redo_log.clear();
     }
 
     /// Applies the undo functions for the last action.
@@ -278,6 +605,7 @@ impl Voxels {
     ) -> CommittedEditorState {
         static EMPTY_EDITOR_STATE: CommittedEditorState = CommittedEditorState {
             selection: Vec::new(),
+            world_face: None,
         };
 
         let last_editor_state = self
@@ -292,18 +620,360 @@ impl Voxels {
             .map(|pair| pair.0)
             .unwrap_or(0);
 
-        while self.undo_log.len() > undo_until {
-            let undo_func = self.undo_log.pop().unwrap();
-            undo_func(self, commands, common);
+        let undone = self.undo_log.split_off(undo_until.min(self.undo_log.len()));
+        for edit in undone.iter().rev() {
+            self.apply_edit(commands, common, edit, false);
+        }
+        if !undone.is_empty() {
+            self.redo_log.push((undone, last_editor_state.clone()));
         }
 
         last_editor_state
     }
 
+    /// Re-applies the most recently undone action, committing it again.
+    ///
+    /// Returns the editor state from just before the action, or `None` if there is nothing to
+    /// redo.
+    pub fn redo_last_action(
+        &mut self,
+        commands: &mut Commands,
+        common: &Common,
+    ) -> Option<CommittedEditorState> {
+        let (edits, editor_state) = self.redo_log.pop()?;
+        for edit in edits.iter() {
+            self.apply_edit(commands, common, edit, true);
+        }
+        self.undo_log.extend(edits);
+        self.undo_commit_indexes
+            .push((self.undo_log.len(), editor_state.clone()));
+
+        Some(editor_state)
+    }
+
     /// Iterates through all of the voxels in the grid.
     pub fn iter_voxels(&self) -> impl Iterator<Item = (IVec3, &VoxelInfo)> {
         self.voxel_fill.iter().map(|(p, v)| (*p, v))
     }
+
+    /// Iterates through the voxels in the box from `min` to `max` (inclusive).
+    ///
+    /// When the box has no more cells than there are voxels in the grid, each cell is looked up
+    /// directly. Otherwise, every voxel is scanned and filtered by the box, so that a huge box
+    /// over a sparse grid doesn't visit millions of empty cells.
+    pub fn iter_region(
+        &self,
+        min: IVec3,
+        max: IVec3,
+    ) -> impl Iterator<Item = (IVec3, &VoxelInfo)> + '_ {
+        let extent = |low: i32, high: i32| (high as i64 - low as i64 + 1).max(0);
+        let volume = extent(min.x, max.x)
+            .saturating_mul(extent(min.y, max.y))
+            .saturating_mul(extent(min.z, max.z));
+
+        if volume <= self.voxel_fill.len() as i64 {
+            Box::new((min.x..=max.x).flat_map(move |x| {
+                (min.y..=max.y).flat_map(move |y| {
+                    (min.z..=max.z).filter_map(move |z| {
+                        let voxel = IVec3::new(x, y, z);
+                        Some((voxel, self.voxel_fill.get(&voxel)?))
+                    })
+                })
+            })) as Box<dyn Iterator<Item = (IVec3, &VoxelInfo)> + '_>
+        } else {
+            Box::new(
+                self.iter_voxels()
+                    .filter(move |(voxel, _)| voxel.cmpge(min).all() && voxel.cmple(max).all()),
+            )
+        }
+    }
+
+    /// Fills every cell in the box between the corners `a` and `b` (inclusive) with voxels, using
+    /// `add_voxel` so that symmetry applies to each cell.
+    ///
+    /// The edits are recorded in the undo log to be committed together as one action.
+    pub fn fill_box(
+        &mut self,
+        commands: &mut Commands,
+        common: &Common,
+        a: IVec3,
+        b: IVec3,
+        mat: Handle<StandardMaterial>,
+    ) {
+        let (min, max) = (a.min(b), a.max(b));
+        for x in min.x..=max.x {
+            for y in min.y..=max.y {
+                for z in min.z..=max.z {
+                    self.add_voxel(commands, common, IVec3::new(x, y, z), mat.clone());
+                }
+            }
+        }
+    }
+
+    /// Fills the volume of a building with voxels, from its floor up to (but not including) its
+    /// ceiling. If `walls_only` is set, only the cells along the edge of the outline are filled.
+    ///
+    /// Cells follow the same convention as `extract_footprint`: a cell is filled when its center
+    /// lies inside the outline, so axis-aligned outlines round-trip exactly. Symmetry is not
+    /// applied, and the edits are recorded in the undo log to be committed together as one action.
+    pub fn rasterize_building(
+        &mut self,
+        commands: &mut Commands,
+        common: &Common,
+        building: &Building,
+        mat: Handle<StandardMaterial>,
+        walls_only: bool,
+    ) {
+        let is_inside = |cell: IVec2| building.contains(cell.as_vec2() + Vec2::splat(0.5));
+
+        let (min, max) = building.bounds();
+        for x in min.x..max.x {
+            for z in min.y..max.y {
+                let cell = IVec2::new(x, z);
+                if !is_inside(cell) {
+                    continue;
+                }
+                if walls_only
+                    && [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y]
+                        .into_iter()
+                        .all(|offset| is_inside(cell + offset))
+                {
+                    continue;
+                }
+                for y in building.floor_y()..building.ceiling_y() {
+                    self.add_voxel_internal(commands, common, IVec3::new(x, y, z), mat.clone());
+                }
+            }
+        }
+    }
+
+    /// Traces the outline of the filled voxels in layer `y`, for use as a `Building` outline.
+    ///
+    /// Each filled cell `(x, z)` covers the square from `(x, z)` to `(x + 1, z + 1)`. If the layer
+    /// contains several disconnected regions, only the largest one is traced, and holes inside it
+    /// are ignored. The outline is wound counterclockwise, with collinear points removed.
+    ///
+    /// Returns `None` if the layer is empty.
+    pub fn extract_footprint(&self, y: i32) -> Option<Vec<IVec2>> {
+        let mut unvisited: HashSet<IVec2> = self
+            .voxel_fill
+            .keys()
+            .filter(|voxel| voxel.y == y)
+            .map(|voxel| voxel.xz())
+            .collect();
+
+        // Find the largest 4-connected region.
+        let mut largest: Vec<IVec2> = Vec::new();
+        while let Some(&seed) = unvisited.iter().next() {
+            unvisited.remove(&seed);
+            let mut component = vec![seed];
+            let mut index = 0;
+            while index < component.len() {
+                let cell = component[index];
+                index += 1;
+                for offset in [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y] {
+                    if unvisited.remove(&(cell + offset)) {
+                        component.push(cell + offset);
+                    }
+                }
+            }
+            if component.len() > largest.len() {
+                largest = component;
+            }
+        }
+        let region: HashSet<IVec2> = largest.into_iter().collect();
+
+        // Collect the boundary edges of the region, keeping the interior on the left of each edge.
+        let mut edges: HashMap<IVec2, Vec<IVec2>> = HashMap::new();
+        for &cell in &region {
+            let corners = [
+                cell,
+                cell + IVec2::new(1, 0),
+                cell + IVec2::new(1, 1),
+                cell + IVec2::new(0, 1),
+            ];
+            // The neighbor across the edge which starts at each corner.
+            let across = [IVec2::NEG_Y, IVec2::X, IVec2::Y, IVec2::NEG_X];
+            for i in 0..4 {
+                if !region.contains(&(cell + across[i])) {
+                    edges
+                        .entry(corners[i])
+                        .or_default()
+                        .push(corners[(i + 1) % 4]);
+                }
+            }
+        }
+
+        // The lowest corner is always on the outer boundary, so holes are never traced.
+        let start = *edges.keys().min_by_key(|p| (p.y, p.x))?;
+        let mut outline = vec![start];
+        let mut current = start;
+        let mut direction = IVec2::X;
+        loop {
+            let ends = edges.get_mut(&current)?;
+            // Where the region touches itself diagonally, turn left to stay against the same cell.
+            let (index, _) = ends.iter().enumerate().max_by_key(|(_, end)| {
+                let step = **end - current;
+                (direction.perp_dot(step), direction.dot(step))
+            })?;
+            let next = ends.swap_remove(index);
+            direction = next - current;
+            current = next;
+            if current == start {
+                break;
+            }
+            outline.push(current);
+        }
+
+        let len = outline.len();
+        Some(
+            (0..len)
+                .filter(|&i| {
+                    let prev = outline[(i + len - 1) % len];
+                    let next = outline[(i + 1) % len];
+                    (outline[i] - prev).perp_dot(next - outline[i]) != 0
+                })
+                .map(|i| outline[i])
+                .collect(),
+        )
+    }
 }
 
 pub const VOXEL_SIZE: f32 = 128.0;
+
+/// The largest shift of a column in either direction, in world units. This keeps the shift below
+/// half of a voxel.
+pub const MAX_COLUMN_SHIFT: i32 = VOXEL_SIZE as i32 / 2 - 1;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::world::CommandQueue;
+
+    /// A `Common` whose handles are distinct but don't refer to any loaded assets.
+    fn test_common() -> Common {
+        let material = |id: u128| Handle::<StandardMaterial>::weak_from_u128(id);
+        Common {
+            cube_mesh: Handle::default(),
+            plane_mesh: Handle::default(),
+            gray_material: material(1),
+            xray_blue_material: material(2),
+            ui_gold_material: material(3),
+            blue_material: material(4),
+            red_material: material(5),
+            sky_material: material(6),
+            outside_material: material(7),
+        }
+    }
+
+    #[test]
+    fn flood_fill_recolors_connected_voxels_with_the_same_material() {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let common = test_common();
+
+        let mut voxels = Voxels::new_empty();
+        let red = common.red_material.clone();
+        let blue = common.blue_material.clone();
+        for (voxel, material) in [
+            (IVec3::new(1, 0, 0), &red),
+            (IVec3::new(2, 0, 0), &red),
+            (IVec3::new(2, 1, 0), &red),
+            // A different material stops the fill.
+            (IVec3::new(3, 0, 0), &blue),
+            (IVec3::new(4, 0, 0), &red),
+            // Touching only along an edge isn't connected.
+            (IVec3::new(3, 1, 1), &red),
+        ] {
+            voxels.add_voxel_internal(&mut commands, &common, voxel, material.clone());
+        }
+
+        voxels.flood_fill_material(
+            &mut commands,
+            &common,
+            IVec3::new(1, 0, 0),
+            common.gray_material.clone(),
+        );
+
+        let recolored: HashSet<IVec3> = voxels
+            .iter_voxels()
+            .filter(|(_, info)| info.material == common.gray_material)
+            .map(|(voxel, _)| voxel)
+            .collect();
+        assert_eq!(
+            recolored,
+            HashSet::from_iter([
+                IVec3::new(1, 0, 0),
+                IVec3::new(2, 0, 0),
+                IVec3::new(2, 1, 0),
+            ])
+        );
+    }
+
+    #[test]
+    fn repeated_large_column_shifts_saturate() {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let common = test_common();
+
+        let mut voxels = Voxels::new_empty();
+        let column = IVec2::new(2, -3);
+
+        assert_eq!(
+            voxels.shift_column(&mut commands, &common, column, i32::MAX),
+            MAX_COLUMN_SHIFT
+        );
+        assert_eq!(
+            voxels.shift_column(&mut commands, &common, column, i32::MAX),
+            0
+        );
+        assert_eq!(voxels.column_shift[&column], MAX_COLUMN_SHIFT);
+
+        let mut total_down = 0;
+        for _ in 0..3 {
+            total_down += voxels.shift_column(&mut commands, &common, column, i32::MIN);
+        }
+        assert_eq!(total_down, -2 * MAX_COLUMN_SHIFT);
+        assert_eq!(voxels.column_shift[&column], -MAX_COLUMN_SHIFT);
+    }
+
+    #[test]
+    fn iter_region_matches_filtered_iter_voxels() {
+        let world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let common = test_common();
+
+        let mut voxels = Voxels::new_empty();
+        for x in -3..=3 {
+            for z in -2..=2 {
+                let voxel = IVec3::new(x, (x * z).rem_euclid(3), z);
+                voxels.add_voxel_internal(
+                    &mut commands,
+                    &common,
+                    voxel,
+                    common.gray_material.clone(),
+                );
+            }
+        }
+
+        // Small regions are scanned cell by cell, and large ones filter every voxel.
+        for (min, max) in [
+            (IVec3::new(-1, 0, -1), IVec3::new(1, 1, 1)),
+            (IVec3::new(0, 0, 0), IVec3::new(0, 0, 0)),
+            (IVec3::new(-100, -100, -100), IVec3::new(100, 100, 100)),
+            (IVec3::new(2, 0, -5), IVec3::new(10, 2, 0)),
+            (IVec3::new(1, 0, 0), IVec3::new(0, 0, 0)),
+        ] {
+            let region: HashSet<IVec3> = voxels.iter_region(min, max).map(|(v, _)| v).collect();
+            let filtered: HashSet<IVec3> = voxels
+                .iter_voxels()
+                .map(|(v, _)| v)
+                .filter(|v| v.cmpge(min).all() && v.cmple(max).all())
+                .collect();
+            assert_eq!(region, filtered, "region {min} to {max}");
+        }
+    }
+}
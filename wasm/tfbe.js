@@ -1,9 +1,28 @@
-import { tfbe_ffi_load_file } from "./bevy_game.js";
+import {
+  tfbe_ffi_export_file,
+  tfbe_ffi_load_file,
+  tfbe_ffi_load_reference_obj,
+} from "./bevy_game.js";
 
 window.tfbe_ffi_alert = (message) => {
   alert(message);
 };
 
+// `line` is 1-based, or 0 when the line of the error is not known.
+window.tfbe_ffi_report_error = (message, line) => {
+  const location = line > 0 ? `line ${line}: ` : "";
+  console.error(`Error in loaded file, ${location}${message}`);
+  alert(`Error in loaded file, ${location}${message}`);
+};
+
+window.tfbe_ffi_save_file = (fileName, contents) => {
+  const link = document.createElement("a");
+  link.href = URL.createObjectURL(new Blob([contents], { type: "text/plain" }));
+  link.download = fileName;
+  link.click();
+  URL.revokeObjectURL(link.href);
+};
+
 let instance = null;
 
 window.tfbe_set_instance = (doneInstance) => {
@@ -28,3 +47,36 @@ loadInputButton.addEventListener("change", (e) => {
   };
   reader.readAsText(file);
 });
+
+const exportButton = document.createElement("button");
+exportButton.textContent = "Export VMF";
+exportButton.id = "export-file";
+exportButton.style.position = "absolute";
+exportButton.style.top = "24px";
+document.body.appendChild(exportButton);
+
+exportButton.addEventListener("click", () => {
+  // The map arrives through `tfbe_ffi_save_file`.
+  tfbe_ffi_export_file();
+});
+
+const referenceInputButton = document.createElement("input");
+referenceInputButton.type = "file";
+referenceInputButton.accept = ".obj";
+referenceInputButton.id = "input-reference";
+referenceInputButton.title = "Load reference OBJ";
+referenceInputButton.style.position = "absolute";
+referenceInputButton.style.top = "48px";
+document.body.appendChild(referenceInputButton);
+
+referenceInputButton.addEventListener("change", (e) => {
+  const file = e.target.files[0];
+  if (!file) {
+    return;
+  }
+  const reader = new FileReader();
+  reader.onload = (e) => {
+    tfbe_ffi_load_reference_obj(e.target.result);
+  };
+  reader.readAsText(file);
+});